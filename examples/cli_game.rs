@@ -1,15 +1,29 @@
-use std::fmt::{Display, Formatter};
+use std::{
+    fmt::{Display, Formatter},
+    fs::File,
+    io::{BufReader, ErrorKind},
+    path::Path,
+};
 
+#[cfg(feature = "sealed")]
+use dialoguer::Password;
 use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
+use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "sealed")]
+use fluff::sealed::{Keyring, SealError, SealedSession as Session};
 use fluff::{
     bet::Bet,
     game::{
         self, round,
-        state::{self, Betting, InRound, NewRound},
+        session::{Action, GameSession},
+        state,
+        view::PlayerView,
         Game,
     },
+    hint::{Difficulty, Hint},
     player::Player,
+    strategy::{bots, Driver},
 };
 
 #[derive(Debug, Copy, Clone)]
@@ -36,6 +50,107 @@ impl std::str::FromStr for BetInput {
     }
 }
 
+/// Where the game in progress is saved after every bet and call
+const AUTOSAVE: &str = "fluff-autosave.json";
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+enum BotKind {
+    Random,
+    Cautious,
+    Bluffer,
+}
+
+/// Everything about a game besides the game itself, so it can be picked up again after a restart
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Setup {
+    bots: Vec<(Player, BotKind)>,
+    hints: Option<Difficulty>,
+}
+
+impl Setup {
+    /// A driver for the bots, which are seeded anew every time the game is loaded
+    fn driver(&self) -> Driver {
+        let mut driver = Driver::new();
+        for (player, kind) in &self.bots {
+            let seed = rand::random();
+            match kind {
+                BotKind::Random => driver.add_bot(player.clone(), bots::RandomLegal::new(seed)),
+                BotKind::Cautious => driver.add_bot(player.clone(), bots::Cautious::default()),
+                BotKind::Bluffer => driver.add_bot(player.clone(), bots::Bluffer::new(seed)),
+            }
+        }
+        driver
+    }
+}
+
+/// A game saved with every roll in the clear, since sealing them needs the `sealed` feature
+#[cfg(not(feature = "sealed"))]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(transparent)]
+struct Session(GameSession);
+
+#[cfg(not(feature = "sealed"))]
+impl Session {
+    fn session(&self) -> &GameSession {
+        &self.0
+    }
+}
+
+/// A game in whichever state it was in when saved, along with its setup
+///
+/// With the `sealed` feature, the rolls of players with a passphrase are sealed in the save, and
+/// only opened again once they enter it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SavedGame {
+    setup: Setup,
+    session: Session,
+}
+
+impl SavedGame {
+    fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Replaces the file at `path` only once the new save is complete, so a closed terminal never
+    /// leaves half a save behind
+    fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let partial = path.with_extension("partial");
+        serde_json::to_writer(File::create(&partial)?, self)?;
+        std::fs::rename(partial, path)?;
+        Ok(())
+    }
+
+    /// The autosaved game, if there is one that isn't over
+    fn autosaved() -> Option<Self> {
+        Self::load(AUTOSAVE)
+            .ok()
+            .filter(|saved| !saved.session.session().is_over())
+    }
+}
+
+fn autosave(setup: &Setup, session: &Session) {
+    let saved = SavedGame {
+        setup: setup.clone(),
+        session: session.clone(),
+    };
+    if let Err(err) = saved.save(AUTOSAVE) {
+        eprintln!("Could not autosave the game: {err}");
+    }
+}
+
+fn save_as(saved: &SavedGame) -> dialoguer::Result<()> {
+    let path: String = Input::with_theme(theme())
+        .with_prompt("Save to which file?")
+        .default("fluff-save.json".to_owned())
+        .interact_text()?;
+    match saved.save(&path) {
+        Ok(()) => println!("Saved the game to {path}"),
+        Err(err) => eprintln!("Could not save the game: {err}"),
+    }
+    Ok(())
+}
+
 static THEME: std::sync::OnceLock<ColorfulTheme> = std::sync::OnceLock::new();
 
 fn theme() -> &'static ColorfulTheme {
@@ -62,17 +177,11 @@ impl BetInput {
     }
 }
 
-pub fn explain_fluff_result(transition: &game::FluffCallTransition) {
-    let round = match transition {
-        game::FluffCallTransition::NextRound(g) => g
-            .round_history()
-            .last()
-            .expect("transitioned game should not have empty round history"),
-        game::FluffCallTransition::GameOver(g) => g
-            .round_history()
-            .last()
-            .expect("finished game should not have empty round history"),
-    };
+pub fn explain_fluff_result(session: &GameSession) {
+    let round = session
+        .round_history()
+        .last()
+        .expect("game after a fluff call should not have empty round history");
     let round::Turn {
         player: _,
         bet: final_bet @ Bet {
@@ -142,70 +251,174 @@ fn wait_player_ready(player: &game::PlayerRef) -> dialoguer::Result<()> {
     Ok(())
 }
 
-pub fn run_round(game: Game<InRound<NewRound>>) -> dialoguer::Result<game::FluffCallTransition> {
+fn print_dice_counts(player_dice_counts: &indexmap::IndexMap<game::PlayerRef, usize>) {
     println!("Current dice counts: ");
-    for (player, dice_count) in game.player_dice_counts() {
+    for (player, dice_count) in player_dice_counts {
         println!("{player} has {dice_count}");
     }
-    wait_player_ready(&game.curr_round().state_data().first_player_rolls.player)?;
-    let mut g: Game<InRound<Betting>> = loop {
-        let NewRound {
-            first_player_rolls: round::PlayerRolls { player, rolls },
-        } = game.curr_round().state_data();
-        println!("Turn of player {player} with rolls {rolls:?}");
-        if let Some(bet) = BetInput::input_with_confirm(None)? {
-            break game.raise_bet(bet);
+}
+
+/// Asks `player` for their passphrase until it opens their rolls, if they have one
+#[cfg(feature = "sealed")]
+fn unlock(session: &mut Session, player: &Player) -> dialoguer::Result<()> {
+    if !session.keyring().has_passphrase(player) {
+        return Ok(());
+    }
+    loop {
+        let passphrase = Password::with_theme(theme())
+            .with_prompt(format!("Passphrase of {player}"))
+            .interact()?;
+        match session.unlock(player, &passphrase) {
+            Ok(()) => return Ok(()),
+            Err(err) => eprintln!("{err}"),
         }
-    };
+    }
+}
+
+/// What `player` sees, once they opened their rolls
+#[cfg(feature = "sealed")]
+fn view_for(session: &mut Session, player: &game::PlayerRef) -> dialoguer::Result<PlayerView> {
+    unlock(session, player)?;
+    Ok(session
+        .view_for(player)
+        .expect("Player should have just unlocked their rolls"))
+}
+
+#[cfg(not(feature = "sealed"))]
+fn view_for(session: &mut Session, player: &game::PlayerRef) -> dialoguer::Result<PlayerView> {
+    Ok(session.0.view_for(player))
+}
+
+/// Applies `action`, first asking whoever still has sealed rolls to open them if it needs them
+#[cfg(feature = "sealed")]
+fn apply(session: &mut Session, action: Action) -> anyhow::Result<()> {
     loop {
-        clear_term()?;
-        println!("Current dice counts: ");
-        for (player, dice_count) in g.player_dice_counts() {
-            println!("{player} has {dice_count}");
+        match session.act(action) {
+            Err(SealError::Locked(player)) => {
+                println!("{player} has to reveal their rolls");
+                unlock(session, &player)?;
+            }
+            result => return Ok(result?),
         }
-        let Betting {
-            curr_player_rolls: round::PlayerRolls { player, rolls },
-            prev_bet,
-        } = g.curr_round().state_data();
+    }
+}
+
+#[cfg(not(feature = "sealed"))]
+fn apply(session: &mut Session, action: Action) -> anyhow::Result<()> {
+    session.0 = session
+        .0
+        .clone()
+        .act(action)
+        .map_err(|rejected| rejected.error)?;
+    Ok(())
+}
+
+fn human_turn(session: &mut Session, clear: bool, setup: &Setup) -> dialoguer::Result<Action> {
+    if clear {
+        clear_term()?;
+    }
+    let player = session
+        .session()
+        .current_player()
+        .expect("Only unfinished games should have turns")
+        .clone();
+    print_dice_counts(session.session().player_dice_counts());
+    let prev_bet = session.session().last_bet();
+    if let Some(prev_bet) = prev_bet {
         println!("Current bet: {prev_bet}");
-        wait_player_ready(&g.curr_round().state_data().curr_player_rolls.player)?;
-        println!("Turn of player {player}, with rolls {rolls:?}");
-        if Select::with_theme(theme())
-            .with_prompt("Do you want to raise the bet or call Fluff?")
-            .items(&["Raise", "Call"])
+    }
+    wait_player_ready(&player)?;
+    let view = view_for(session, &player)?;
+    let hint = setup
+        .hints
+        .and_then(|difficulty| Hint::for_view(&view, difficulty));
+    let saved = SavedGame {
+        setup: setup.clone(),
+        session: session.clone(),
+    };
+    let rolls = view.own_rolls.unwrap_or_default();
+    println!("Turn of player {player} with rolls {rolls:?}");
+    let items = match (prev_bet, &hint) {
+        (None, None) => &["Bet", "Save"][..],
+        (None, Some(_)) => &["Bet", "Save", "Hint"][..],
+        (Some(_), None) => &["Raise", "Call", "Save"][..],
+        (Some(_), Some(_)) => &["Raise", "Call", "Save", "Hint"][..],
+    };
+    loop {
+        let choice = Select::with_theme(theme())
+            .with_prompt(match prev_bet {
+                None => "Do you want to bet, save the game or get a hint?",
+                Some(_) => "Do you want to raise the bet, call Fluff or save the game?",
+            })
+            .items(items)
             .default(0)
-            .interact()?
-            == 1
-            && Confirm::with_theme(theme())
-                .with_prompt(format!(
-                    "Are you sure you want to call Fluff on {prev_bet}?"
-                ))
-                .interact()?
-        {
-            return Ok(g.call_fluff());
-        }
-        if let Some(bet) = BetInput::input_with_confirm(Some(*prev_bet))? {
-            if let Err(err) = g.raise_bet(bet) {
-                eprintln!("{err:#?}");
-                continue;
-            };
+            .interact()?;
+        match (items[choice], prev_bet, &hint) {
+            ("Call", Some(prev_bet), _) => {
+                if Confirm::with_theme(theme())
+                    .with_prompt(format!(
+                        "Are you sure you want to call Fluff on {prev_bet}?"
+                    ))
+                    .interact()?
+                {
+                    return Ok(Action::CallFluff);
+                }
+            }
+            ("Save", _, _) => save_as(&saved)?,
+            ("Hint", _, Some(hint)) => print!("{hint}"),
+            _ => {
+                if let Some(bet) = BetInput::input_with_confirm(prev_bet)? {
+                    return Ok(Action::Raise(bet));
+                }
+            }
         }
     }
 }
 
-pub fn run_game(mut g: Game<InRound<NewRound>>) -> dialoguer::Result<Game<state::GameOver>> {
-    // println!("{g:#?}");
-    // println!("{:#?}", g.curr_round().curr_bet());
+fn describe_action(session: &GameSession, action: Action) -> String {
+    let player = session
+        .current_player()
+        .expect("Actions should only be taken in unfinished games");
+    match (action, session.last_bet()) {
+        (Action::Raise(bet), _) => format!("{player} bets {bet}"),
+        (Action::CallFluff, Some(bet)) => format!("{player} calls Fluff on {bet}"),
+        (Action::CallFluff, None) => format!("{player} calls Fluff"),
+    }
+}
+
+fn run_game(mut session: Session, setup: &Setup) -> anyhow::Result<Game<state::GameOver>> {
+    let mut driver = setup.driver();
+    let mut clear = true;
+    autosave(setup, &session);
     loop {
-        use game::FluffCallTransition as T;
-        let transition = run_round(g)?;
-        clear_term()?;
-        explain_fluff_result(&transition);
-        println!();
-        match transition {
-            T::NextRound(new_game) => g = new_game,
-            T::GameOver(finished_game) => return Ok(finished_game),
+        if let GameSession::GameOver(finished_game) = session.session() {
+            driver.finish(session.session());
+            return Ok(finished_game.clone());
+        }
+        let rounds_played = session.session().round_history().len();
+        let by_bot = match driver.bot_action(session.session()) {
+            Some(action) => {
+                println!("{}", describe_action(session.session(), action));
+                apply(&mut session, action)?;
+                true
+            }
+            None => {
+                let action = human_turn(&mut session, clear, setup)?;
+                apply(&mut session, action)?;
+                false
+            }
         };
+        clear = !by_bot;
+        if session.session().round_history().len() != rounds_played {
+            if by_bot {
+                println!();
+            } else {
+                clear_term()?;
+            }
+            explain_fluff_result(session.session());
+            println!();
+        }
+        autosave(setup, &session);
     }
 }
 
@@ -221,10 +434,9 @@ pub fn prompt_players() -> dialoguer::Result<std::collections::HashSet<Player>>
                     .collect::<Vec<_>>()
                     .join(", ")
             );
-            if players.len() > 1
-                && Confirm::with_theme(theme())
-                    .with_prompt("Use these players?")
-                    .interact()?
+            if Confirm::with_theme(theme())
+                .with_prompt("Use these players?")
+                .interact()?
             {
                 return Ok(players);
             }
@@ -250,9 +462,159 @@ pub fn prompt_players() -> dialoguer::Result<std::collections::HashSet<Player>>
     }
 }
 
-pub fn main() -> dialoguer::Result<()> {
-    Ok(println!(
-        "{:#?}",
-        run_game(Game::new(prompt_players()?, game::GameConfig::default()))?
+fn prompt_bots(
+    players: &mut std::collections::HashSet<Player>,
+) -> dialoguer::Result<Vec<(Player, BotKind)>> {
+    let mut bots = Vec::new();
+    while players.len() < 2
+        || Confirm::with_theme(theme())
+            .with_prompt("Add a computer player?")
+            .default(false)
+            .interact()?
+    {
+        if players.len() < 2 {
+            println!("At least 2 players are needed, so a computer player will be added");
+        }
+        let player: Player = Input::with_theme(theme())
+            .with_prompt("Bot name?")
+            .validate_with(|x: &Player| {
+                if players.contains(x) {
+                    Err("Player already in game")
+                } else {
+                    Ok(())
+                }
+            })
+            .interact_text()?;
+        let kind = match Select::with_theme(theme())
+            .with_prompt("How should it play?")
+            .items(&["Randomly", "Cautiously", "Aggressively"])
+            .default(1)
+            .interact()?
+        {
+            0 => BotKind::Random,
+            1 => BotKind::Cautious,
+            _ => BotKind::Bluffer,
+        };
+        bots.push((player.clone(), kind));
+        players.insert(player);
+    }
+    Ok(bots)
+}
+
+pub fn prompt_hints() -> dialoguer::Result<Option<Difficulty>> {
+    if !Confirm::with_theme(theme())
+        .with_prompt("Allow asking for hints?")
+        .default(false)
+        .interact()?
+    {
+        return Ok(None);
+    }
+    Ok(Some(
+        match Select::with_theme(theme())
+            .with_prompt("How much should hints give away?")
+            .items(&[
+                "Everything, including a recommended action",
+                "Probabilities and the safest raise",
+                "Only the probability of the current bet",
+            ])
+            .default(0)
+            .interact()?
+        {
+            0 => Difficulty::Easy,
+            1 => Difficulty::Medium,
+            _ => Difficulty::Hard,
+        },
     ))
 }
+
+/// Lets every human player pick a passphrase to seal their rolls with, if they want to
+#[cfg(feature = "sealed")]
+fn prompt_passphrases(humans: &[Player]) -> dialoguer::Result<Keyring> {
+    let mut keyring = Keyring::new();
+    if !Confirm::with_theme(theme())
+        .with_prompt("Protect each player's rolls in save files with a passphrase?")
+        .default(false)
+        .interact()?
+    {
+        return Ok(keyring);
+    }
+    for player in humans {
+        let passphrase = Password::with_theme(theme())
+            .with_prompt(format!("Passphrase of {player}"))
+            .with_confirmation("Repeat the passphrase", "The passphrases don't match")
+            .interact()?;
+        keyring.set_passphrase(player.clone().into(), &passphrase);
+        clear_term()?;
+    }
+    Ok(keyring)
+}
+
+fn new_game() -> dialoguer::Result<SavedGame> {
+    let mut players = prompt_players()?;
+    #[cfg(feature = "sealed")]
+    let humans = players.iter().cloned().collect::<Vec<_>>();
+    let bots = prompt_bots(&mut players)?;
+    let hints = prompt_hints()?;
+    let session = GameSession::from(Game::new(players, game::GameConfig::default()));
+    #[cfg(feature = "sealed")]
+    let session = Session::new(session, prompt_passphrases(&humans)?)
+        .expect("Every player with a passphrase just set it");
+    #[cfg(not(feature = "sealed"))]
+    let session = Session(session);
+    Ok(SavedGame {
+        setup: Setup { bots, hints },
+        session,
+    })
+}
+
+fn load_game() -> dialoguer::Result<Option<SavedGame>> {
+    let path: String = Input::with_theme(theme())
+        .with_prompt("Load which file?")
+        .default("fluff-save.json".to_owned())
+        .interact_text()?;
+    match SavedGame::load(&path) {
+        Ok(saved) if saved.session.session().is_over() => {
+            eprintln!("The game in {path} is already over")
+        }
+        Ok(saved) => return Ok(Some(saved)),
+        Err(err) => eprintln!("Could not load {path}: {err}"),
+    }
+    Ok(None)
+}
+
+fn prompt_game() -> dialoguer::Result<SavedGame> {
+    let mut autosaved = SavedGame::autosaved();
+    loop {
+        let mut items = vec!["New game", "Load a saved game"];
+        if autosaved.is_some() {
+            items.insert(0, "Resume previous game");
+        }
+        let choice = Select::with_theme(theme())
+            .with_prompt("What do you want to play?")
+            .items(&items)
+            .default(0)
+            .interact()?;
+        match items[choice] {
+            "Resume previous game" => {
+                return Ok(autosaved.take().expect("Resuming needs an autosave"))
+            }
+            "New game" => return new_game(),
+            _ => {
+                if let Some(saved) = load_game()? {
+                    return Ok(saved);
+                }
+            }
+        }
+    }
+}
+
+pub fn main() -> anyhow::Result<()> {
+    let SavedGame { setup, session } = prompt_game()?;
+    let game = run_game(session, &setup)?;
+    match std::fs::remove_file(AUTOSAVE) {
+        Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+        _ => {}
+    }
+    println!("{game:#?}");
+    Ok(())
+}
@@ -6,17 +6,45 @@ use std::{
 
 use thiserror::Error;
 
-// SAFETY: 1≠0 :/
-const NONZERO_ONE: NonZeroUsize = unsafe { NonZeroUsize::new_unchecked(1) };
+const NONZERO_ONE: NonZeroUsize = NonZeroUsize::new(1).unwrap();
 
-#[derive(Debug, PartialOrd, Ord, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialOrd, Ord, PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
 pub struct Bet {
     // Field order is necessary for ord derivation, since its a lexicographic ordering of (count, roll)
     pub count: NonZeroUsize,
     pub roll: NonZeroUsize,
 }
 
-#[derive(Error, Debug, Serialize, Deserialize, Copy, Clone)]
+/// Which rolls count towards a bet on a roll other than their own
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Default)]
+pub enum WildRule {
+    /// 1s count towards every bet, as well as bets on 1s
+    #[default]
+    OnesWild,
+    /// Rolls only ever count towards bets on that same roll
+    NoWilds,
+}
+
+impl WildRule {
+    #[must_use]
+    pub fn matches(self, bet_roll: NonZeroUsize, roll: NonZeroUsize) -> bool {
+        match self {
+            Self::OnesWild => bet_roll == roll || NONZERO_ONE == roll,
+            Self::NoWilds => bet_roll == roll,
+        }
+    }
+
+    /// How many of the faces of a die with `max_roll` faces count towards a bet on `bet_roll`
+    #[must_use]
+    pub fn matching_faces(self, bet_roll: NonZeroUsize, max_roll: NonZeroUsize) -> usize {
+        (1..=max_roll.get())
+            .filter_map(NonZeroUsize::new)
+            .filter(|x| self.matches(bet_roll, *x))
+            .count()
+    }
+}
+
+#[derive(Error, Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
 pub enum RaiseError {
     #[error("Count can not be decreased, but it changed from {prev} to {new}")]
     CountDecreased {
@@ -77,10 +105,28 @@ impl Bet {
         }
     }
 
+    /// The smallest bet that is raised from this one, without going over `max_roll`
+    #[must_use]
+    pub fn min_raise(&self, max_roll: NonZeroUsize) -> Self {
+        if self.roll < max_roll {
+            Self::new(self.count, self.roll.saturating_add(1))
+        } else {
+            Self::new(self.count.saturating_add(1), NONZERO_ONE)
+        }
+    }
+
     pub fn count_matches(&self, rolls: impl IntoIterator<Item = NonZeroUsize>) -> usize {
+        self.count_matches_with(rolls, WildRule::OnesWild)
+    }
+
+    pub fn count_matches_with(
+        &self,
+        rolls: impl IntoIterator<Item = NonZeroUsize>,
+        wild_rule: WildRule,
+    ) -> usize {
         rolls
             .into_iter()
-            .filter(|x| self.roll.eq(x) || NONZERO_ONE.eq(x))
+            .filter(|x| wild_rule.matches(self.roll, *x))
             .count()
     }
 
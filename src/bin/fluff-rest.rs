@@ -0,0 +1,17 @@
+use fluff::rest::RestApi;
+use tiny_http::Server;
+
+/// Serves correspondence games over HTTP, keeping them in the given file between restarts
+///
+/// Usage: fluff-rest <address> <file>
+fn main() -> std::io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let (Some(addr), Some(path)) = (args.next(), args.next()) else {
+        eprintln!("Usage: fluff-rest <address> <file>");
+        std::process::exit(2);
+    };
+    let mut api = RestApi::open(path)?;
+    let server = Server::http(&addr).map_err(std::io::Error::other)?;
+    println!("Listening on http://{}", server.server_addr());
+    api.serve(&server)
+}
@@ -0,0 +1,26 @@
+use std::net::TcpListener;
+
+use fluff::{game::GameConfig, host::Host, player::Player, server::Server};
+
+/// Hosts a single game for the players named on the command line, printing every seat's token
+///
+/// Usage: fluff-server <address> <player> <player>...
+fn main() -> std::io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let (Some(addr), players) = (args.next(), args.map(Player::new).collect::<Vec<_>>()) else {
+        eprintln!("Usage: fluff-server <address> <player> <player>...");
+        std::process::exit(2);
+    };
+    if players.len() < 2 {
+        eprintln!("At least 2 players are needed");
+        std::process::exit(2);
+    }
+    let mut host = Host::new();
+    let (id, seats) = host.create_game(players, GameConfig::default());
+    let listener = TcpListener::bind(&addr)?;
+    println!("Game {id} listening on ws://{}", listener.local_addr()?);
+    for (player, token) in seats {
+        println!("{player}: {token}");
+    }
+    Server::new(host).serve(&listener)
+}
@@ -0,0 +1,276 @@
+//! Long-running games that players take turns in whenever they get around to it, independent of
+//! how their requests arrive
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use indexmap::{IndexMap, IndexSet};
+use thiserror::Error;
+
+use crate::{
+    game::{
+        log::{GameLog, LogEvent},
+        session::{Action, ActionError, GameSession},
+        view::PlayerView,
+        GameConfig, PlayerRef,
+    },
+    host::{new_token, GameId},
+    player::Player,
+};
+
+#[derive(Error, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub enum CorrespondenceError {
+    #[error("No player has this token")]
+    UnknownToken,
+    #[error("{0} is already registered")]
+    AlreadyRegistered(Player),
+    #[error("{0} is not registered")]
+    UnknownPlayer(Player),
+    #[error("A game needs at least 2 different players, including whoever creates it")]
+    InvalidPlayers,
+    #[error("No game has id {0}")]
+    UnknownGame(GameId),
+    #[error("{0} is not playing this game")]
+    NotInGame(Player),
+    #[error("It is not {0}'s turn")]
+    NotYourTurn(Player),
+    #[error(transparent)]
+    Action(#[from] ActionError),
+}
+
+/// A game along with its full history
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct CorrespondenceGame {
+    pub log: GameLog,
+    pub session: GameSession,
+}
+
+/// What a player sees of one of their games in a list
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct GameSummary {
+    pub id: GameId,
+    pub players: Vec<Player>,
+    pub round_number: usize,
+    pub current_player: Option<PlayerRef>,
+    pub my_turn: bool,
+    pub winner: Option<PlayerRef>,
+}
+
+/// Every registered player and their games
+///
+/// Players act through the token they got when registering, which is the only way to see their own
+/// rolls.
+#[derive(Debug, Serialize, Deserialize, Default, Eq, PartialEq, Clone)]
+pub struct Correspondence {
+    accounts: IndexMap<String, Player>,
+    games: IndexMap<GameId, CorrespondenceGame>,
+    next_id: GameId,
+}
+
+impl Correspondence {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Signs up `player`, returning the token they act with
+    pub fn register(&mut self, player: Player) -> Result<String, CorrespondenceError> {
+        if self.accounts.values().any(|x| *x == player) {
+            return Err(CorrespondenceError::AlreadyRegistered(player));
+        }
+        let token = new_token(&mut rand::thread_rng());
+        self.accounts.insert(token.clone(), player);
+        Ok(token)
+    }
+
+    /// The player a token belongs to
+    pub fn player(&self, token: &str) -> Result<&Player, CorrespondenceError> {
+        self.accounts
+            .get(token)
+            .ok_or(CorrespondenceError::UnknownToken)
+    }
+
+    pub fn game(&self, id: GameId) -> Result<&CorrespondenceGame, CorrespondenceError> {
+        self.games
+            .get(&id)
+            .ok_or(CorrespondenceError::UnknownGame(id))
+    }
+
+    /// The game `id` if the player of `token` plays in it
+    fn seat(
+        &self,
+        token: &str,
+        id: GameId,
+    ) -> Result<(PlayerRef, &CorrespondenceGame), CorrespondenceError> {
+        let player = self.player(token)?;
+        let game = self.game(id)?;
+        let (player, _) = game
+            .session
+            .player_dice_counts()
+            .get_key_value(player)
+            .ok_or_else(|| CorrespondenceError::NotInGame(player.clone()))?;
+        Ok((player.clone(), game))
+    }
+
+    /// Starts a game between `players` in seating order, which must include whoever creates it
+    pub fn create_game(
+        &mut self,
+        token: &str,
+        players: Vec<Player>,
+        config: GameConfig,
+    ) -> Result<GameId, CorrespondenceError> {
+        let creator = self.player(token)?;
+        let distinct = players.iter().collect::<IndexSet<_>>().len();
+        if distinct < 2 || distinct != players.len() || !players.contains(creator) {
+            return Err(CorrespondenceError::InvalidPlayers);
+        }
+        if let Some(unknown) = players
+            .iter()
+            .find(|player| !self.accounts.values().any(|x| x == *player))
+        {
+            return Err(CorrespondenceError::UnknownPlayer(unknown.clone()));
+        }
+        let (log, session) = GameLog::start(players, config);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.games.insert(id, CorrespondenceGame { log, session });
+        Ok(id)
+    }
+
+    pub fn view(&self, token: &str, id: GameId) -> Result<PlayerView, CorrespondenceError> {
+        let (player, game) = self.seat(token, id)?;
+        Ok(game.session.view_for(&player))
+    }
+
+    /// Applies `action` for the player of `token`, returning their new view
+    pub fn act(
+        &mut self,
+        token: &str,
+        id: GameId,
+        action: Action,
+    ) -> Result<PlayerView, CorrespondenceError> {
+        let (player, _) = self.seat(token, id)?;
+        let game = self
+            .games
+            .get_mut(&id)
+            .ok_or(CorrespondenceError::UnknownGame(id))?;
+        if game.session.current_player() != Some(&player) {
+            return Err(CorrespondenceError::NotYourTurn((*player).clone()));
+        }
+        // the log is only written to once the action is accepted
+        game.session = game
+            .log
+            .act(game.session.clone(), action)
+            .map_err(|rejected| rejected.error)?;
+        Ok(game.session.view_for(&player))
+    }
+
+    /// Every game the player of `token` plays in, most recently created first
+    pub fn games(&self, token: &str) -> Result<Vec<GameSummary>, CorrespondenceError> {
+        let player = self.player(token)?;
+        Ok(self
+            .games
+            .iter()
+            .rev()
+            .filter(|(_, game)| game.log.players().contains(player))
+            .map(|(id, game)| {
+                let view = game.session.view_for(&PlayerRef::new(player.clone()));
+                GameSummary {
+                    id: *id,
+                    players: game.log.players().to_vec(),
+                    round_number: view.round_number,
+                    my_turn: view.is_my_turn(),
+                    current_player: view.current_player,
+                    winner: view.winner,
+                }
+            })
+            .collect())
+    }
+
+    /// Everything that happened in the game, leaving out the other players' rolls in the current
+    /// round
+    pub fn history(&self, token: &str, id: GameId) -> Result<Vec<LogEvent>, CorrespondenceError> {
+        let (player, game) = self.seat(token, id)?;
+        let mut events = game.log.events().to_vec();
+        let current_round = events
+            .iter_mut()
+            .rev()
+            .find(|event| matches!(event, LogEvent::RoundStarted { .. }))
+            .filter(|_| !game.session.is_over());
+        if let Some(LogEvent::RoundStarted { rolls, .. }) = current_round {
+            rolls.retain(|x, _| *x == player);
+        }
+        Ok(events)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Writes everything to `path`, replacing it only once the new contents are complete
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let partial = path.with_extension("partial");
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        std::fs::rename(partial, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::bet::Bet;
+
+    #[test]
+    fn test_correspondence() {
+        let mut games = Correspondence::new();
+        let [a, b, c] = ["A", "B", "C"].map(Player::new);
+        let [token_a, token_b, token_c] = [&a, &b, &c].map(|x| games.register(x.clone()).unwrap());
+        assert_eq!(
+            games.register(a.clone()),
+            Err(CorrespondenceError::AlreadyRegistered(a.clone()))
+        );
+        assert_eq!(
+            games.create_game(&token_a, vec![b.clone(), c.clone()], GameConfig::default()),
+            Err(CorrespondenceError::InvalidPlayers)
+        );
+        let id = games
+            .create_game(&token_a, vec![a.clone(), b.clone()], GameConfig::default())
+            .unwrap();
+        assert_eq!(
+            games.view(&token_c, id),
+            Err(CorrespondenceError::NotInGame(c))
+        );
+
+        let bet = Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN));
+        assert_eq!(
+            games.act(&token_b, id, bet),
+            Err(CorrespondenceError::NotYourTurn(b.clone()))
+        );
+        let view = games.act(&token_a, id, bet).unwrap();
+        assert!(!view.is_my_turn());
+        let summaries = games.games(&token_b).unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert!(summaries[0].my_turn);
+        assert!(games.games(&token_c).unwrap().is_empty());
+
+        // B only sees their own rolls of the round in progress
+        let history = games.history(&token_b, id).unwrap();
+        let LogEvent::RoundStarted { rolls, .. } = &history[0] else {
+            panic!("History should start with a round");
+        };
+        assert_eq!(rolls.keys().map(|x| (**x).clone()).collect::<Vec<_>>(), [b]);
+
+        let path = std::env::temp_dir().join(format!("fluff-correspondence-{id}.json"));
+        games.save(&path).unwrap();
+        assert_eq!(Correspondence::load(&path).unwrap(), games);
+        std::fs::remove_file(path).unwrap();
+    }
+}
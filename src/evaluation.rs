@@ -0,0 +1,356 @@
+use std::{collections::HashMap, num::NonZeroUsize};
+
+use indexmap::IndexMap;
+
+use crate::{
+    game::{round::Turn, view::PlayerView, GameConfig, PlayerRef},
+    player::Player,
+    simulation::{wilson_interval, GameResult, Seat, Simulation, SimulationError},
+    solver::{RoundTree, SolveError, Solver},
+    strategy::Strategy,
+};
+
+/// How well a best response does against a strategy, from -1 for a certain loss to 1 for a certain
+/// win
+#[derive(Debug, Serialize, Deserialize, PartialEq, Copy, Clone)]
+pub struct BestResponse {
+    /// Value of the best response when it opens the first round
+    pub value_as_opener: f64,
+    /// Value of the best response when the strategy opens the first round
+    pub value_as_responder: f64,
+}
+
+impl BestResponse {
+    /// How much a best response wins on average over both seat orders, which is 0 for an
+    /// equilibrium strategy and higher the more exploitable the strategy is
+    #[must_use]
+    pub fn exploitability(&self) -> f64 {
+        (self.value_as_opener + self.value_as_responder) / 2.0
+    }
+}
+
+/// Computes the value of a best response to `strategy` in a 2 player game small enough for
+/// [`Solver`]
+///
+/// `strategy` is asked for its [`Strategy::policy`] in every spot of every round, with views
+/// that have no round history. Raises outside of the solved tree, i.e. bets on more dice than
+/// are in play, are treated as losing the round.
+pub fn best_response(
+    config: &GameConfig,
+    strategy: &mut dyn Strategy,
+) -> Result<BestResponse, SolveError> {
+    // values for the best response keyed by its dice, the strategy's dice and whether it opens
+    let mut values: HashMap<(usize, usize, bool), f64> = HashMap::new();
+    let players = [Player::new("Opener"), Player::new("Responder")].map(PlayerRef::new);
+    for [opener_dice, responder_dice] in Solver::rounds(config)? {
+        for br_opens in [true, false] {
+            let (br_dice, s_dice) = if br_opens {
+                (opener_dice, responder_dice)
+            } else {
+                (responder_dice, opener_dice)
+            };
+            let win = if s_dice == 1 {
+                1.0
+            } else {
+                values[&(br_dice, s_dice - 1, true)]
+            };
+            let lose = if br_dice == 1 {
+                -1.0
+            } else {
+                values[&(br_dice - 1, s_dice, false)]
+            };
+            let payoffs = if br_opens { (win, lose) } else { (-lose, -win) };
+            let tree = RoundTree::new([opener_dice, responder_dice], config.max_roll(), payoffs);
+            let br_player = usize::from(!br_opens);
+            let mut search = BestResponseSearch {
+                tree: &tree,
+                br_player,
+                win,
+                config,
+                players: &players,
+                strategy: &mut *strategy,
+            };
+            let reach = tree.hands[1 - br_player]
+                .iter()
+                .map(|x| x.weight)
+                .collect::<Vec<_>>();
+            let value = search
+                .values(0, &reach)
+                .iter()
+                .zip(&tree.hands[br_player])
+                .map(|(v, hand)| v * hand.weight)
+                .sum();
+            values.insert((br_dice, s_dice, br_opens), value);
+        }
+    }
+    let max_dice = config.max_dice().get();
+    Ok(BestResponse {
+        value_as_opener: values[&(max_dice, max_dice, true)],
+        value_as_responder: values[&(max_dice, max_dice, false)],
+    })
+}
+
+struct BestResponseSearch<'a> {
+    tree: &'a RoundTree,
+    br_player: usize,
+    /// Value for the best response of winning the round
+    win: f64,
+    config: &'a GameConfig,
+    players: &'a [PlayerRef; 2],
+    strategy: &'a mut dyn Strategy,
+}
+
+impl BestResponseSearch<'_> {
+    fn view(&self, node: usize, hand: usize) -> PlayerView {
+        let node = &self.tree.nodes[node];
+        let player = node.player;
+        PlayerView {
+            viewer: self.players[player].clone(),
+            own_rolls: Some(self.tree.hands[player][hand].rolls.as_slice().into()),
+            player_dice_counts: self
+                .players
+                .iter()
+                .cloned()
+                .zip(self.tree.dice)
+                .collect::<IndexMap<_, _>>(),
+            config: *self.config,
+            round_number: 1,
+            turns: node
+                .history
+                .iter()
+                .enumerate()
+                .map(|(i, bet)| Turn {
+                    player: self.players[i % 2].clone(),
+                    bet: *bet,
+                })
+                .collect(),
+            current_player: Some(self.players[player].clone()),
+            round_history: Vec::new(),
+            winner: None,
+            clocks: None,
+        }
+    }
+
+    /// Probability of each action of `node` for the strategy holding `hand`, and the probability
+    /// of anything outside of the tree
+    fn policy(&mut self, node: usize, hand: usize) -> (Vec<f64>, f64) {
+        let view = self.view(node, hand);
+        let actions = &self.tree.nodes[node].actions;
+        let mut probabilities = vec![0.0; actions.len()];
+        let mut outside = 0.0;
+        for (action, probability) in self.strategy.policy(&view) {
+            match actions.iter().position(|x| *x == action) {
+                Some(i) => probabilities[i] += probability,
+                None => outside += probability,
+            }
+        }
+        (probabilities, outside)
+    }
+
+    /// Value of every hand of the best response at `node`, weighted by `reach` of the strategy's
+    /// hands
+    fn values(&mut self, node: usize, reach: &[f64]) -> Vec<f64> {
+        let tree = self.tree;
+        let br_hands = tree.hands[self.br_player].len();
+        let children = &tree.nodes[node].children;
+        if tree.nodes[node].player == self.br_player {
+            let mut values = vec![f64::NEG_INFINITY; br_hands];
+            for child in children {
+                let child_values = match child {
+                    Some(child) => self.values(*child, reach),
+                    None => self.showdown(node, reach),
+                };
+                for (x, child_x) in values.iter_mut().zip(child_values) {
+                    *x = x.max(child_x);
+                }
+            }
+            return values;
+        }
+        let policies = (0..reach.len())
+            .map(|hand| self.policy(node, hand))
+            .collect::<Vec<_>>();
+        let forfeited: f64 = reach
+            .iter()
+            .zip(&policies)
+            .map(|(r, (_, outside))| r * outside)
+            .sum();
+        let mut values = vec![forfeited * self.win; br_hands];
+        for (action, child) in children.iter().enumerate() {
+            let child_reach = reach
+                .iter()
+                .zip(&policies)
+                .map(|(r, (p, _))| r * p[action])
+                .collect::<Vec<_>>();
+            if child_reach.iter().all(|x| *x == 0.0) {
+                continue;
+            }
+            let child_values = match child {
+                Some(child) => self.values(*child, &child_reach),
+                None => self.showdown(node, &child_reach),
+            };
+            for (x, child_x) in values.iter_mut().zip(child_values) {
+                *x += child_x;
+            }
+        }
+        values
+    }
+
+    fn showdown(&self, node: usize, reach: &[f64]) -> Vec<f64> {
+        let history = &self.tree.nodes[node].history;
+        (0..self.tree.hands[self.br_player].len())
+            .map(|br_hand| {
+                reach
+                    .iter()
+                    .enumerate()
+                    .map(|(s_hand, r)| {
+                        let value = if self.br_player == 0 {
+                            self.tree.showdown(history, br_hand, s_hand)
+                        } else {
+                            -self.tree.showdown(history, s_hand, br_hand)
+                        };
+                        r * value
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SeatOrderReport {
+    pub first: Player,
+    pub second: Player,
+    pub games: usize,
+    pub first_wins: usize,
+    pub first_win_rate: f64,
+    /// 95% confidence interval of the first seat's win rate
+    pub confidence_interval: (f64, f64),
+}
+
+impl SeatOrderReport {
+    fn new(first: Player, second: Player, results: &[GameResult]) -> Self {
+        let games = results.len();
+        let first_wins = results.iter().filter(|x| x.winner_seat == 0).count();
+        Self {
+            first,
+            second,
+            games,
+            first_wins,
+            first_win_rate: first_wins as f64 / games.max(1) as f64,
+            confidence_interval: wilson_interval(first_wins, games),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct HeadToHeadReport {
+    /// Results with the first strategy seated first, and then with it seated second
+    pub orders: [SeatOrderReport; 2],
+    /// Expected value of the first strategy from -1 to 1, over both seat orders
+    pub expected_value: f64,
+    pub standard_error: f64,
+    /// 95% confidence interval of the expected value
+    pub confidence_interval: (f64, f64),
+}
+
+/// Plays `a` against `b` in both seat orders, with every game played twice on the same seed so the
+/// two strategies trade the same dice and the luck of the rolls mostly cancels out
+pub fn head_to_head(
+    config: GameConfig,
+    a: Seat,
+    b: Seat,
+    seed: u64,
+    games: usize,
+    threads: NonZeroUsize,
+) -> Result<HeadToHeadReport, SimulationError> {
+    let a_first = Simulation::new(config, vec![a.clone(), b.clone()], seed, games)
+        .with_threads(threads)
+        .with_fixed_seats()
+        .results()?;
+    let b_first = Simulation::new(config, vec![b.clone(), a.clone()], seed, games)
+        .with_threads(threads)
+        .with_fixed_seats()
+        .results()?;
+    let a_value = |won: bool| if won { 1.0 } else { -1.0 };
+    let paired = a_first
+        .iter()
+        .zip(&b_first)
+        .map(|(x, y)| (a_value(x.winner_seat == 0) + a_value(y.winner_seat == 1)) / 2.0)
+        .collect::<Vec<_>>();
+    let n = paired.len().max(1) as f64;
+    let expected_value = paired.iter().sum::<f64>() / n;
+    let variance = paired
+        .iter()
+        .map(|x| (x - expected_value).powi(2))
+        .sum::<f64>()
+        / (n - 1.0).max(1.0);
+    let standard_error = (variance / n).sqrt();
+    Ok(HeadToHeadReport {
+        orders: [
+            SeatOrderReport::new(a.player.clone(), b.player.clone(), &a_first),
+            SeatOrderReport::new(b.player, a.player, &b_first),
+        ],
+        expected_value,
+        standard_error,
+        confidence_interval: (
+            expected_value - 1.96 * standard_error,
+            expected_value + 1.96 * standard_error,
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        simulation::factory,
+        solver::SolvedStrategy,
+        strategy::bots::{Cautious, RandomLegal},
+    };
+
+    #[test]
+    fn test_exploitability() {
+        let config = GameConfig::new(NonZeroUsize::MIN, NonZeroUsize::new(3).unwrap());
+        let solution = Solver::new(config, 2000).solve().unwrap();
+        let mut solved = SolvedStrategy::new(Arc::new(solution.strategy), 0);
+        let solved_br = best_response(&config, &mut solved).unwrap();
+        assert!(solved_br.exploitability() < 0.02);
+        // against an equilibrium, a best response as the opener gets about the game value
+        assert!((solved_br.value_as_opener - solution.values[&(1, 1)]).abs() < 0.02);
+        let random_br = best_response(&config, &mut RandomLegal::new(0)).unwrap();
+        assert!(random_br.exploitability() > solved_br.exploitability() + 0.1);
+    }
+
+    #[test]
+    fn test_head_to_head() {
+        let config = GameConfig::new(NonZeroUsize::new(2).unwrap(), NonZeroUsize::new(6).unwrap());
+        let report = head_to_head(
+            config,
+            Seat::new(Player::new("Cautious"), factory(|_| Cautious::default())),
+            Seat::new(Player::new("Random"), factory(RandomLegal::new)),
+            99,
+            200,
+            NonZeroUsize::new(2).unwrap(),
+        )
+        .unwrap();
+        // a random bot calls and raises blindly, so the cautious one wins nearly every game in
+        // either seat
+        assert!(report.expected_value > 0.8);
+        assert!(report.confidence_interval.0 > 0.5);
+        assert!(report.orders[0].first_win_rate > 0.8);
+        assert!(report.orders[1].first_win_rate < 0.2);
+        // and from the random bot's side, the value is the same with the sign flipped
+        let reversed = head_to_head(
+            config,
+            Seat::new(Player::new("Random"), factory(RandomLegal::new)),
+            Seat::new(Player::new("Cautious"), factory(|_| Cautious::default())),
+            99,
+            200,
+            NonZeroUsize::new(2).unwrap(),
+        )
+        .unwrap();
+        assert!((reversed.expected_value + report.expected_value).abs() < 1e-9);
+    }
+}
@@ -1,33 +1,86 @@
 use std::num::NonZeroUsize;
 
 use indexmap::IndexMap;
+use rand::{thread_rng, Rng};
+use thiserror::Error;
 
 pub use round::Round;
-use state::{Betting, Called, GameOver, GameState, InRound, NewRound, RoundState};
+use state::{Betting, Called, GameOver, GameState, InRound, NewRound, RoundState, UnfinishedRound};
 
 use crate::{
     bet::{self, Bet},
     player::Player,
 };
 
+#[cfg(feature = "audit")]
+pub mod audit;
+pub mod clock;
+pub mod log;
 pub mod round;
+pub mod session;
 pub mod state;
+pub mod view;
 
 pub type PlayerRef = std::sync::Arc<Player>;
 
+/// When a player is allowed to take back the last bet of a round
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Default)]
+pub enum UndoPolicy {
+    /// Bets can be taken back one after another, all the way back to the opening bid
+    Always,
+    /// Only the latest bet can be taken back, and only once in a row, so bets that the next player
+    /// already acted on stay
+    BeforeNextPlayerActs,
+    #[default]
+    Never,
+}
+
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
 pub struct GameConfig {
     max_dice: NonZeroUsize,
     max_roll: NonZeroUsize,
+    #[serde(default)]
+    undo_policy: UndoPolicy,
 }
 
-impl Default for GameConfig {
-    fn default() -> Self {
+impl GameConfig {
+    #[must_use]
+    pub const fn new(max_dice: NonZeroUsize, max_roll: NonZeroUsize) -> Self {
+        Self {
+            max_dice,
+            max_roll,
+            undo_policy: UndoPolicy::Never,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_undo_policy(self, undo_policy: UndoPolicy) -> Self {
         Self {
-            max_dice: NonZeroUsize::new(5).unwrap(),
-            max_roll: NonZeroUsize::new(6).unwrap(),
+            undo_policy,
+            ..self
         }
     }
+
+    #[must_use]
+    pub const fn undo_policy(&self) -> UndoPolicy {
+        self.undo_policy
+    }
+
+    #[must_use]
+    pub const fn max_dice(&self) -> NonZeroUsize {
+        self.max_dice
+    }
+
+    #[must_use]
+    pub const fn max_roll(&self) -> NonZeroUsize {
+        self.max_roll
+    }
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self::new(NonZeroUsize::new(5).unwrap(), NonZeroUsize::new(6).unwrap())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
@@ -35,23 +88,42 @@ pub struct Game<State: GameState = InRound<NewRound>> {
     player_dice_counts: IndexMap<PlayerRef, usize>,
     config: GameConfig,
     round_history: Vec<Round<Called>>,
+    #[serde(default)]
+    forfeits: Vec<Forfeit>,
     state_data: State,
 }
 
+/// A player knocked out without losing a round, by giving up or running out of time
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct Forfeit {
+    pub player: PlayerRef,
+    /// How many rounds had been called when they were knocked out
+    pub rounds_played: usize,
+}
+
 impl Game {
     pub fn new(
         players: impl IntoIterator<Item = Player>,
         config: GameConfig,
+    ) -> Game<InRound<NewRound>> {
+        Self::new_with_rng(players, config, &mut thread_rng())
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(
+        players: impl IntoIterator<Item = Player>,
+        config: GameConfig,
+        rng: &mut R,
     ) -> Game<InRound<NewRound>> {
         let player_dice_counts = players
             .into_iter()
             .map(|x| (PlayerRef::from(x), config.max_dice.get()))
             .collect();
-        let curr_round = Round::new(&player_dice_counts, config.max_roll);
+        let curr_round = Round::new_with_rng(&player_dice_counts, config.max_roll, rng);
         Game {
             player_dice_counts,
             config,
             round_history: Vec::new(),
+            forfeits: Vec::new(),
             state_data: InRound { curr_round },
         }
     }
@@ -65,6 +137,34 @@ impl<T: GameState> Game<T> {
     pub fn player_dice_counts(&self) -> &IndexMap<PlayerRef, usize> {
         &self.player_dice_counts
     }
+
+    pub fn config(&self) -> &GameConfig {
+        &self.config
+    }
+
+    /// Players who gave up, in the order they did
+    pub fn forfeits(&self) -> &Vec<Forfeit> {
+        &self.forfeits
+    }
+
+    /// How many dice `player` has left, or [`None`] if they are not in this game
+    #[must_use]
+    pub fn dice_count_of(&self, player: &Player) -> Option<usize> {
+        self.player_dice_counts.get(player).copied()
+    }
+
+    /// Players who still have dice, in seating order
+    pub fn alive_players(&self) -> impl Iterator<Item = &PlayerRef> {
+        self.player_dice_counts
+            .iter()
+            .filter(|(_, dice_count)| **dice_count != 0)
+            .map(|(player, _)| player)
+    }
+
+    #[must_use]
+    pub fn total_dice_in_play(&self) -> usize {
+        self.player_dice_counts.values().sum()
+    }
 }
 
 impl<T: RoundState> Game<InRound<T>> {
@@ -72,15 +172,221 @@ impl<T: RoundState> Game<InRound<T>> {
     pub fn curr_round(&self) -> &Round<T> {
         &self.state_data.curr_round
     }
+
+    /// Number of the current round, starting from 1
+    #[must_use]
+    pub fn round_number(&self) -> usize {
+        self.round_history.len() + 1
+    }
+
+    #[must_use]
+    pub fn last_bet(&self) -> Option<Bet> {
+        self.curr_round().last_bet()
+    }
+}
+
+impl<T: UnfinishedRound> Game<InRound<T>> {
+    /// Replaces the dice `player` rolled this round without any checks, e.g. to hide them before
+    /// saving
+    #[cfg(feature = "sealed")]
+    pub(crate) fn replace_rolls(&mut self, player: &PlayerRef, rolls: round::RollSet) {
+        self.state_data.curr_round.replace_rolls(player, rolls);
+    }
+
+    #[must_use]
+    pub fn current_player(&self) -> &PlayerRef {
+        self.curr_round().current_player()
+    }
+
+    #[must_use]
+    pub fn next_player(&self) -> PlayerRef {
+        self.curr_round().next_player()
+    }
+
+    #[must_use]
+    pub fn forfeit(self, player: &Player) -> FluffCallTransition {
+        self.forfeit_with_rng(player, &mut thread_rng())
+    }
+
+    /// Takes every die away from `player`, abandoning the current round
+    ///
+    /// The other players start a new round, opened by whoever would have been next to act, unless
+    /// only one of them is left.
+    ///
+    /// # Panics
+    ///
+    /// If `player` has no dice left.
+    #[must_use]
+    pub fn forfeit_with_rng<R: Rng + ?Sized>(
+        self,
+        player: &Player,
+        rng: &mut R,
+    ) -> FluffCallTransition {
+        self.take_dice(player, usize::MAX, rng)
+    }
+
+    #[must_use]
+    pub fn forfeit_die(self, player: &Player) -> FluffCallTransition {
+        self.forfeit_die_with_rng(player, &mut thread_rng())
+    }
+
+    /// Takes a die away from `player` as if they lost the round, but without calling it
+    ///
+    /// The current round is abandoned like with [`Game::forfeit_with_rng`].
+    ///
+    /// # Panics
+    ///
+    /// If `player` has no dice left.
+    #[must_use]
+    pub fn forfeit_die_with_rng<R: Rng + ?Sized>(
+        self,
+        player: &Player,
+        rng: &mut R,
+    ) -> FluffCallTransition {
+        self.take_dice(player, 1, rng)
+    }
+
+    fn take_dice<R: Rng + ?Sized>(
+        self,
+        player: &Player,
+        dice: usize,
+        rng: &mut R,
+    ) -> FluffCallTransition {
+        let first_player = if **self.current_player() == *player {
+            self.next_player()
+        } else {
+            self.current_player().clone()
+        };
+        let mut player_dice_counts = self.player_dice_counts;
+        let (player, dice_count) = player_dice_counts
+            .get_key_value_mut(player)
+            .filter(|(_, dice_count)| **dice_count != 0)
+            .expect("Only a player with dice left can forfeit");
+        *dice_count = dice_count.saturating_sub(dice);
+        let mut forfeits = self.forfeits;
+        if *dice_count == 0 {
+            forfeits.push(Forfeit {
+                player: player.clone(),
+                rounds_played: self.round_history.len(),
+            });
+        }
+        let mut alive = player_dice_counts.iter().filter(|(_, x)| **x != 0);
+        if let (Some((winner, _)), None) = (alive.next(), alive.next()) {
+            return FluffCallTransition::GameOver(Game {
+                state_data: GameOver {
+                    winner: winner.clone(),
+                },
+                player_dice_counts,
+                config: self.config,
+                round_history: self.round_history,
+                forfeits,
+            });
+        }
+        let curr_round = Round::new_with_rng(&player_dice_counts, self.config.max_roll, rng)
+            .with_first_player(&first_player)
+            .expect("The next player should still have dice");
+        FluffCallTransition::NextRound(Game {
+            player_dice_counts,
+            config: self.config,
+            round_history: self.round_history,
+            forfeits,
+            state_data: InRound { curr_round },
+        })
+    }
+}
+
+impl Game<GameOver> {
+    #[must_use]
+    pub fn winner(&self) -> &PlayerRef {
+        &self.state_data.winner
+    }
+
+    /// Every player from the winner to the first one knocked out
+    #[must_use]
+    pub fn finishing_order(&self) -> Vec<PlayerRef> {
+        // a forfeit after n rounds comes after the loss in round n
+        let mut knocked_out = self
+            .round_history
+            .iter()
+            .enumerate()
+            .filter(|(_, round)| round.players_rolls()[round.state_data().loser()].len() == 1)
+            .map(|(i, round)| ((i + 1) * 2, round.state_data().loser().clone()))
+            .chain(
+                self.forfeits
+                    .iter()
+                    .map(|forfeit| (forfeit.rounds_played * 2 + 1, forfeit.player.clone())),
+            )
+            .collect::<Vec<_>>();
+        knocked_out.sort_by_key(|(time, _)| *time);
+        let mut order = vec![self.winner().clone()];
+        order.extend(knocked_out.into_iter().rev().map(|(_, player)| player));
+        order
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RollsError {
+    #[error("Player {0} was given rolls but has no dice in this game")]
+    UnknownPlayer(PlayerRef),
+    #[error("Player {player} should have {expected} dice, but {actual} were given")]
+    WrongDiceCount {
+        player: PlayerRef,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Player {player} rolled a {roll}, which is higher than the max roll of {max_roll}")]
+    RollTooHigh {
+        player: PlayerRef,
+        roll: NonZeroUsize,
+        max_roll: NonZeroUsize,
+    },
 }
 
 impl Game<InRound<NewRound>> {
+    /// Replaces the dice of the current round with already rolled ones, e.g. from a log or from
+    /// another source of randomness, keeping the same first player
+    pub fn set_rolls(
+        &mut self,
+        mut rolls: IndexMap<PlayerRef, round::RollSet>,
+    ) -> Result<(), RollsError> {
+        if let Some(unknown) = rolls
+            .keys()
+            .find(|player| self.player_dice_counts.get(*player).is_none_or(|x| *x == 0))
+        {
+            return Err(RollsError::UnknownPlayer(unknown.clone()));
+        }
+        let mut ordered_rolls = IndexMap::with_capacity(rolls.len());
+        for (player, &expected) in self.player_dice_counts.iter().filter(|(_, x)| **x != 0) {
+            let player_rolls = rolls.swap_remove(player).unwrap_or_else(|| [].into());
+            if player_rolls.len() != expected {
+                return Err(RollsError::WrongDiceCount {
+                    player: player.clone(),
+                    expected,
+                    actual: player_rolls.len(),
+                });
+            }
+            if let Some(&roll) = player_rolls.iter().find(|x| **x > self.config.max_roll) {
+                return Err(RollsError::RollTooHigh {
+                    player: player.clone(),
+                    roll,
+                    max_roll: self.config.max_roll,
+                });
+            }
+            ordered_rolls.insert(player.clone(), player_rolls);
+        }
+        self.state_data.curr_round = Round::from_rolls(ordered_rolls)
+            .with_first_player(self.current_player())
+            .expect("First player of the current round should still have dice");
+        Ok(())
+    }
+
     #[must_use]
     pub fn raise_bet(self, bet: Bet) -> Game<InRound<Betting>> {
         Game {
             player_dice_counts: self.player_dice_counts,
             config: self.config,
             round_history: self.round_history,
+            forfeits: self.forfeits,
             state_data: InRound {
                 curr_round: self.state_data.curr_round.raise_bet(bet),
             },
@@ -105,7 +411,68 @@ impl From<Game<GameOver>> for FluffCallTransition {
     }
 }
 
+pub enum UndoTransition {
+    NewRound(Game<InRound<NewRound>>),
+    Betting(Game<InRound<Betting>>),
+}
+
+#[derive(Error, Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub enum UndoError {
+    #[error("Bets can not be taken back in this game")]
+    NotAllowed,
+    #[error("Only the latest bet can be taken back, and it already was")]
+    AlreadyUndone,
+    #[error("There is no bet to take back")]
+    NothingToUndo,
+}
+
+/// An undo that was refused by the game's [`UndoPolicy`], along with the untouched game
+#[derive(Error, Debug)]
+#[error("{error}")]
+pub struct UndoRefused {
+    pub game: Box<Game<InRound<Betting>>>,
+    pub error: UndoError,
+}
+
 impl Game<InRound<Betting>> {
+    /// Checks whether the game's [`UndoPolicy`] allows taking back the last bet
+    pub fn can_undo(&self) -> Result<(), UndoError> {
+        match self.config.undo_policy {
+            UndoPolicy::Always => Ok(()),
+            UndoPolicy::BeforeNextPlayerActs if self.curr_round().state_data().after_undo => {
+                Err(UndoError::AlreadyUndone)
+            }
+            UndoPolicy::BeforeNextPlayerActs => Ok(()),
+            UndoPolicy::Never => Err(UndoError::NotAllowed),
+        }
+    }
+
+    /// Takes back the last bet of the round, going back to [`NewRound`] if it was the opening bid
+    pub fn undo(self) -> Result<UndoTransition, UndoRefused> {
+        if let Err(error) = self.can_undo() {
+            return Err(UndoRefused {
+                game: Box::new(self),
+                error,
+            });
+        }
+        Ok(match self.state_data.curr_round.undo() {
+            round::RoundUndo::NewRound(curr_round) => UndoTransition::NewRound(Game {
+                player_dice_counts: self.player_dice_counts,
+                config: self.config,
+                round_history: self.round_history,
+                forfeits: self.forfeits,
+                state_data: InRound { curr_round },
+            }),
+            round::RoundUndo::Betting(curr_round) => UndoTransition::Betting(Game {
+                player_dice_counts: self.player_dice_counts,
+                config: self.config,
+                round_history: self.round_history,
+                forfeits: self.forfeits,
+                state_data: InRound { curr_round },
+            }),
+        })
+    }
+
     pub fn raise_bet(&mut self, bet: Bet) -> Result<(), bet::RaiseError> {
         self.state_data.curr_round.raise_bet(bet)?;
         Ok(())
@@ -113,6 +480,11 @@ impl Game<InRound<Betting>> {
 
     #[must_use]
     pub fn call_fluff(self) -> FluffCallTransition {
+        self.call_fluff_with_rng(&mut thread_rng())
+    }
+
+    #[must_use]
+    pub fn call_fluff_with_rng<R: Rng + ?Sized>(self, rng: &mut R) -> FluffCallTransition {
         let finished_round = self.state_data.curr_round.call_fluff();
         let winner = finished_round.state_data().winner().clone();
         let (player_is_out, player_dice_counts) = {
@@ -134,15 +506,18 @@ impl Game<InRound<Betting>> {
                 player_dice_counts,
                 config,
                 round_history,
+                forfeits: self.forfeits,
                 state_data: GameOver { winner },
             });
         };
-        let new_round = Round::new_with_first_player(&player_dice_counts, config.max_roll, &winner)
+        let new_round = Round::new_with_rng(&player_dice_counts, config.max_roll, rng)
+            .with_first_player(&winner)
             .expect("Winner of previous round should be in player dice counts");
         FluffCallTransition::NextRound(Game {
             player_dice_counts,
             config,
             round_history,
+            forfeits: self.forfeits,
             state_data: InRound {
                 curr_round: new_round,
             },
@@ -175,4 +550,114 @@ mod tests {
         assert_eq!(g_clone, g_de);
         // println!("\n\n\n{g_de:#?}\n\n\n{g_clone:#?}");
     }
+
+    #[test]
+    fn test_undo() {
+        let bet = |count, roll| {
+            Bet::new(
+                NonZeroUsize::new(count).unwrap(),
+                NonZeroUsize::new(roll).unwrap(),
+            )
+        };
+        for policy in [
+            UndoPolicy::Always,
+            UndoPolicy::BeforeNextPlayerActs,
+            UndoPolicy::Never,
+        ] {
+            let g = Game::new(
+                [
+                    Player::new("Unga"),
+                    Player::new("Bunga"),
+                    Player::new("Ooga"),
+                ],
+                GameConfig::default().with_undo_policy(policy),
+            );
+            let new_round = g.clone();
+            let opening_bid = g.raise_bet(bet(1, 2));
+            let mut g = opening_bid.clone();
+            g.raise_bet(bet(1, 3)).unwrap();
+            let g = match (policy, g.undo()) {
+                (UndoPolicy::Never, Err(refused)) => {
+                    assert_eq!(refused.error, UndoError::NotAllowed);
+                    continue;
+                }
+                (_, Ok(UndoTransition::Betting(g))) => g,
+                _ => panic!("Undoing the second bet should go back to betting on the opening bid"),
+            };
+            assert_eq!(
+                g.curr_round().state_data().prev_bet,
+                opening_bid.curr_round().state_data().prev_bet
+            );
+            assert_eq!(
+                g.curr_round().state_data().curr_player_rolls,
+                opening_bid.curr_round().state_data().curr_player_rolls
+            );
+            match (policy, g.undo()) {
+                (UndoPolicy::BeforeNextPlayerActs, Err(refused)) => {
+                    assert_eq!(refused.error, UndoError::AlreadyUndone);
+                }
+                (UndoPolicy::Always, Ok(UndoTransition::NewRound(g))) => assert_eq!(g, new_round),
+                _ => panic!("Undo should have followed the undo policy"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_queries() {
+        let bet = Bet::new(NonZeroUsize::MIN, NonZeroUsize::new(6).unwrap());
+        let g = Game::new(
+            [
+                Player::new("Unga"),
+                Player::new("Bunga"),
+                Player::new("Ooga"),
+            ],
+            GameConfig::default(),
+        );
+        assert_eq!(g.round_number(), 1);
+        assert_eq!(g.total_dice_in_play(), 15);
+        assert_eq!(g.current_player().as_str(), "Unga");
+        assert_eq!(g.next_player().as_str(), "Bunga");
+        assert_eq!(g.last_bet(), None);
+        let g = g.raise_bet(bet);
+        assert_eq!(g.current_player().as_str(), "Bunga");
+        assert_eq!(g.next_player().as_str(), "Ooga");
+        assert_eq!(g.last_bet(), Some(bet));
+        assert_eq!(g.curr_round().turns().len(), 1);
+        let FluffCallTransition::NextRound(g) = g.call_fluff() else {
+            panic!("One lost die should not end the game");
+        };
+        assert_eq!(g.round_number(), 2);
+        assert_eq!(g.total_dice_in_play(), 14);
+        assert_eq!(g.alive_players().count(), 3);
+        assert_eq!(g.dice_count_of(&Player::new("Ooga")), Some(5));
+        assert_eq!(g.dice_count_of(&Player::new("Booga")), None);
+    }
+
+    #[test]
+    fn test_forfeit() {
+        let bet = Bet::new(NonZeroUsize::MIN, NonZeroUsize::new(6).unwrap());
+        let [unga, bunga, ooga] = ["Unga", "Bunga", "Ooga"].map(Player::new);
+        let g = Game::new(
+            [unga.clone(), bunga.clone(), ooga.clone()],
+            GameConfig::default(),
+        )
+        .raise_bet(bet);
+        // the current player giving up passes the opening bid on to the next one
+        let FluffCallTransition::NextRound(g) = g.forfeit(&bunga) else {
+            panic!("Two players should be left");
+        };
+        assert_eq!(g.round_number(), 1);
+        assert_eq!(g.last_bet(), None);
+        assert_eq!(g.current_player().as_str(), "Ooga");
+        assert_eq!(g.dice_count_of(&bunga), Some(0));
+        let FluffCallTransition::GameOver(g) = g.forfeit(&unga) else {
+            panic!("One player should be left");
+        };
+        assert_eq!(g.winner().as_str(), "Ooga");
+        assert_eq!(
+            g.finishing_order(),
+            [ooga, unga, bunga].map(PlayerRef::new).to_vec()
+        );
+        assert_eq!(g.forfeits().len(), 2);
+    }
 }
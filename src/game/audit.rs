@@ -0,0 +1,453 @@
+//! A tamper-evident wrapper around [`GameLog`]
+//!
+//! Every entry records the hash of the entry before it, starting from a hash of the game's setup,
+//! so changing, adding or removing any entry breaks the chain after it. Entries of a player's own
+//! actions can also be signed with that player's key. Since the last entry has nothing after it to
+//! break, [`AuditLog::head`] should be kept somewhere else to notice it being changed.
+
+use std::collections::HashMap;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use indexmap::IndexMap;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    game::{
+        log::{steps, GameLog, LogEvent, ReplayError},
+        session::{Action, GameSession, RejectedAction},
+        PlayerRef,
+    },
+    player::Player,
+};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AuditError {
+    #[error("The log has {events} events, but {links} links")]
+    WrongLength { events: usize, links: usize },
+    #[error("Entry {index}: does not link to the entry before it")]
+    BrokenLink { index: usize },
+    #[error("Entry {index}: the signature is not from whoever acted")]
+    BadSignature { index: usize },
+    #[error("Entry {index}: {player} did not sign their action")]
+    MissingSignature { index: usize, player: PlayerRef },
+    #[error(transparent)]
+    Replay(#[from] ReplayError),
+}
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub struct EntryHash(#[serde(with = "hex::serde")] [u8; 32]);
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+struct PublicKey(#[serde(with = "hex::serde")] [u8; 32]);
+
+/// What each entry adds to its event
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct Link {
+    pub prev: EntryHash,
+    #[serde(default, with = "hex::serde", skip_serializing_if = "Vec::is_empty")]
+    signature: Vec<u8>,
+}
+
+impl Link {
+    #[must_use]
+    pub fn is_signed(&self) -> bool {
+        !self.signature.is_empty()
+    }
+}
+
+fn entry_hash(prev: &EntryHash, event: &LogEvent) -> EntryHash {
+    let event = serde_json::to_vec(event).expect("Log events should serialize");
+    EntryHash(
+        Sha256::new()
+            .chain_update(prev.0)
+            .chain_update(event)
+            .finalize()
+            .into(),
+    )
+}
+
+/// The player whose own doing `event` is, if anyone's
+fn actor(event: &LogEvent) -> Option<&PlayerRef> {
+    match event {
+        LogEvent::Acted { player, .. }
+        | LogEvent::Undone { player, .. }
+        | LogEvent::Forfeited { player }
+        | LogEvent::ForfeitedDie { player } => Some(player),
+        LogEvent::RoundStarted { .. } | LogEvent::TimedOut { .. } | LogEvent::Clock { .. } => None,
+    }
+}
+
+/// A [`GameLog`] with a hash chain over its events, optionally signed by the players who acted
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct AuditLog {
+    log: GameLog,
+    /// Keys of the players who sign their actions
+    keys: IndexMap<PlayerRef, PublicKey>,
+    /// One link for each event of the log
+    links: Vec<Link>,
+}
+
+impl AuditLog {
+    /// Starts auditing `log`, linking the events it already has without any signatures
+    ///
+    /// Every player in `keys` has to sign their bets, calls, undos and forfeits from then on.
+    pub fn new(log: GameLog, keys: impl IntoIterator<Item = (Player, VerifyingKey)>) -> Self {
+        let mut audit = Self {
+            log,
+            keys: keys
+                .into_iter()
+                .map(|(player, key)| (player.into(), PublicKey(key.to_bytes())))
+                .collect(),
+            links: Vec::new(),
+        };
+        audit.link(None);
+        audit
+    }
+
+    #[must_use]
+    pub fn log(&self) -> &GameLog {
+        &self.log
+    }
+
+    #[must_use]
+    pub fn links(&self) -> &[Link] {
+        &self.links
+    }
+
+    /// The hash every log starts from, covering the game's setup and the players' keys
+    fn genesis(&self) -> EntryHash {
+        let mut hasher = Sha256::new();
+        hasher.update(b"fluff audit log");
+        for part in [
+            serde_json::to_vec(self.log.config()),
+            serde_json::to_vec(self.log.players()),
+            serde_json::to_vec(&self.log.seed()),
+            serde_json::to_vec(&self.keys),
+        ] {
+            hasher.update(part.expect("The game's setup should serialize"));
+        }
+        EntryHash(hasher.finalize().into())
+    }
+
+    /// The hash of the last entry, which the next entry will link to
+    #[must_use]
+    pub fn head(&self) -> EntryHash {
+        let last = self.links.len().checked_sub(1);
+        last.and_then(|index| {
+            Some(entry_hash(
+                &self.links[index].prev,
+                self.log.events().get(index)?,
+            ))
+        })
+        .unwrap_or_else(|| self.genesis())
+    }
+
+    /// Links every event that is not linked yet, signing those that are a player's own doing with
+    /// `key`
+    fn link(&mut self, key: Option<&SigningKey>) {
+        let mut prev = self.head();
+        for event in &self.log.events()[self.links.len()..] {
+            let hash = entry_hash(&prev, event);
+            let signature = match (key, actor(event)) {
+                (Some(key), Some(_)) => key.sign(&hash.0).to_vec(),
+                _ => Vec::new(),
+            };
+            self.links.push(Link { prev, signature });
+            prev = hash;
+        }
+    }
+
+    /// Runs `record` on the log, then links whatever it recorded, signed with `key` if it is the
+    /// acting player's
+    pub fn record<T>(
+        &mut self,
+        key: Option<&SigningKey>,
+        record: impl FnOnce(&mut GameLog) -> T,
+    ) -> T {
+        let result = record(&mut self.log);
+        self.link(key);
+        result
+    }
+
+    /// Same as [`GameLog::act`], signed with `key`
+    pub fn act(
+        &mut self,
+        session: GameSession,
+        action: Action,
+        key: Option<&SigningKey>,
+    ) -> Result<GameSession, RejectedAction> {
+        self.record(key, |log| log.act(session, action))
+    }
+
+    /// Replays the log, returning the state it ends in along with what each timeout requires
+    /// next, by the index of the event that should be it
+    fn replay(&self) -> (Result<GameSession, ReplayError>, HashMap<usize, LogEvent>) {
+        let events = self.log.events();
+        let mut outcomes = HashMap::new();
+        let mut steps = steps(&self.log);
+        let mut replayed = Err(ReplayError::MissingStart);
+        while let Some(step) = steps.next() {
+            if let Ok(session) = &step {
+                // a timeout is always the first event of a step other than clocks
+                let start = steps.next_index();
+                let mut rest = events[start..]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, x)| !matches!(x, LogEvent::Clock { .. }));
+                if let (Some((_, LogEvent::TimedOut { player, action })), Some((offset, _))) =
+                    (rest.next(), rest.next())
+                {
+                    if let Some(outcome) = action.outcome(session, player.clone()) {
+                        outcomes.insert(start + offset, outcome);
+                    }
+                }
+            }
+            replayed = step;
+        }
+        (replayed, outcomes)
+    }
+
+    /// Checks the signature of `event`, which every event of a player with a key needs unless it
+    /// is `timeout_outcome`, i.e. exactly what their timeout required
+    fn check_signature(
+        &self,
+        index: usize,
+        event: &LogEvent,
+        link: &Link,
+        hash: &EntryHash,
+        timeout_outcome: Option<&LogEvent>,
+    ) -> Result<(), AuditError> {
+        let Some(player) = actor(event) else {
+            if link.is_signed() {
+                return Err(AuditError::BadSignature { index });
+            }
+            return Ok(());
+        };
+        let key = self.keys.get(player);
+        if !link.is_signed() {
+            // actions taken for a player who ran out of time can't be signed by them
+            if key.is_some() && timeout_outcome != Some(event) {
+                return Err(AuditError::MissingSignature {
+                    index,
+                    player: player.clone(),
+                });
+            }
+            return Ok(());
+        }
+        let signature = Signature::from_slice(&link.signature);
+        let key = key.map(|x| VerifyingKey::from_bytes(&x.0));
+        match (key, signature) {
+            (Some(Ok(key)), Ok(signature)) if key.verify_strict(&hash.0, &signature).is_ok() => {
+                Ok(())
+            }
+            _ => Err(AuditError::BadSignature { index }),
+        }
+    }
+
+    /// Walks the whole log, returning the state it ends in, or the first entry that breaks the
+    /// chain, has a bad signature or records an invalid action
+    ///
+    /// A changed entry shows up as a broken link in the entry after it, unless it was signed or no
+    /// longer replays.
+    pub fn verify(&self) -> Result<GameSession, AuditError> {
+        let events = self.log.events();
+        if events.len() != self.links.len() {
+            return Err(AuditError::WrongLength {
+                events: events.len(),
+                links: self.links.len(),
+            });
+        }
+        let (replayed, timeout_outcomes) = self.replay();
+        let mut prev = self.genesis();
+        let mut broken = None;
+        for (index, (event, link)) in events.iter().zip(&self.links).enumerate() {
+            if link.prev != prev {
+                broken = Some((index, AuditError::BrokenLink { index }));
+                break;
+            }
+            let hash = entry_hash(&prev, event);
+            let timeout_outcome = timeout_outcomes.get(&index);
+            if let Err(err) = self.check_signature(index, event, link, &hash, timeout_outcome) {
+                broken = Some((index, err));
+                break;
+            }
+            prev = hash;
+        }
+        match (broken, replayed) {
+            (Some((index, _)), Err(err)) if err.index() < index => Err(err.into()),
+            (Some((_, err)), _) => Err(err),
+            (None, replayed) => Ok(replayed?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{
+        bet::{Bet, RaiseError},
+        game::{clock::TimeoutAction, session::ActionError, GameConfig},
+    };
+
+    fn bet(count: usize) -> Action {
+        Action::Raise(Bet::new(
+            NonZeroUsize::new(count).unwrap(),
+            NonZeroUsize::MIN,
+        ))
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let [a, b] = ["A", "B"].map(Player::new);
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let (log, session) = GameLog::start([a.clone(), b], GameConfig::default());
+        let mut audit = AuditLog::new(log, [(a.clone(), key.verifying_key())]);
+        let session = audit.act(session, bet(1), Some(&key)).unwrap();
+        let session = audit.act(session, bet(2), None).unwrap();
+        let session = audit.act(session, bet(3), Some(&key)).unwrap();
+        assert_eq!(audit.verify(), Ok(session));
+        assert!(audit.links()[1].is_signed() && !audit.links()[2].is_signed());
+
+        let tamper = |index: usize, count: usize| -> AuditLog {
+            let mut tampered = serde_json::to_value(&audit).unwrap();
+            tampered["log"]["events"][index]["Acted"]["action"]["Raise"]["count"] = count.into();
+            serde_json::from_value(tampered).unwrap()
+        };
+        // a signed entry can't be changed at all
+        assert_eq!(
+            tamper(3, 4).verify(),
+            Err(AuditError::BadSignature { index: 3 })
+        );
+        // an unsigned one breaks the link after it
+        assert_eq!(
+            tamper(2, 5).verify(),
+            Err(AuditError::BrokenLink { index: 3 })
+        );
+        // and relinking everything after it breaks the signatures
+        let mut relinked = tamper(2, 5);
+        relinked.links.truncate(2);
+        relinked.link(None);
+        relinked.links[3].signature = audit.links[3].signature.clone();
+        assert_eq!(
+            relinked.verify(),
+            Err(AuditError::BadSignature { index: 3 })
+        );
+
+        audit.links.pop();
+        assert_eq!(
+            audit.verify(),
+            Err(AuditError::WrongLength {
+                events: 4,
+                links: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_actions() {
+        let [a, b] = ["A", "B"].map(Player::new);
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let (log, session) = GameLog::start([a.clone(), b], GameConfig::default());
+        let mut audit = AuditLog::new(log.clone(), [(a.clone(), key.verifying_key())]);
+        audit.act(session.clone(), bet(1), None).unwrap();
+        assert_eq!(
+            audit.verify(),
+            Err(AuditError::MissingSignature {
+                index: 1,
+                player: PlayerRef::new(a),
+            })
+        );
+
+        // without any keys, a rebuilt chain is only caught by replaying it
+        let mut audit = AuditLog::new(log, []);
+        let session = audit.act(session, bet(2), None).unwrap();
+        audit.act(session, bet(3), None).unwrap();
+        let mut tampered = serde_json::to_value(&audit).unwrap();
+        tampered["log"]["events"][1]["Acted"]["action"]["Raise"]["count"] = 5.into();
+        let mut tampered: AuditLog = serde_json::from_value(tampered).unwrap();
+        tampered.links.clear();
+        tampered.link(None);
+        assert_eq!(
+            tampered.verify(),
+            Err(AuditError::Replay(ReplayError::Action {
+                index: 2,
+                source: ActionError::Raise(RaiseError::CountDecreased {
+                    prev: NonZeroUsize::new(5).unwrap(),
+                    new: NonZeroUsize::new(3).unwrap(),
+                })
+            }))
+        );
+    }
+
+    #[test]
+    fn test_forged_timeout() {
+        let [a, b] = ["A", "B"].map(Player::new);
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let (log, session) = GameLog::start([a.clone(), b], GameConfig::default());
+        let mut audit = AuditLog::new(log, [(a.clone(), key.verifying_key())]);
+        let session = audit.act(session, bet(1), Some(&key)).unwrap();
+        let session = audit.act(session, bet(2), None).unwrap();
+        // what a real timeout does for A needs no signature
+        let mut timed_out = audit.clone();
+        let session = timed_out
+            .record(None, |log| log.time_out(session, TimeoutAction::MinRaise))
+            .unwrap();
+        assert_eq!(timed_out.verify(), Ok(session));
+
+        // but a bet A never made can't be slipped in behind a made-up timeout
+        let a = PlayerRef::new(a);
+        let mut forged = serde_json::to_value(&audit).unwrap();
+        let events = forged["log"]["events"].as_array_mut().unwrap();
+        for event in [
+            LogEvent::TimedOut {
+                player: a.clone(),
+                action: TimeoutAction::MinRaise,
+            },
+            LogEvent::Acted {
+                player: a.clone(),
+                action: bet(5),
+            },
+        ] {
+            events.push(serde_json::to_value(event).unwrap());
+        }
+        let mut forged: AuditLog = serde_json::from_value(forged).unwrap();
+        forged.link(None);
+        assert_eq!(
+            forged.verify(),
+            Err(AuditError::MissingSignature {
+                index: 4,
+                player: a.clone(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_forged_forfeit() {
+        let [a, b] = ["A", "B"].map(Player::new);
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let (log, session) = GameLog::start([a.clone(), b], GameConfig::default());
+        let mut audit = AuditLog::new(log, [(a.clone(), key.verifying_key())]);
+        let session = audit.act(session, bet(1), Some(&key)).unwrap();
+
+        // A forfeiting needs their signature, just like their bets
+        let mut forged = audit.clone();
+        forged
+            .record(None, |log| log.forfeit(session.clone(), &a))
+            .unwrap();
+        assert_eq!(
+            forged.verify(),
+            Err(AuditError::MissingSignature {
+                index: 2,
+                player: PlayerRef::new(a.clone()),
+            })
+        );
+        let mut signed = audit.clone();
+        let forfeited = signed
+            .record(Some(&key), |log| log.forfeit(session.clone(), &a))
+            .unwrap();
+        assert_eq!(signed.verify(), Ok(forfeited));
+    }
+}
@@ -0,0 +1,482 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use indexmap::IndexMap;
+
+use crate::{
+    game::{
+        log::{GameLog, LogEvent},
+        session::{Action, ActionError, GameSession, RejectedAction},
+        view::PlayerView,
+        GameConfig, PlayerRef,
+    },
+    player::Player,
+};
+
+/// Where a timed game gets the current time from, so tests can control it
+pub trait Clock {
+    /// Time since some fixed point, which only has to stay the same for the whole game
+    fn now(&self) -> Duration;
+}
+
+/// Wall-clock time since the Unix epoch
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+}
+
+/// A clock that only moves when told to, shared between its clones
+#[derive(Debug, Default, Clone)]
+pub struct ManualClock(Arc<Mutex<Duration>>);
+
+impl ManualClock {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock().expect("Clock lock should not be poisoned") += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.0.lock().expect("Clock lock should not be poisoned")
+    }
+}
+
+/// How much time players get
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub enum TimeControl {
+    /// Every turn gets the same time, and time left over is lost
+    Fixed { per_turn: Duration },
+    /// One bank for the whole game, topped up by `increment` after every turn
+    Increment { bank: Duration, increment: Duration },
+    /// Every turn gets `per_turn` before the bank for the whole game starts running
+    Bank { per_turn: Duration, bank: Duration },
+}
+
+/// What is done for a player who runs out of time
+#[derive(Debug, Serialize, Deserialize, Default, Eq, PartialEq, Copy, Clone)]
+pub enum TimeoutAction {
+    /// Calls fluff, or makes the smallest opening bid if nobody has bet yet
+    #[default]
+    CallFluff,
+    /// Makes the smallest legal raise, or calls fluff if there is none
+    MinRaise,
+    /// Loses a die and starts a new round, without calling the current one
+    ForfeitDie,
+    ForfeitGame,
+}
+
+impl TimeoutAction {
+    /// The action to take for `view.viewer`, or [`None`] if this is not an action, or the game is
+    /// over
+    #[must_use]
+    pub fn action_for(self, view: &PlayerView) -> Option<Action> {
+        let actions = view.legal_actions();
+        let min_raise = actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::Raise(bet) => Some(*bet),
+                Action::CallFluff => None,
+            })
+            .min()
+            .map(Action::Raise);
+        let can_call = actions.contains(&Action::CallFluff);
+        match self {
+            Self::CallFluff if can_call => Some(Action::CallFluff),
+            Self::CallFluff => min_raise,
+            Self::MinRaise => min_raise.or(can_call.then_some(Action::CallFluff)),
+            Self::ForfeitDie | Self::ForfeitGame => None,
+        }
+    }
+
+    /// What a log records for `player` running out of time in `session` right after the
+    /// [`LogEvent::TimedOut`], or [`None`] if the game is over
+    #[must_use]
+    pub fn outcome(self, session: &GameSession, player: PlayerRef) -> Option<LogEvent> {
+        if session.is_over() {
+            return None;
+        }
+        Some(match self {
+            Self::CallFluff | Self::MinRaise => LogEvent::Acted {
+                action: self.action_for(&session.view_for(&player))?,
+                player,
+            },
+            Self::ForfeitDie => LogEvent::ForfeitedDie { player },
+            Self::ForfeitGame => LogEvent::Forfeited { player },
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub struct ClockConfig {
+    pub control: TimeControl,
+    pub on_timeout: TimeoutAction,
+}
+
+impl ClockConfig {
+    #[must_use]
+    pub const fn new(control: TimeControl) -> Self {
+        Self {
+            control,
+            on_timeout: TimeoutAction::CallFluff,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_timeout_action(self, on_timeout: TimeoutAction) -> Self {
+        Self { on_timeout, ..self }
+    }
+}
+
+/// Time left on every player's clock, with only the current turn's clock running
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct Clocks {
+    config: ClockConfig,
+    /// Time of the whole game every player has left, which is unused with fixed time per turn
+    banks: IndexMap<PlayerRef, Duration>,
+    /// When the current turn started, by the game's [`Clock`]
+    turn_started: Duration,
+}
+
+impl Clocks {
+    #[must_use]
+    pub fn new<'a>(
+        config: ClockConfig,
+        players: impl IntoIterator<Item = &'a PlayerRef>,
+        now: Duration,
+    ) -> Self {
+        let bank = match config.control {
+            TimeControl::Fixed { .. } => Duration::ZERO,
+            TimeControl::Increment { bank, .. } | TimeControl::Bank { bank, .. } => bank,
+        };
+        Self {
+            config,
+            banks: players
+                .into_iter()
+                .map(|player| (player.clone(), bank))
+                .collect(),
+            turn_started: now,
+        }
+    }
+
+    #[must_use]
+    pub const fn config(&self) -> &ClockConfig {
+        &self.config
+    }
+
+    /// Time `player` has for a turn that has not started yet
+    #[must_use]
+    pub fn allowance(&self, player: &Player) -> Duration {
+        let bank = self.banks.get(player).copied().unwrap_or_default();
+        match self.config.control {
+            TimeControl::Fixed { per_turn } => per_turn,
+            TimeControl::Increment { .. } => bank,
+            TimeControl::Bank { per_turn, .. } => per_turn + bank,
+        }
+    }
+
+    /// Time `player` has left in the current turn
+    #[must_use]
+    pub fn remaining(&self, player: &Player, now: Duration) -> Duration {
+        self.allowance(player)
+            .saturating_sub(now.saturating_sub(self.turn_started))
+    }
+
+    /// Time every player has left, with `current` being the one whose clock is running
+    #[must_use]
+    pub fn snapshot(
+        &self,
+        current: Option<&PlayerRef>,
+        now: Duration,
+    ) -> IndexMap<PlayerRef, Duration> {
+        self.banks
+            .keys()
+            .map(|player| {
+                let left = if Some(player) == current {
+                    self.remaining(player, now)
+                } else {
+                    self.allowance(player)
+                };
+                (player.clone(), left)
+            })
+            .collect()
+    }
+
+    /// Sets `player`'s clock so their next turn gets `allowance`, e.g. as recorded in a
+    /// [`LogEvent::Clock`]
+    pub fn set_allowance(&mut self, player: &Player, allowance: Duration) {
+        if let Some(bank) = self.banks.get_mut(player) {
+            *bank = match self.config.control {
+                TimeControl::Fixed { .. } => Duration::ZERO,
+                TimeControl::Increment { .. } => allowance,
+                TimeControl::Bank { per_turn, .. } => allowance.saturating_sub(per_turn),
+            };
+        }
+    }
+
+    /// Charges the current turn to `player` and starts the next one, returning what they have
+    /// left for their next turn
+    pub fn end_turn(&mut self, player: &Player, now: Duration) -> Duration {
+        let elapsed = now.saturating_sub(self.turn_started);
+        self.turn_started = now;
+        if let Some(bank) = self.banks.get_mut(player) {
+            match self.config.control {
+                TimeControl::Fixed { .. } => {}
+                TimeControl::Increment { increment, .. } => {
+                    *bank = bank.saturating_sub(elapsed) + increment;
+                }
+                TimeControl::Bank { per_turn, .. } => {
+                    *bank = bank.saturating_sub(elapsed.saturating_sub(per_turn));
+                }
+            }
+        }
+        self.allowance(player)
+    }
+}
+
+/// A game played against the clock and recorded in a [`GameLog`], along with every player's time
+///
+/// The game only notices that time ran out when it is told to act or [`TimedGame::enforce_time`]
+/// is called, so whoever drives it should call that whenever the current player's time may be up.
+#[derive(Debug, Clone)]
+pub struct TimedGame<C: Clock = SystemClock> {
+    /// Only [`None`] while an action is being applied
+    session: Option<GameSession>,
+    log: GameLog,
+    clocks: Clocks,
+    clock: C,
+}
+
+impl<C: Clock> TimedGame<C> {
+    pub fn start(
+        players: impl IntoIterator<Item = Player>,
+        config: GameConfig,
+        clock_config: ClockConfig,
+        clock: C,
+    ) -> Self {
+        let (log, session) = GameLog::start(players, config);
+        Self::resume(log, session, clock_config, clock)
+    }
+
+    /// Starts timing a game that is already being played
+    ///
+    /// Every player gets the time the last [`LogEvent::Clock`] of `log` recorded for them, or a
+    /// full clock if they have none. The current turn starts over, so the time the game spent
+    /// saved is not charged to anyone.
+    pub fn resume(log: GameLog, session: GameSession, clock_config: ClockConfig, clock: C) -> Self {
+        let mut clocks = Clocks::new(
+            clock_config,
+            session.player_dice_counts().keys(),
+            clock.now(),
+        );
+        for event in log.events() {
+            if let LogEvent::Clock { player, remaining } = event {
+                clocks.set_allowance(player, *remaining);
+            }
+        }
+        Self {
+            session: Some(session),
+            log,
+            clocks,
+            clock,
+        }
+    }
+
+    #[must_use]
+    pub fn session(&self) -> &GameSession {
+        self.session
+            .as_ref()
+            .expect("Session should only be taken while acting")
+    }
+
+    #[must_use]
+    pub const fn log(&self) -> &GameLog {
+        &self.log
+    }
+
+    #[must_use]
+    pub const fn clocks(&self) -> &Clocks {
+        &self.clocks
+    }
+
+    /// Time the current player has left, or [`None`] if the game is over
+    #[must_use]
+    pub fn remaining(&self) -> Option<Duration> {
+        let player = self.session().current_player()?;
+        Some(self.clocks.remaining(player, self.clock.now()))
+    }
+
+    /// What `viewer` is allowed to know about this game, including everyone's time
+    #[must_use]
+    pub fn view_for(&self, viewer: &PlayerRef) -> PlayerView {
+        let session = self.session();
+        PlayerView {
+            clocks: Some(
+                self.clocks
+                    .snapshot(session.current_player(), self.clock.now()),
+            ),
+            ..session.view_for(viewer)
+        }
+    }
+
+    /// Applies `f` to the session, then starts the next turn's clock
+    fn transition(
+        &mut self,
+        f: impl FnOnce(&mut GameLog, GameSession) -> Result<GameSession, RejectedAction>,
+    ) -> Result<(), ActionError> {
+        let session = self
+            .session
+            .take()
+            .expect("Session should only be taken while acting");
+        let Some(player) = session.current_player().cloned() else {
+            self.session = Some(session);
+            return Err(ActionError::GameOver);
+        };
+        match f(&mut self.log, session) {
+            Ok(session) => {
+                let remaining = self.clocks.end_turn(&player, self.clock.now());
+                self.log.record_clock(player, remaining);
+                self.session = Some(session);
+                Ok(())
+            }
+            Err(rejected) => {
+                self.session = Some(*rejected.session);
+                Err(rejected.error)
+            }
+        }
+    }
+
+    /// Takes the timeout action if the current player is out of time, returning whether they were
+    pub fn enforce_time(&mut self) -> bool {
+        if self.remaining() != Some(Duration::ZERO) {
+            return false;
+        }
+        let on_timeout = self.clocks.config.on_timeout;
+        self.transition(|log, session| log.time_out(session, on_timeout))
+            .expect("A timeout should always be possible in an unfinished game");
+        true
+    }
+
+    /// Applies `action` for the current player, unless their time already ran out
+    pub fn act(&mut self, action: Action) -> Result<(), ActionError> {
+        if self.enforce_time() {
+            return Err(ActionError::OutOfTime);
+        }
+        self.transition(|log, session| log.act(session, action))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{bet::Bet, game::log::replay};
+
+    const fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_clocks() {
+        let [a, b] = ["A", "B"].map(|x| PlayerRef::new(Player::new(x)));
+        let mut fixed = Clocks::new(
+            ClockConfig::new(TimeControl::Fixed { per_turn: secs(5) }),
+            [&a, &b],
+            secs(0),
+        );
+        assert_eq!(fixed.remaining(&a, secs(3)), secs(2));
+        assert_eq!(fixed.end_turn(&a, secs(3)), secs(5));
+        assert_eq!(fixed.remaining(&b, secs(10)), Duration::ZERO);
+
+        let mut bank = Clocks::new(
+            ClockConfig::new(TimeControl::Bank {
+                per_turn: secs(5),
+                bank: secs(10),
+            }),
+            [&a, &b],
+            secs(0),
+        );
+        // only the 3 seconds over the turn's own time come out of the bank
+        assert_eq!(bank.end_turn(&a, secs(8)), secs(12));
+        assert_eq!(
+            bank.snapshot(Some(&b), secs(9))
+                .values()
+                .copied()
+                .collect::<Vec<_>>(),
+            [secs(12), secs(14)]
+        );
+    }
+
+    #[test]
+    fn test_timed_game() {
+        let clock = ManualClock::new();
+        let config = ClockConfig::new(TimeControl::Increment {
+            bank: secs(10),
+            increment: secs(2),
+        })
+        .with_timeout_action(TimeoutAction::ForfeitDie);
+        let [a, b, c] = ["A", "B", "C"].map(Player::new);
+        let mut game = TimedGame::start(
+            [a.clone(), b.clone(), c.clone()],
+            GameConfig::default(),
+            config,
+            clock.clone(),
+        );
+        clock.advance(secs(4));
+        assert_eq!(game.remaining(), Some(secs(6)));
+        game.act(Action::Raise(Bet::new(
+            NonZeroUsize::MIN,
+            NonZeroUsize::MIN,
+        )))
+        .unwrap();
+        clock.advance(secs(3));
+        let clocks = game.view_for(&PlayerRef::new(a)).clocks.unwrap();
+        assert_eq!(
+            clocks.values().copied().collect::<Vec<_>>(),
+            [secs(8), secs(7), secs(10)]
+        );
+
+        // B runs out of time, losing a die, and C opens the next round
+        clock.advance(secs(7));
+        assert_eq!(game.act(Action::CallFluff), Err(ActionError::OutOfTime));
+        let GameSession::NewRound(g) = game.session() else {
+            panic!("A new round should have started");
+        };
+        assert_eq!(g.dice_count_of(&b), Some(4));
+        assert_eq!(**g.current_player(), c);
+        assert!(game.log().events().iter().any(|event| matches!(
+            event,
+            LogEvent::TimedOut {
+                action: TimeoutAction::ForfeitDie,
+                ..
+            }
+        )));
+        assert_eq!(replay(game.log()).as_ref(), Ok(game.session()));
+
+        // reloading the game gives everyone back the time they had
+        let resumed = TimedGame::resume(
+            game.log().clone(),
+            game.session().clone(),
+            config,
+            ManualClock::new(),
+        );
+        assert_eq!(
+            resumed.clocks().snapshot(None, Duration::ZERO),
+            game.clocks().snapshot(None, Duration::ZERO)
+        );
+        assert_eq!(resumed.clocks().allowance(&Player::new("A")), secs(8));
+    }
+}
@@ -0,0 +1,763 @@
+use std::time::Duration;
+
+use indexmap::IndexMap;
+use rand::{rngs::StdRng, SeedableRng};
+use thiserror::Error;
+
+use crate::{
+    bet::Bet,
+    game::{
+        clock::TimeoutAction,
+        round::{RollSet, Round},
+        session::{Action, ActionError, GameSession, RejectedAction},
+        state::{InRound, NewRound},
+        Game, GameConfig, PlayerRef, RollsError,
+    },
+    player::Player,
+};
+
+/// Something that happened in a game, in the order it happened
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub enum LogEvent {
+    RoundStarted {
+        first_player: PlayerRef,
+        rolls: IndexMap<PlayerRef, RollSet>,
+    },
+    Acted {
+        player: PlayerRef,
+        action: Action,
+    },
+    /// `player` took back their `bet`, making it their turn again
+    Undone {
+        player: PlayerRef,
+        bet: Bet,
+    },
+    /// `player` gave up the rest of the game, abandoning the round
+    Forfeited {
+        player: PlayerRef,
+    },
+    /// `player` lost a die without the round being called, abandoning it
+    ForfeitedDie {
+        player: PlayerRef,
+    },
+    /// `player` ran out of time, so `action` is taken for them by the next event
+    TimedOut {
+        player: PlayerRef,
+        action: TimeoutAction,
+    },
+    /// Time `player` had left on their clock when their turn ended
+    Clock {
+        player: PlayerRef,
+        remaining: Duration,
+    },
+}
+
+impl LogEvent {
+    fn round_started(round: &Round<NewRound>) -> Self {
+        Self::RoundStarted {
+            first_player: round.state_data().first_player_rolls.player.clone(),
+            rolls: round.players_rolls().clone(),
+        }
+    }
+}
+
+/// An append-only record of a game, enough to rebuild every state it went through with [`replay`]
+/// or [`steps`]
+///
+/// If the log is seeded, each round is rolled from a [`StdRng`] seeded by the log's seed and the
+/// round's index, so replaying also proves that the recorded rolls came from the seed.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(from = "LogRecord")]
+pub struct GameLog {
+    config: GameConfig,
+    players: Vec<Player>,
+    seed: Option<u64>,
+    events: Vec<LogEvent>,
+    /// How many [`LogEvent::RoundStarted`] are in `events`, which picks the next round's rng
+    #[serde(skip)]
+    rounds_started: u64,
+}
+
+/// A [`GameLog`] as it is saved, before its rounds are counted
+#[derive(Deserialize)]
+struct LogRecord {
+    config: GameConfig,
+    players: Vec<Player>,
+    seed: Option<u64>,
+    events: Vec<LogEvent>,
+}
+
+impl From<LogRecord> for GameLog {
+    fn from(record: LogRecord) -> Self {
+        let rounds_started = record
+            .events
+            .iter()
+            .filter(|x| matches!(x, LogEvent::RoundStarted { .. }))
+            .count() as u64;
+        Self {
+            config: record.config,
+            players: record.players,
+            seed: record.seed,
+            events: record.events,
+            rounds_started,
+        }
+    }
+}
+
+impl GameLog {
+    /// Starts a new game with dice from [`rand::thread_rng`], returning its log and the game
+    pub fn start(
+        players: impl IntoIterator<Item = Player>,
+        config: GameConfig,
+    ) -> (Self, GameSession) {
+        Self::start_inner(players.into_iter().collect(), config, None)
+    }
+
+    /// Starts a new game with dice rolled deterministically from `seed`
+    pub fn start_seeded(
+        players: impl IntoIterator<Item = Player>,
+        config: GameConfig,
+        seed: u64,
+    ) -> (Self, GameSession) {
+        Self::start_inner(players.into_iter().collect(), config, Some(seed))
+    }
+
+    fn start_inner(
+        players: Vec<Player>,
+        config: GameConfig,
+        seed: Option<u64>,
+    ) -> (Self, GameSession) {
+        let game = match seed {
+            Some(seed) => Game::new_with_rng(players.clone(), config, &mut round_rng(seed, 0)),
+            None => Game::new(players.clone(), config),
+        };
+        let log = Self {
+            config,
+            players,
+            seed,
+            events: vec![LogEvent::round_started(game.curr_round())],
+            rounds_started: 1,
+        };
+        (log, game.into())
+    }
+
+    #[must_use]
+    pub const fn config(&self) -> &GameConfig {
+        &self.config
+    }
+
+    #[must_use]
+    pub fn players(&self) -> &[Player] {
+        &self.players
+    }
+
+    #[must_use]
+    pub const fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    #[must_use]
+    pub fn events(&self) -> &[LogEvent] {
+        &self.events
+    }
+
+    /// Rolls of every round in the log, in the order the rounds started
+    #[cfg(feature = "sealed")]
+    pub(crate) fn round_rolls_mut(
+        &mut self,
+    ) -> impl Iterator<Item = &mut IndexMap<PlayerRef, RollSet>> {
+        self.events.iter_mut().filter_map(|event| match event {
+            LogEvent::RoundStarted { rolls, .. } => Some(rolls),
+            _ => None,
+        })
+    }
+
+    /// Applies `action` to `session` and records it, along with the next round if one starts
+    ///
+    /// `session` should be the latest state of the game this log is recording.
+    pub fn act(
+        &mut self,
+        session: GameSession,
+        action: Action,
+    ) -> Result<GameSession, RejectedAction> {
+        let Some(player) = session.current_player().cloned() else {
+            return Err(RejectedAction {
+                session: Box::new(session),
+                error: ActionError::GameOver,
+            });
+        };
+        let session = match self.seed {
+            Some(seed) => {
+                session.act_with_rng(action, &mut round_rng(seed, self.rounds_started))?
+            }
+            None => session.act(action)?,
+        };
+        self.events.push(LogEvent::Acted { player, action });
+        if let (GameSession::NewRound(g), Action::CallFluff) = (&session, action) {
+            self.push_round(g);
+        }
+        Ok(session)
+    }
+
+    /// Knocks `player` out of `session` and records it, along with the next round if one starts
+    pub fn forfeit(
+        &mut self,
+        session: GameSession,
+        player: &Player,
+    ) -> Result<GameSession, RejectedAction> {
+        let session = match self.seed {
+            Some(seed) => {
+                session.forfeit_with_rng(player, &mut round_rng(seed, self.rounds_started))?
+            }
+            None => session.forfeit(player)?,
+        };
+        self.events.push(LogEvent::Forfeited {
+            player: PlayerRef::new(player.clone()),
+        });
+        Ok(self.record_restart(session))
+    }
+
+    /// Takes a die away from `player` in `session` and records it, along with the next round
+    pub fn forfeit_die(
+        &mut self,
+        session: GameSession,
+        player: &Player,
+    ) -> Result<GameSession, RejectedAction> {
+        let session = match self.seed {
+            Some(seed) => {
+                session.forfeit_die_with_rng(player, &mut round_rng(seed, self.rounds_started))?
+            }
+            None => session.forfeit_die(player)?,
+        };
+        self.events.push(LogEvent::ForfeitedDie {
+            player: PlayerRef::new(player.clone()),
+        });
+        Ok(self.record_restart(session))
+    }
+
+    fn push_round(&mut self, game: &Game<InRound<NewRound>>) {
+        self.events.push(LogEvent::round_started(game.curr_round()));
+        self.rounds_started += 1;
+    }
+
+    fn record_restart(&mut self, session: GameSession) -> GameSession {
+        if let GameSession::NewRound(g) = &session {
+            self.push_round(g);
+        }
+        session
+    }
+
+    /// Takes `action` for the current player of `session`, who ran out of time, and records it
+    pub fn time_out(
+        &mut self,
+        session: GameSession,
+        action: TimeoutAction,
+    ) -> Result<GameSession, RejectedAction> {
+        let Some(player) = session.current_player().cloned() else {
+            return Err(RejectedAction {
+                session: Box::new(session),
+                error: ActionError::GameOver,
+            });
+        };
+        let timed_out = LogEvent::TimedOut {
+            player: player.clone(),
+            action,
+        };
+        match action {
+            TimeoutAction::CallFluff | TimeoutAction::MinRaise => {
+                let action = action
+                    .action_for(&session.view_for(&player))
+                    .expect("An unfinished game should have a legal action");
+                self.events.push(timed_out);
+                self.act(session, action)
+            }
+            TimeoutAction::ForfeitDie => {
+                self.events.push(timed_out);
+                self.forfeit_die(session, &player)
+            }
+            TimeoutAction::ForfeitGame => {
+                self.events.push(timed_out);
+                self.forfeit(session, &player)
+            }
+        }
+    }
+
+    /// Records how much time `player` had left when their turn ended
+    pub fn record_clock(&mut self, player: PlayerRef, remaining: Duration) {
+        self.events.push(LogEvent::Clock { player, remaining });
+    }
+
+    /// Takes back the last bet of `session` and records it, if the game's undo policy allows it
+    pub fn undo(&mut self, session: GameSession) -> Result<GameSession, RejectedAction> {
+        let bet = match &session {
+            GameSession::Betting(g) => Some(g.curr_round().state_data().prev_bet),
+            _ => None,
+        };
+        let session = session.undo()?;
+        let (Some(bet), Some(player)) = (bet, session.current_player()) else {
+            unreachable!(
+                "A successful undo should have come from Betting into an unfinished round"
+            );
+        };
+        self.events.push(LogEvent::Undone {
+            player: player.clone(),
+            bet,
+        });
+        Ok(session)
+    }
+}
+
+/// Mixes `seed` with `index` instead of adding them, so consecutive seeds don't share anything
+/// seeded from them
+pub(crate) const fn mix_seed(seed: u64, index: u64) -> u64 {
+    seed ^ index.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+/// The rng of round `round_index` in a game seeded with `seed`
+fn round_rng(seed: u64, round_index: u64) -> StdRng {
+    StdRng::seed_from_u64(mix_seed(seed, round_index))
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    #[error("Log does not start with a round")]
+    MissingStart,
+    #[error("Event {index}: a new round should have started here")]
+    ExpectedRoundStart { index: usize },
+    #[error("Event {index}: a round can not start while another is in progress")]
+    UnexpectedRoundStart { index: usize },
+    #[error("Event {index}: it is {expected}'s turn, but the log says {actual}")]
+    WrongPlayer {
+        index: usize,
+        expected: PlayerRef,
+        actual: PlayerRef,
+    },
+    #[error("Event {index}: {source}")]
+    Rolls { index: usize, source: RollsError },
+    #[error("Event {index}: recorded rolls do not match the rolls generated from the seed")]
+    SeedMismatch { index: usize },
+    #[error("Event {index}: {source}")]
+    Action { index: usize, source: ActionError },
+    #[error("Event {index}: the taken back bet does not match the last bet of the round")]
+    WrongUndo { index: usize },
+    #[error("Event {index}: should be what the timeout before it says is done for the player")]
+    WrongTimeout { index: usize },
+}
+
+impl ReplayError {
+    /// Index of the event the error is about
+    #[must_use]
+    pub const fn index(&self) -> usize {
+        match self {
+            Self::MissingStart => 0,
+            Self::ExpectedRoundStart { index }
+            | Self::UnexpectedRoundStart { index }
+            | Self::WrongPlayer { index, .. }
+            | Self::Rolls { index, .. }
+            | Self::SeedMismatch { index }
+            | Self::Action { index, .. }
+            | Self::WrongUndo { index }
+            | Self::WrongTimeout { index } => *index,
+        }
+    }
+}
+
+/// Rebuilds the latest state of the game recorded in `log`
+pub fn replay(log: &GameLog) -> Result<GameSession, ReplayError> {
+    steps(log).last().unwrap_or(Err(ReplayError::MissingStart))
+}
+
+/// Iterates over every state of the game recorded in `log`, starting with its first round
+///
+/// Iteration stops after the first error.
+#[must_use]
+pub fn steps(log: &GameLog) -> Steps<'_> {
+    Steps {
+        log,
+        next_index: 0,
+        rounds_started: 0,
+        session: None,
+        after_timeout: None,
+        failed: false,
+    }
+}
+
+pub struct Steps<'a> {
+    log: &'a GameLog,
+    next_index: usize,
+    rounds_started: u64,
+    session: Option<GameSession>,
+    /// The event the last timeout requires next, if it was not seen yet
+    after_timeout: Option<LogEvent>,
+    failed: bool,
+}
+
+impl<'a> Steps<'a> {
+    /// Index of the first event of the log not replayed yet
+    #[must_use]
+    pub fn next_index(&self) -> usize {
+        self.next_index
+    }
+
+    fn next_event(&mut self) -> Option<(usize, &'a LogEvent)> {
+        let index = self.next_index;
+        let log: &'a GameLog = self.log;
+        let event = log.events.get(index)?;
+        self.next_index += 1;
+        Some((index, event))
+    }
+
+    /// Checks the rolls of a round that was just started against the next event of the log
+    fn start_round(
+        &mut self,
+        mut game: Game<InRound<NewRound>>,
+    ) -> Result<Game<InRound<NewRound>>, ReplayError> {
+        let expected_index = self.next_index;
+        let Some((
+            index,
+            LogEvent::RoundStarted {
+                first_player,
+                rolls,
+            },
+        )) = self.next_event()
+        else {
+            return Err(if expected_index == 0 {
+                ReplayError::MissingStart
+            } else {
+                ReplayError::ExpectedRoundStart {
+                    index: expected_index,
+                }
+            });
+        };
+        let expected_first_player = game.current_player();
+        if expected_first_player != first_player {
+            return Err(ReplayError::WrongPlayer {
+                index,
+                expected: expected_first_player.clone(),
+                actual: first_player.clone(),
+            });
+        }
+        if self.log.seed.is_some() {
+            if game.curr_round().players_rolls() != rolls {
+                return Err(ReplayError::SeedMismatch { index });
+            }
+        } else {
+            game.set_rolls(rolls.clone())
+                .map_err(|source| ReplayError::Rolls { index, source })?;
+        }
+        self.rounds_started += 1;
+        Ok(game)
+    }
+
+    fn undo(
+        session: GameSession,
+        index: usize,
+        player: &PlayerRef,
+        bet: Bet,
+    ) -> Result<GameSession, ReplayError> {
+        let undone_bet = match &session {
+            GameSession::Betting(g) => Some(g.curr_round().state_data().prev_bet),
+            _ => None,
+        };
+        let session = session.undo().map_err(|rejected| ReplayError::Action {
+            index,
+            source: rejected.error,
+        })?;
+        if undone_bet != Some(bet) || session.current_player() != Some(player) {
+            return Err(ReplayError::WrongUndo { index });
+        }
+        Ok(session)
+    }
+
+    fn forfeit(
+        &mut self,
+        session: GameSession,
+        index: usize,
+        event: &LogEvent,
+        player: &Player,
+    ) -> Result<GameSession, ReplayError> {
+        let die = matches!(event, LogEvent::ForfeitedDie { .. });
+        let next = match (self.log.seed, die) {
+            (Some(seed), false) => {
+                session.forfeit_with_rng(player, &mut round_rng(seed, self.rounds_started))
+            }
+            (Some(seed), true) => {
+                session.forfeit_die_with_rng(player, &mut round_rng(seed, self.rounds_started))
+            }
+            (None, false) => session.forfeit(player),
+            (None, true) => session.forfeit_die(player),
+        };
+        match next {
+            Ok(GameSession::NewRound(game)) => self.start_round(game).map(GameSession::from),
+            Ok(session) => Ok(session),
+            Err(rejected) => Err(ReplayError::Action {
+                index,
+                source: rejected.error,
+            }),
+        }
+    }
+
+    fn step(&mut self) -> Option<Result<GameSession, ReplayError>> {
+        let Some(session) = self.session.take() else {
+            if self.next_index != 0 {
+                return None;
+            }
+            let game = match self.log.seed {
+                Some(seed) => Game::new_with_rng(
+                    self.log.players.clone(),
+                    self.log.config,
+                    &mut round_rng(seed, 0),
+                ),
+                None => Game::new(self.log.players.clone(), self.log.config),
+            };
+            return Some(self.start_round(game).map(GameSession::from));
+        };
+        // clocks and timeouts don't change the game by themselves, so skip to the next event that
+        // does
+        let (index, event) = loop {
+            let Some((index, event)) = self.next_event() else {
+                // a timeout is always recorded along with what was done for it
+                return self.after_timeout.take().map(|_| {
+                    Err(ReplayError::WrongTimeout {
+                        index: self.log.events.len(),
+                    })
+                });
+            };
+            if !matches!(event, LogEvent::Clock { .. }) {
+                if let Some(expected) = self.after_timeout.take() {
+                    if *event != expected {
+                        return Some(Err(ReplayError::WrongTimeout { index }));
+                    }
+                }
+            }
+            match event {
+                LogEvent::Clock { .. } => {}
+                LogEvent::TimedOut { player, action } => {
+                    match session.current_player() {
+                        Some(expected) if expected != player => {
+                            return Some(Err(ReplayError::WrongPlayer {
+                                index,
+                                expected: expected.clone(),
+                                actual: player.clone(),
+                            }))
+                        }
+                        Some(_) => {}
+                        None => {
+                            return Some(Err(ReplayError::Action {
+                                index,
+                                source: ActionError::GameOver,
+                            }))
+                        }
+                    }
+                    self.after_timeout = Some(
+                        action
+                            .outcome(&session, player.clone())
+                            .expect("An unfinished game should have a legal action"),
+                    );
+                }
+                _ => break (index, event),
+            }
+        };
+        let (player, action) = match event {
+            LogEvent::RoundStarted { .. } => {
+                return Some(Err(ReplayError::UnexpectedRoundStart { index }))
+            }
+            LogEvent::Undone { player, bet } => {
+                return Some(Self::undo(session, index, player, *bet))
+            }
+            LogEvent::Acted { player, action } => (player, action),
+            LogEvent::Forfeited { player } | LogEvent::ForfeitedDie { player } => {
+                return Some(self.forfeit(session, index, event, player))
+            }
+            LogEvent::TimedOut { .. } | LogEvent::Clock { .. } => {
+                unreachable!("Timeouts and clocks should have been skipped")
+            }
+        };
+        let (player, action) = (player.clone(), *action);
+        match session.current_player() {
+            Some(expected) if *expected != player => {
+                return Some(Err(ReplayError::WrongPlayer {
+                    index,
+                    expected: expected.clone(),
+                    actual: player,
+                }))
+            }
+            _ => {}
+        }
+        let next = match self.log.seed {
+            Some(seed) => session.act_with_rng(action, &mut round_rng(seed, self.rounds_started)),
+            None => session.act(action),
+        };
+        Some(match next {
+            Ok(GameSession::NewRound(game)) if action == Action::CallFluff => {
+                self.start_round(game).map(GameSession::from)
+            }
+            Ok(session) => Ok(session),
+            Err(rejected) => Err(ReplayError::Action {
+                index,
+                source: rejected.error,
+            }),
+        })
+    }
+}
+
+impl Iterator for Steps<'_> {
+    type Item = Result<GameSession, ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let step = self.step()?;
+        match &step {
+            Ok(session) => self.session = Some(session.clone()),
+            Err(_) => self.failed = true,
+        }
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use rand::Rng;
+
+    use super::*;
+    use crate::bet::Bet;
+
+    fn play_randomly(mut log: GameLog, mut session: GameSession) -> (GameLog, GameSession) {
+        let mut rng = StdRng::seed_from_u64(0xF1_0FF);
+        while !session.is_over() {
+            let prev_bet = match &session {
+                GameSession::Betting(g) => Some(g.curr_round().state_data().prev_bet),
+                _ => None,
+            };
+            let action = match prev_bet {
+                Some(_) if rng.gen_bool(0.3) => Action::CallFluff,
+                Some(Bet { count, roll }) if roll < log.config().max_roll() => {
+                    Action::Raise(Bet::new(count, roll.saturating_add(1)))
+                }
+                Some(Bet { count, .. }) => Action::Raise(Bet::new(
+                    count.saturating_add(1),
+                    NonZeroUsize::new(2).unwrap(),
+                )),
+                None => Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN)),
+            };
+            session = log.act(session, action).unwrap();
+        }
+        (log, session)
+    }
+
+    fn players() -> [Player; 3] {
+        [
+            Player::new("Unga"),
+            Player::new("Bunga"),
+            Player::new("Ooga"),
+        ]
+    }
+
+    #[test]
+    fn test_replay() {
+        for (log, session) in [
+            GameLog::start(players(), GameConfig::default()),
+            GameLog::start_seeded(players(), GameConfig::default(), 42),
+        ] {
+            let (log, session) = play_randomly(log, session);
+            let steps = steps(&log).collect::<Result<Vec<_>, _>>().unwrap();
+            let actions = log
+                .events()
+                .iter()
+                .filter(|x| matches!(x, LogEvent::Acted { .. }))
+                .count();
+            assert_eq!(steps.len(), actions + 1);
+            assert_eq!(steps.last(), Some(&session));
+            assert_eq!(replay(&log).unwrap(), session);
+            let log_de: GameLog =
+                serde_json::from_str(&serde_json::to_string(&log).unwrap()).unwrap();
+            assert_eq!(replay(&log_de).unwrap(), session);
+            assert_eq!(log_de, log);
+        }
+    }
+
+    #[test]
+    fn test_replay_many_clocks() {
+        let (mut log, session) = GameLog::start(players(), GameConfig::default());
+        let player = session.current_player().unwrap().clone();
+        for _ in 0..1_000_000 {
+            log.record_clock(player.clone(), Duration::ZERO);
+        }
+        assert_eq!(replay(&log).unwrap(), session);
+    }
+
+    #[test]
+    fn test_round_rng_differs_between_seeds() {
+        let rolls = |rng: &mut StdRng| (0..8).map(|_| rng.gen::<u64>()).collect::<Vec<_>>();
+        for seed in 0..16 {
+            assert_ne!(
+                rolls(&mut round_rng(seed, 1)),
+                rolls(&mut round_rng(seed + 1, 0))
+            );
+        }
+    }
+
+    #[test]
+    fn test_replay_detects_tampering() {
+        let (log, session) = GameLog::start_seeded(players(), GameConfig::default(), 42);
+        let (mut log, _) = play_randomly(log, session);
+        let LogEvent::RoundStarted { rolls, .. } = &mut log.events[0] else {
+            panic!("Log should start with a round");
+        };
+        let (_, first_rolls) = rolls.first_mut().unwrap();
+        let mut tampered = first_rolls.to_vec();
+        tampered[0] = NonZeroUsize::new(tampered[0].get() % 6 + 1).unwrap();
+        *first_rolls = tampered.into();
+        assert_eq!(replay(&log), Err(ReplayError::SeedMismatch { index: 0 }));
+    }
+
+    #[test]
+    fn test_replay_checks_timeouts() {
+        let (mut log, session) = GameLog::start_seeded(players(), GameConfig::default(), 3);
+        let bet = Bet::new(NonZeroUsize::MIN, NonZeroUsize::new(3).unwrap());
+        let session = log.act(session, Action::Raise(bet)).unwrap();
+        let player = session.current_player().unwrap().clone();
+        let session = log.time_out(session, TimeoutAction::CallFluff).unwrap();
+        assert_eq!(replay(&log), Ok(session));
+
+        // whatever follows the timeout has to be the call it recorded
+        let index = log
+            .events
+            .iter()
+            .rposition(|x| matches!(x, LogEvent::TimedOut { .. }))
+            .unwrap()
+            + 1;
+        let mut tampered = log.clone();
+        tampered.events[index] = LogEvent::Acted {
+            player,
+            action: Action::Raise(Bet::new(NonZeroUsize::new(4).unwrap(), NonZeroUsize::MIN)),
+        };
+        assert_eq!(replay(&tampered), Err(ReplayError::WrongTimeout { index }));
+        let mut truncated = log;
+        truncated.events.truncate(index);
+        assert_eq!(replay(&truncated), Err(ReplayError::WrongTimeout { index }));
+    }
+
+    #[test]
+    fn test_replay_undo() {
+        let config = GameConfig::default().with_undo_policy(crate::game::UndoPolicy::Always);
+        let (mut log, session) = GameLog::start_seeded(players(), config, 7);
+        let bet = Bet::new(NonZeroUsize::MIN, NonZeroUsize::new(3).unwrap());
+        let session = log.act(session, Action::Raise(bet)).unwrap();
+        let session = log.undo(session).unwrap();
+        assert_eq!(
+            log.events().last(),
+            Some(&LogEvent::Undone {
+                player: session.current_player().unwrap().clone(),
+                bet
+            })
+        );
+        assert!(matches!(session, GameSession::NewRound(_)));
+        let (log, session) = play_randomly(log, session);
+        assert_eq!(replay(&log).unwrap(), session);
+    }
+}
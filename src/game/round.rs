@@ -3,7 +3,7 @@ use std::num::NonZeroUsize;
 use indexmap::IndexMap;
 use rand::{
     distributions::{Distribution, Uniform},
-    thread_rng,
+    thread_rng, Rng,
 };
 
 use crate::{
@@ -11,7 +11,7 @@ use crate::{
     game::{state::UnfinishedRound, Betting, Called, NewRound, PlayerRef, RoundState},
 };
 
-type RollSet = std::sync::Arc<[NonZeroUsize]>;
+pub type RollSet = std::sync::Arc<[NonZeroUsize]>;
 
 #[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
 pub struct PlayerRolls {
@@ -46,24 +46,73 @@ impl<State: RoundState> Round<State> {
     pub fn state_data(&self) -> &State {
         &self.state_data
     }
-}
 
-impl<State: UnfinishedRound> Round<State> {
-    fn init_next_state(&self, turn: &Turn) -> Betting {
+    #[must_use]
+    pub fn players_rolls(&self) -> &IndexMap<PlayerRef, RollSet> {
+        &self.players_rolls
+    }
+
+    #[must_use]
+    pub fn turns(&self) -> &Vec<Turn> {
+        &self.turns
+    }
+
+    #[must_use]
+    pub fn last_bet(&self) -> Option<Bet> {
+        self.turns.last().map(|x| x.bet)
+    }
+
+    /// Total number of dice rolled this round
+    #[must_use]
+    pub fn dice_in_play(&self) -> usize {
+        self.players_rolls.values().map(|x| x.len()).sum()
+    }
+
+    fn player_after(&self, player: &PlayerRef) -> PlayerRolls {
         let next_player_index = (self
             .players_rolls
-            .get_index_of(&turn.player)
+            .get_index_of(player)
             .expect("Current player should be in player rolls")
             + 1)
             % self.players_rolls.len();
-        let next_player_rolls = self
-            .players_rolls
+        self.players_rolls
             .get_index(next_player_index)
             .expect("Next player index should be in player rolls")
-            .into();
+            .into()
+    }
+}
+
+impl<State: UnfinishedRound> Round<State> {
+    #[must_use]
+    pub fn current_player(&self) -> &PlayerRef {
+        &self.state_data.curr_player_rolls().player
+    }
+
+    /// Whoever gets the turn after the current player, if the current player raises
+    #[must_use]
+    pub fn next_player(&self) -> PlayerRef {
+        self.player_after(self.current_player()).player
+    }
+
+    /// Replaces the dice `player` rolled this round, keeping whose turn it is
+    #[cfg(feature = "sealed")]
+    pub(crate) fn replace_rolls(&mut self, player: &PlayerRef, rolls: RollSet) {
+        *self
+            .players_rolls
+            .get_mut(player)
+            .expect("Player should have rolled this round") = rolls.clone();
+        let current = self.state_data.curr_player_rolls_mut();
+        if current.player == *player {
+            current.rolls = rolls;
+        }
+    }
+
+    fn init_next_state(&self, turn: &Turn) -> Betting {
+        let next_player_rolls = self.player_after(&turn.player);
         Betting {
             curr_player_rolls: next_player_rolls,
             prev_bet: turn.bet,
+            after_undo: false,
         }
     }
 }
@@ -76,6 +125,15 @@ impl Round<NewRound> {
     pub fn new(
         player_dice_counts: &IndexMap<PlayerRef, usize>,
         max_roll: NonZeroUsize,
+    ) -> Round<NewRound> {
+        Self::new_with_rng(player_dice_counts, max_roll, &mut thread_rng())
+    }
+
+    #[must_use]
+    pub fn new_with_rng<R: Rng + ?Sized>(
+        player_dice_counts: &IndexMap<PlayerRef, usize>,
+        max_roll: NonZeroUsize,
+        rng: &mut R,
     ) -> Round<NewRound> {
         let dist = Uniform::new_inclusive(1, max_roll.get());
         let rolls: IndexMap<PlayerRef, RollSet> = player_dice_counts
@@ -84,13 +142,19 @@ impl Round<NewRound> {
             .map(|(player_ref, dice_count)| {
                 (
                     player_ref.clone(),
-                    dist.sample_iter(thread_rng())
-                        .take(*dice_count)
+                    (0..*dice_count)
+                        .map(|_| dist.sample(rng))
                         .filter_map(NonZeroUsize::new)
                         .collect(),
                 )
             })
             .collect();
+        Self::from_rolls(rolls)
+    }
+
+    /// Builds a round out of already rolled dice, with the first player in `rolls` going first
+    #[must_use]
+    pub fn from_rolls(rolls: IndexMap<PlayerRef, RollSet>) -> Round<NewRound> {
         let first_player_rolls = rolls
             .first()
             .expect("Players rolls should not be empty")
@@ -107,13 +171,19 @@ impl Round<NewRound> {
         max_roll: NonZeroUsize,
         first_player: &PlayerRef,
     ) -> Result<Round<NewRound>, FirstPlayerNotInGivenPlayers> {
-        let mut round = Self::new(player_dice_counts, max_roll);
-        round.state_data.first_player_rolls = round
+        Self::new(player_dice_counts, max_roll).with_first_player(first_player)
+    }
+
+    pub fn with_first_player(
+        mut self,
+        first_player: &PlayerRef,
+    ) -> Result<Round<NewRound>, FirstPlayerNotInGivenPlayers> {
+        self.state_data.first_player_rolls = self
             .players_rolls
             .get_key_value(first_player)
             .ok_or(FirstPlayerNotInGivenPlayers {})?
             .into();
-        Ok(round)
+        Ok(self)
     }
 
     #[must_use]
@@ -136,6 +206,12 @@ impl Round<NewRound> {
     }
 }
 
+/// The round that is left after taking back its last bet
+pub enum RoundUndo {
+    NewRound(Round<NewRound>),
+    Betting(Round<Betting>),
+}
+
 impl Round<Betting> {
     fn is_fluff(&self) -> bool {
         self.state_data
@@ -174,17 +250,37 @@ impl Round<Betting> {
             },
         }
     }
-}
 
-impl Round<Called> {
+    /// Takes back the last bet, giving the turn back to whoever made it
     #[must_use]
-    pub fn turns(&self) -> &Vec<Turn> {
-        &self.turns
-    }
-
-    #[must_use]
-    pub fn players_rolls(&self) -> &IndexMap<PlayerRef, RollSet> {
-        &self.players_rolls
+    pub fn undo(self) -> RoundUndo {
+        let mut turns = self.turns;
+        let undone = turns
+            .pop()
+            .expect("There should be past turns, otherwise this shouldn't be Betting");
+        let undone_player_rolls = self
+            .players_rolls
+            .get_key_value(&undone.player)
+            .expect("Undone player should be in player rolls")
+            .into();
+        match turns.last() {
+            None => RoundUndo::NewRound(Round {
+                players_rolls: self.players_rolls,
+                turns,
+                state_data: NewRound {
+                    first_player_rolls: undone_player_rolls,
+                },
+            }),
+            Some(prev_turn) => RoundUndo::Betting(Round {
+                state_data: Betting {
+                    curr_player_rolls: undone_player_rolls,
+                    prev_bet: prev_turn.bet,
+                    after_undo: true,
+                },
+                players_rolls: self.players_rolls,
+                turns,
+            }),
+        }
     }
 }
 
@@ -0,0 +1,250 @@
+use indexmap::IndexMap;
+use rand::{thread_rng, Rng};
+use thiserror::Error;
+
+use crate::{
+    bet::{Bet, RaiseError},
+    game::{
+        state::{Betting, Called, GameOver, InRound, NewRound},
+        FluffCallTransition, Game, GameConfig, PlayerRef, Round, UndoError, UndoTransition,
+    },
+    player::Player,
+};
+
+/// Something the current player can do on their turn
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub enum Action {
+    Raise(Bet),
+    CallFluff,
+}
+
+#[derive(Error, Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub enum ActionError {
+    #[error("Fluff can not be called before anyone has bet")]
+    NothingToCall,
+    #[error(transparent)]
+    Raise(#[from] RaiseError),
+    #[error(transparent)]
+    Undo(#[from] UndoError),
+    #[error("The game is already over")]
+    GameOver,
+    #[error("Only a player with dice left can forfeit")]
+    NoDiceLeft,
+    #[error("The turn's time already ran out")]
+    OutOfTime,
+}
+
+/// An action that could not be applied, along with the untouched session it was applied to
+#[derive(Error, Debug)]
+#[error("{error}")]
+pub struct RejectedAction {
+    pub session: Box<GameSession>,
+    pub error: ActionError,
+}
+
+/// A [`Game`] in any of its states, for when the state is only known at runtime
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub enum GameSession {
+    NewRound(Game<InRound<NewRound>>),
+    Betting(Game<InRound<Betting>>),
+    GameOver(Game<GameOver>),
+}
+
+impl From<Game<InRound<NewRound>>> for GameSession {
+    fn from(value: Game<InRound<NewRound>>) -> Self {
+        Self::NewRound(value)
+    }
+}
+
+impl From<Game<InRound<Betting>>> for GameSession {
+    fn from(value: Game<InRound<Betting>>) -> Self {
+        Self::Betting(value)
+    }
+}
+
+impl From<Game<GameOver>> for GameSession {
+    fn from(value: Game<GameOver>) -> Self {
+        Self::GameOver(value)
+    }
+}
+
+impl From<FluffCallTransition> for GameSession {
+    fn from(value: FluffCallTransition) -> Self {
+        match value {
+            FluffCallTransition::NextRound(g) => g.into(),
+            FluffCallTransition::GameOver(g) => g.into(),
+        }
+    }
+}
+
+impl From<UndoTransition> for GameSession {
+    fn from(value: UndoTransition) -> Self {
+        match value {
+            UndoTransition::NewRound(g) => g.into(),
+            UndoTransition::Betting(g) => g.into(),
+        }
+    }
+}
+
+impl GameSession {
+    #[must_use]
+    pub const fn is_over(&self) -> bool {
+        matches!(self, Self::GameOver(_))
+    }
+
+    /// The player whose turn it is, or [`None`] if the game is over
+    #[must_use]
+    pub fn current_player(&self) -> Option<&PlayerRef> {
+        match self {
+            Self::NewRound(g) => Some(g.current_player()),
+            Self::Betting(g) => Some(g.current_player()),
+            Self::GameOver(_) => None,
+        }
+    }
+
+    /// The bet to beat in the current round, or [`None`] if nobody has bet yet or the game is over
+    #[must_use]
+    pub fn last_bet(&self) -> Option<Bet> {
+        match self {
+            Self::NewRound(_) | Self::GameOver(_) => None,
+            Self::Betting(g) => g.last_bet(),
+        }
+    }
+
+    #[must_use]
+    pub fn player_dice_counts(&self) -> &IndexMap<PlayerRef, usize> {
+        match self {
+            Self::NewRound(g) => g.player_dice_counts(),
+            Self::Betting(g) => g.player_dice_counts(),
+            Self::GameOver(g) => g.player_dice_counts(),
+        }
+    }
+
+    #[must_use]
+    pub fn round_history(&self) -> &Vec<Round<Called>> {
+        match self {
+            Self::NewRound(g) => g.round_history(),
+            Self::Betting(g) => g.round_history(),
+            Self::GameOver(g) => g.round_history(),
+        }
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &GameConfig {
+        match self {
+            Self::NewRound(g) => g.config(),
+            Self::Betting(g) => g.config(),
+            Self::GameOver(g) => g.config(),
+        }
+    }
+
+    /// Checks whether `action` could be applied without actually applying it
+    pub fn check(&self, action: &Action) -> Result<(), ActionError> {
+        match (self, action) {
+            (Self::GameOver(_), _) => Err(ActionError::GameOver),
+            (Self::NewRound(_), Action::CallFluff) => Err(ActionError::NothingToCall),
+            (Self::NewRound(_), Action::Raise(_)) | (Self::Betting(_), Action::CallFluff) => Ok(()),
+            (Self::Betting(g), Action::Raise(bet)) => {
+                Ok(bet.is_raised_from(&g.curr_round().state_data().prev_bet)?)
+            }
+        }
+    }
+
+    pub fn act(self, action: Action) -> Result<Self, RejectedAction> {
+        self.act_with_rng(action, &mut thread_rng())
+    }
+
+    /// Applies `action` for the current player, using `rng` to roll the next round if one starts
+    pub fn act_with_rng<R: Rng + ?Sized>(
+        self,
+        action: Action,
+        rng: &mut R,
+    ) -> Result<Self, RejectedAction> {
+        if let Err(error) = self.check(&action) {
+            return Err(RejectedAction {
+                session: Box::new(self),
+                error,
+            });
+        }
+        Ok(match (self, action) {
+            (Self::NewRound(g), Action::Raise(bet)) => g.raise_bet(bet).into(),
+            (Self::Betting(mut g), Action::Raise(bet)) => {
+                g.raise_bet(bet)
+                    .expect("Raise should have already been checked");
+                g.into()
+            }
+            (Self::Betting(g), Action::CallFluff) => g.call_fluff_with_rng(rng).into(),
+            _ => unreachable!("Action should have already been checked"),
+        })
+    }
+
+    pub fn forfeit(self, player: &Player) -> Result<Self, RejectedAction> {
+        self.forfeit_with_rng(player, &mut thread_rng())
+    }
+
+    /// Knocks `player` out of the game, see [`Game::forfeit_with_rng`]
+    pub fn forfeit_with_rng<R: Rng + ?Sized>(
+        self,
+        player: &Player,
+        rng: &mut R,
+    ) -> Result<Self, RejectedAction> {
+        self.take_dice(player, usize::MAX, rng)
+    }
+
+    pub fn forfeit_die(self, player: &Player) -> Result<Self, RejectedAction> {
+        self.forfeit_die_with_rng(player, &mut thread_rng())
+    }
+
+    /// Takes a die away from `player`, see [`Game::forfeit_die_with_rng`]
+    pub fn forfeit_die_with_rng<R: Rng + ?Sized>(
+        self,
+        player: &Player,
+        rng: &mut R,
+    ) -> Result<Self, RejectedAction> {
+        self.take_dice(player, 1, rng)
+    }
+
+    fn take_dice<R: Rng + ?Sized>(
+        self,
+        player: &Player,
+        dice: usize,
+        rng: &mut R,
+    ) -> Result<Self, RejectedAction> {
+        let error = if self.is_over() {
+            ActionError::GameOver
+        } else if self.player_dice_counts().get(player).copied().unwrap_or(0) == 0 {
+            ActionError::NoDiceLeft
+        } else {
+            return Ok(match self {
+                Self::NewRound(g) => g.take_dice(player, dice, rng).into(),
+                Self::Betting(g) => g.take_dice(player, dice, rng).into(),
+                Self::GameOver(_) => unreachable!("A finished game was already refused"),
+            });
+        };
+        Err(RejectedAction {
+            session: Box::new(self),
+            error,
+        })
+    }
+
+    /// Takes back the last bet of the current round, if the game's undo policy allows it
+    pub fn undo(self) -> Result<Self, RejectedAction> {
+        let error = match self {
+            Self::Betting(g) => match g.undo() {
+                Ok(transition) => return Ok(transition.into()),
+                Err(refused) => {
+                    return Err(RejectedAction {
+                        session: Box::new((*refused.game).into()),
+                        error: refused.error.into(),
+                    })
+                }
+            },
+            Self::NewRound(_) => UndoError::NothingToUndo.into(),
+            Self::GameOver(_) => ActionError::GameOver,
+        };
+        Err(RejectedAction {
+            session: Box::new(self),
+            error,
+        })
+    }
+}
@@ -31,6 +31,9 @@ pub struct NewRound {
 pub struct Betting {
     pub curr_player_rolls: PlayerRolls,
     pub prev_bet: Bet,
+    /// Whether this turn was reached by taking back a bet, rather than by a new one
+    #[serde(default)]
+    pub after_undo: bool,
 }
 
 #[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
@@ -70,8 +73,29 @@ impl RoundState for Betting {}
 
 impl RoundState for Called {}
 
-pub trait UnfinishedRound: RoundState {}
+pub trait UnfinishedRound: RoundState {
+    /// Rolls of the player whose turn it is
+    fn curr_player_rolls(&self) -> &PlayerRolls;
 
-impl UnfinishedRound for NewRound {}
+    fn curr_player_rolls_mut(&mut self) -> &mut PlayerRolls;
+}
+
+impl UnfinishedRound for NewRound {
+    fn curr_player_rolls(&self) -> &PlayerRolls {
+        &self.first_player_rolls
+    }
+
+    fn curr_player_rolls_mut(&mut self) -> &mut PlayerRolls {
+        &mut self.first_player_rolls
+    }
+}
+
+impl UnfinishedRound for Betting {
+    fn curr_player_rolls(&self) -> &PlayerRolls {
+        &self.curr_player_rolls
+    }
 
-impl UnfinishedRound for Betting {}
+    fn curr_player_rolls_mut(&mut self) -> &mut PlayerRolls {
+        &mut self.curr_player_rolls
+    }
+}
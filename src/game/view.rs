@@ -0,0 +1,131 @@
+use std::{num::NonZeroUsize, time::Duration};
+
+use indexmap::IndexMap;
+
+use crate::{
+    bet::Bet,
+    game::{
+        round::{RollSet, Turn},
+        session::{Action, GameSession},
+        state::Called,
+        GameConfig, PlayerRef, Round,
+    },
+};
+
+/// Everything one player is allowed to know about a game, which leaves out the other players'
+/// rolls in the current round
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct PlayerView {
+    pub viewer: PlayerRef,
+    /// The viewer's rolls in the current round, or [`None`] if they have no dice in it
+    pub own_rolls: Option<RollSet>,
+    pub player_dice_counts: IndexMap<PlayerRef, usize>,
+    pub config: GameConfig,
+    /// Number of the current round, or of the last round if the game is over
+    pub round_number: usize,
+    /// Bids made so far in the current round
+    pub turns: Vec<Turn>,
+    pub current_player: Option<PlayerRef>,
+    pub round_history: Vec<Round<Called>>,
+    pub winner: Option<PlayerRef>,
+    /// Time every player has left, if the game is played against the clock
+    #[serde(default)]
+    pub clocks: Option<IndexMap<PlayerRef, Duration>>,
+}
+
+impl PlayerView {
+    #[must_use]
+    pub fn is_my_turn(&self) -> bool {
+        self.current_player.as_ref() == Some(&self.viewer)
+    }
+
+    #[must_use]
+    pub fn last_bet(&self) -> Option<Bet> {
+        self.turns.last().map(|x| x.bet)
+    }
+
+    #[must_use]
+    pub fn own_rolls(&self) -> &[NonZeroUsize] {
+        self.own_rolls.as_deref().unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn total_dice_in_play(&self) -> usize {
+        self.player_dice_counts.values().sum()
+    }
+
+    /// Dice in play that the viewer can not see
+    #[must_use]
+    pub fn unknown_dice(&self) -> usize {
+        self.total_dice_in_play() - self.own_rolls().len()
+    }
+
+    /// Every raise that could be made now without betting on more dice than are in play or rolls
+    /// higher than the max roll, as well as calling fluff if there is a bet to call
+    ///
+    /// If no raise fits within the dice in play, the smallest raise is still included.
+    #[must_use]
+    pub fn legal_actions(&self) -> Vec<Action> {
+        if self.current_player.is_none() {
+            return Vec::new();
+        }
+        let max_roll = self.config.max_roll();
+        let max_count = self.total_dice_in_play();
+        let mut actions = (1..=max_count)
+            .flat_map(|count| (1..=max_roll.get()).map(move |roll| (count, roll)))
+            .filter_map(|(count, roll)| {
+                Some(Bet::new(
+                    NonZeroUsize::new(count)?,
+                    NonZeroUsize::new(roll)?,
+                ))
+            })
+            .filter(|bet| {
+                self.last_bet()
+                    .is_none_or(|prev| bet.is_raised_from(&prev).is_ok())
+            })
+            .map(Action::Raise)
+            .collect::<Vec<_>>();
+        if let Some(prev) = self.last_bet() {
+            if actions.is_empty() {
+                actions.push(Action::Raise(prev.min_raise(max_roll)));
+            }
+            actions.push(Action::CallFluff);
+        }
+        actions
+    }
+}
+
+impl GameSession {
+    /// What `viewer` is allowed to know about this game
+    #[must_use]
+    pub fn view_for(&self, viewer: &PlayerRef) -> PlayerView {
+        let (own_rolls, turns, round_number) = match self {
+            Self::NewRound(g) => (
+                g.curr_round().players_rolls().get(viewer).cloned(),
+                g.curr_round().turns().clone(),
+                g.round_number(),
+            ),
+            Self::Betting(g) => (
+                g.curr_round().players_rolls().get(viewer).cloned(),
+                g.curr_round().turns().clone(),
+                g.round_number(),
+            ),
+            Self::GameOver(g) => (None, Vec::new(), g.round_history().len()),
+        };
+        PlayerView {
+            viewer: viewer.clone(),
+            own_rolls,
+            player_dice_counts: self.player_dice_counts().clone(),
+            config: *self.config(),
+            round_number,
+            turns,
+            current_player: self.current_player().cloned(),
+            round_history: self.round_history().clone(),
+            winner: match self {
+                Self::GameOver(g) => Some(g.winner().clone()),
+                _ => None,
+            },
+            clocks: None,
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt::{Display, Formatter};
+
+use crate::{
+    bet::Bet,
+    game::{session::Action, view::PlayerView},
+    strategy::bots::{bet_probabilities, probability_of},
+};
+
+/// How much a hint gives away
+#[derive(Debug, Serialize, Deserialize, Default, Eq, PartialEq, Copy, Clone)]
+pub enum Difficulty {
+    /// The probability of the current bet, the safest raise and a recommended action
+    #[default]
+    Easy,
+    /// The probability of the current bet and the safest raise, without a recommendation
+    Medium,
+    /// Only the probability of the current bet
+    Hard,
+}
+
+/// An action worth taking, along with why
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Recommendation {
+    pub action: Action,
+    pub reason: String,
+}
+
+/// Advice for the player whose turn it is, based only on what they can see
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Hint {
+    /// Probability that the current bet is true, or [`None`] if nobody has bet yet
+    pub bet_probability: Option<f64>,
+    /// The legal raise most likely to be true, with its probability
+    pub safest_raise: Option<(Bet, f64)>,
+    pub recommendation: Option<Recommendation>,
+}
+
+impl Hint {
+    /// A hint for `view.viewer`, or [`None`] if it is not their turn
+    #[must_use]
+    pub fn for_view(view: &PlayerView, difficulty: Difficulty) -> Option<Self> {
+        if !view.is_my_turn() {
+            return None;
+        }
+        let probabilities = bet_probabilities(view);
+        let bet_probability = view
+            .last_bet()
+            .map(|bet| probability_of(&probabilities, &bet));
+        let safest_raise = view
+            .legal_actions()
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Raise(bet) => Some((bet, probability_of(&probabilities, &bet))),
+                Action::CallFluff => None,
+            })
+            .max_by(|(a, p_a), (b, p_b)| p_a.total_cmp(p_b).then(b.cmp(a)));
+        let recommendation = recommend(view.last_bet().zip(bet_probability), safest_raise);
+        Some(match difficulty {
+            Difficulty::Easy => Self {
+                bet_probability,
+                safest_raise,
+                recommendation,
+            },
+            Difficulty::Medium => Self {
+                bet_probability,
+                safest_raise,
+                recommendation: None,
+            },
+            Difficulty::Hard => Self {
+                bet_probability,
+                safest_raise: None,
+                recommendation: None,
+            },
+        })
+    }
+}
+
+/// Calls fluff when the current bet is more likely false than the safest raise is true
+fn recommend(
+    current: Option<(Bet, f64)>,
+    safest_raise: Option<(Bet, f64)>,
+) -> Option<Recommendation> {
+    let percent = |p: f64| (p * 100.0).round();
+    match (current, safest_raise) {
+        (Some((bet, p)), Some((_, q))) if 1.0 - p > q => Some(Recommendation {
+            action: Action::CallFluff,
+            reason: format!(
+                "{bet} is only {}% likely to be true, and no raise is safer than calling",
+                percent(p)
+            ),
+        }),
+        (Some((bet, p)), None) => Some(Recommendation {
+            action: Action::CallFluff,
+            reason: format!(
+                "{bet} is {}% likely to be true, but there is no raise left",
+                percent(p)
+            ),
+        }),
+        (_, Some((raise, q))) => Some(Recommendation {
+            action: Action::Raise(raise),
+            reason: format!(
+                "{raise} is the safest raise, {}% likely to be true",
+                percent(q)
+            ),
+        }),
+        (None, None) => None,
+    }
+}
+
+impl Display for Hint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(p) = self.bet_probability {
+            writeln!(f, "The current bet is {:.1}% likely to be true", p * 100.0)?;
+        }
+        if let Some((bet, p)) = self.safest_raise {
+            writeln!(
+                f,
+                "The safest raise is {bet}, {:.1}% likely to be true",
+                p * 100.0
+            )?;
+        }
+        if let Some(Recommendation { action, reason }) = &self.recommendation {
+            match action {
+                Action::Raise(bet) => writeln!(f, "Recommended: bet {bet}, since {reason}")?,
+                Action::CallFluff => writeln!(f, "Recommended: call fluff, since {reason}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{
+        game::{round::RollSet, session::GameSession, Game, GameConfig, PlayerRef},
+        player::Player,
+    };
+
+    #[test]
+    fn test_hint() {
+        let config = GameConfig::new(NonZeroUsize::new(2).unwrap(), NonZeroUsize::new(6).unwrap());
+        let mut game = Game::new([Player::new("A"), Player::new("B")], config);
+        let rolls = |x: [usize; 2]| -> RollSet { x.map(|x| NonZeroUsize::new(x).unwrap()).into() };
+        game.set_rolls(
+            [("A", [6, 6]), ("B", [2, 3])]
+                .map(|(player, x)| (PlayerRef::new(Player::new(player)), rolls(x)))
+                .into_iter()
+                .collect(),
+        )
+        .unwrap();
+        let bet = |count, roll| {
+            Bet::new(
+                NonZeroUsize::new(count).unwrap(),
+                NonZeroUsize::new(roll).unwrap(),
+            )
+        };
+        let session = GameSession::from(game);
+        let a = PlayerRef::new(Player::new("A"));
+        let b = PlayerRef::new(Player::new("B"));
+        let hint = Hint::for_view(&session.view_for(&a), Difficulty::Easy).unwrap();
+        assert_eq!(hint.bet_probability, None);
+        // A holds two 6s, so any bet on at most two 6s is certain
+        assert_eq!(hint.safest_raise.map(|x| x.1), Some(1.0));
+        assert!(Hint::for_view(&session.view_for(&b), Difficulty::Easy).is_none());
+
+        // B has no 5s or 1s, so 4 of them is impossible
+        let session = session.act(Action::Raise(bet(4, 5))).unwrap();
+        let hint = Hint::for_view(&session.view_for(&b), Difficulty::Easy).unwrap();
+        assert_eq!(hint.bet_probability, Some(0.0));
+        assert_eq!(
+            hint.recommendation.map(|x| x.action),
+            Some(Action::CallFluff)
+        );
+        let hard = Hint::for_view(&session.view_for(&b), Difficulty::Hard).unwrap();
+        assert_eq!((hard.safest_raise, hard.recommendation), (None, None));
+    }
+}
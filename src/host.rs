@@ -0,0 +1,655 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Formatter},
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+use rand::Rng;
+use thiserror::Error;
+
+use crate::{
+    game::{
+        session::{Action, ActionError, GameSession, RejectedAction},
+        view::PlayerView,
+        Game, GameConfig, PlayerRef,
+    },
+    player::Player,
+    simulation::StrategyFactory,
+};
+
+pub type GameId = u64;
+
+/// Position of an event in its game's history, starting from 1
+pub type Seq = u64;
+
+/// Something that happened in a hosted game, which every client of the game is told about
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GameEvent {
+    Acted {
+        player: PlayerRef,
+        action: Action,
+    },
+    Disconnected {
+        player: PlayerRef,
+    },
+    Reconnected {
+        player: PlayerRef,
+    },
+    /// The player's grace period ran out, and a bot plays for them until they reconnect
+    AutoPlaying {
+        player: PlayerRef,
+    },
+    Forfeited {
+        player: PlayerRef,
+    },
+    GameOver {
+        winner: PlayerRef,
+    },
+}
+
+/// What happens to a player who stays disconnected for longer than the grace period
+#[derive(Clone)]
+pub enum OnExpiry {
+    Forfeit,
+    /// Plays their turns with bots from the factory until they reconnect, each seeded with the
+    /// sequence number of the turn's event
+    AutoPlay(StrategyFactory),
+}
+
+impl Debug for OnExpiry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Forfeit => f.write_str("Forfeit"),
+            Self::AutoPlay(_) => f.write_str("AutoPlay(..)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GracePolicy {
+    pub grace: Duration,
+    pub on_expiry: OnExpiry,
+}
+
+impl Default for GracePolicy {
+    fn default() -> Self {
+        Self {
+            grace: Duration::from_secs(60),
+            on_expiry: OnExpiry::Forfeit,
+        }
+    }
+}
+
+/// Whether a seat's player is there to play it
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Presence {
+    /// Nobody has connected to the seat yet
+    Waiting,
+    Connected,
+    Disconnected {
+        since: Instant,
+    },
+    AutoPlayed,
+    Forfeited,
+}
+
+/// A client that connected or resumed, along with the events it has not seen yet
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Connection {
+    pub game: GameId,
+    pub player: PlayerRef,
+    /// Resumes the seat after a disconnect, and is replaced every time it is used
+    pub reconnect_token: String,
+    pub missed: Vec<(Seq, GameEvent)>,
+}
+
+#[derive(Error, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub enum HostError {
+    #[error("No seat has this token")]
+    UnknownToken,
+    #[error("No game has id {0}")]
+    UnknownGame(GameId),
+    #[error("It is not {0}'s turn")]
+    NotYourTurn(PlayerRef),
+    #[error("{0} forfeited the game")]
+    Forfeited(PlayerRef),
+    #[error(transparent)]
+    Action(#[from] ActionError),
+}
+
+/// A secret that proves a client may play a seat
+pub(crate) fn new_token(rng: &mut impl Rng) -> String {
+    format!("{:016x}{:016x}", rng.gen::<u64>(), rng.gen::<u64>())
+}
+
+/// A game along with who may play which seat
+#[derive(Debug)]
+pub struct HostedGame {
+    /// Only [`None`] while an action is being applied
+    session: Option<GameSession>,
+    seats: IndexMap<PlayerRef, String>,
+    presence: IndexMap<PlayerRef, Presence>,
+    /// Latest event every player acknowledged seeing
+    acked: IndexMap<PlayerRef, Seq>,
+    events: Vec<GameEvent>,
+}
+
+impl HostedGame {
+    #[must_use]
+    pub fn session(&self) -> &GameSession {
+        self.session
+            .as_ref()
+            .expect("Session should only be taken while acting")
+    }
+
+    /// Players in seating order
+    pub fn players(&self) -> impl Iterator<Item = &PlayerRef> {
+        self.seats.keys()
+    }
+
+    /// Token of `player`'s seat, which only they should be given
+    #[must_use]
+    pub fn token(&self, player: &PlayerRef) -> Option<&str> {
+        self.seats.get(player).map(String::as_str)
+    }
+
+    #[must_use]
+    pub fn presence(&self, player: &PlayerRef) -> Option<Presence> {
+        self.presence.get(player).copied()
+    }
+
+    /// Sequence number of the latest event, or 0 if nothing has happened yet
+    #[must_use]
+    pub fn last_seq(&self) -> Seq {
+        self.events.len() as Seq
+    }
+
+    /// Every event after `seq`, with their sequence numbers
+    pub fn events_since(&self, seq: Seq) -> impl Iterator<Item = (Seq, &GameEvent)> {
+        (seq + 1..).zip(
+            self.events
+                .iter()
+                .skip(usize::try_from(seq).unwrap_or(usize::MAX)),
+        )
+    }
+
+    /// Records `event`, returning it for the clients to be told about
+    fn push(&mut self, event: GameEvent) -> GameEvent {
+        self.events.push(event.clone());
+        event
+    }
+
+    /// Applies `f` to the session, returning the winner if it ended the game
+    fn transition(
+        &mut self,
+        f: impl FnOnce(GameSession) -> Result<GameSession, RejectedAction>,
+    ) -> Result<Option<PlayerRef>, ActionError> {
+        let session = self
+            .session
+            .take()
+            .expect("Session should only be taken while acting");
+        let (session, result) = match f(session) {
+            Ok(session) => {
+                let winner = match &session {
+                    GameSession::GameOver(game) => Some(game.winner().clone()),
+                    _ => None,
+                };
+                (session, Ok(winner))
+            }
+            Err(rejected) => (*rejected.session, Err(rejected.error)),
+        };
+        self.session = Some(session);
+        result
+    }
+
+    fn act(
+        &mut self,
+        player: &PlayerRef,
+        action: Action,
+        policy: &GracePolicy,
+    ) -> Result<Vec<GameEvent>, HostError> {
+        if self.session().current_player() != Some(player) {
+            return Err(HostError::NotYourTurn(player.clone()));
+        }
+        let winner = self.transition(|session| session.act(action))?;
+        let mut events = vec![self.push(GameEvent::Acted {
+            player: player.clone(),
+            action,
+        })];
+        events.extend(winner.map(|winner| self.push(GameEvent::GameOver { winner })));
+        events.extend(self.auto_play(policy));
+        Ok(events)
+    }
+
+    /// Plays every turn of an auto-played seat, until it is someone else's turn
+    fn auto_play(&mut self, policy: &GracePolicy) -> Vec<GameEvent> {
+        let OnExpiry::AutoPlay(factory) = &policy.on_expiry else {
+            return Vec::new();
+        };
+        let mut events = Vec::new();
+        while let Some(player) = self.session().current_player().cloned() {
+            if self.presence(&player) != Some(Presence::AutoPlayed) {
+                break;
+            }
+            let mut bot = factory(self.last_seq() + 1);
+            let action = bot.act(&self.session().view_for(&player));
+            let Ok(winner) = self.transition(|session| session.act(action)) else {
+                // a bot that can't play the seat loses it like a player who never came back
+                events.extend(self.forfeit(&player, policy));
+                break;
+            };
+            events.push(self.push(GameEvent::Acted { player, action }));
+            events.extend(winner.map(|winner| self.push(GameEvent::GameOver { winner })));
+        }
+        events
+    }
+
+    fn forfeit(&mut self, player: &PlayerRef, policy: &GracePolicy) -> Vec<GameEvent> {
+        let Ok(winner) = self.transition(|session| session.forfeit(player)) else {
+            // the player was already out of the game, or it was over
+            return Vec::new();
+        };
+        self.presence.insert(player.clone(), Presence::Forfeited);
+        let mut events = vec![self.push(GameEvent::Forfeited {
+            player: player.clone(),
+        })];
+        events.extend(winner.map(|winner| self.push(GameEvent::GameOver { winner })));
+        events.extend(self.auto_play(policy));
+        events
+    }
+
+    fn connect(&mut self, player: &PlayerRef) -> Vec<GameEvent> {
+        let presence = self
+            .presence
+            .get_mut(player)
+            .expect("Every seat should have a presence");
+        match *presence {
+            Presence::Disconnected { .. } | Presence::AutoPlayed => {
+                *presence = Presence::Connected;
+                vec![self.push(GameEvent::Reconnected {
+                    player: player.clone(),
+                })]
+            }
+            Presence::Waiting => {
+                *presence = Presence::Connected;
+                Vec::new()
+            }
+            Presence::Connected | Presence::Forfeited => Vec::new(),
+        }
+    }
+
+    fn disconnect(&mut self, player: &PlayerRef, now: Instant) -> Vec<GameEvent> {
+        if self.session().is_over() {
+            return Vec::new();
+        }
+        let presence = self
+            .presence
+            .get_mut(player)
+            .expect("Every seat should have a presence");
+        if *presence != Presence::Connected {
+            return Vec::new();
+        }
+        *presence = Presence::Disconnected { since: now };
+        vec![self.push(GameEvent::Disconnected {
+            player: player.clone(),
+        })]
+    }
+
+    /// Forfeits or auto-plays every seat that has been disconnected for longer than `policy` allows
+    fn check_grace(&mut self, policy: &GracePolicy, now: Instant) -> Vec<GameEvent> {
+        let expired = self
+            .presence
+            .iter()
+            .filter(|(_, presence)| {
+                matches!(presence, Presence::Disconnected { since }
+                    if now.saturating_duration_since(*since) >= policy.grace)
+            })
+            .map(|(player, _)| player.clone())
+            .collect::<Vec<_>>();
+        let mut events = Vec::new();
+        for player in expired {
+            match policy.on_expiry {
+                OnExpiry::Forfeit => events.extend(self.forfeit(&player, policy)),
+                OnExpiry::AutoPlay(_) => {
+                    self.presence.insert(player.clone(), Presence::AutoPlayed);
+                    events.push(self.push(GameEvent::AutoPlaying { player }));
+                    events.extend(self.auto_play(policy));
+                }
+            }
+        }
+        events
+    }
+}
+
+/// Hosts any number of games, independent of how clients reach it
+///
+/// Every seat gets a token when its game is created, and clients act and see the game only through
+/// their token. Connecting with it hands out a reconnect token, which resumes the seat after a
+/// dropped connection along with every event missed in between, as long as the player comes back
+/// within the [`GracePolicy`].
+#[derive(Debug, Default)]
+pub struct Host {
+    games: IndexMap<GameId, HostedGame>,
+    tokens: HashMap<String, (GameId, PlayerRef)>,
+    reconnect_tokens: HashMap<String, (GameId, PlayerRef)>,
+    policy: GracePolicy,
+    next_id: GameId,
+}
+
+impl Host {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_grace(mut self, policy: GracePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Starts a game and returns its id along with the token of every seat
+    pub fn create_game(
+        &mut self,
+        players: impl IntoIterator<Item = Player>,
+        config: GameConfig,
+    ) -> (GameId, IndexMap<PlayerRef, String>) {
+        self.add_session(Game::new(players, config).into())
+    }
+
+    /// Hosts an existing game and returns its id along with the token of every seat
+    pub fn add_session(&mut self, session: GameSession) -> (GameId, IndexMap<PlayerRef, String>) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut rng = rand::thread_rng();
+        let seats = session
+            .player_dice_counts()
+            .keys()
+            .map(|player| (player.clone(), new_token(&mut rng)))
+            .collect::<IndexMap<_, _>>();
+        for (player, token) in &seats {
+            self.tokens.insert(token.clone(), (id, player.clone()));
+        }
+        self.games.insert(
+            id,
+            HostedGame {
+                session: Some(session),
+                seats: seats.clone(),
+                presence: seats
+                    .keys()
+                    .map(|player| (player.clone(), Presence::Waiting))
+                    .collect(),
+                acked: seats.keys().map(|player| (player.clone(), 0)).collect(),
+                events: Vec::new(),
+            },
+        );
+        (id, seats)
+    }
+
+    pub fn game(&self, id: GameId) -> Result<&HostedGame, HostError> {
+        self.games.get(&id).ok_or(HostError::UnknownGame(id))
+    }
+
+    fn game_mut(&mut self, id: GameId) -> Result<&mut HostedGame, HostError> {
+        self.games.get_mut(&id).ok_or(HostError::UnknownGame(id))
+    }
+
+    /// The game and player a token belongs to
+    pub fn seat(&self, token: &str) -> Result<(GameId, PlayerRef), HostError> {
+        self.tokens
+            .get(token)
+            .cloned()
+            .ok_or(HostError::UnknownToken)
+    }
+
+    pub fn view(&self, token: &str) -> Result<PlayerView, HostError> {
+        let (id, player) = self.seat(token)?;
+        Ok(self.game(id)?.session().view_for(&player))
+    }
+
+    /// Applies `action` for the player holding `token`, returning what happened
+    ///
+    /// Seats that are auto-played act right after, so their events are included too.
+    pub fn act(
+        &mut self,
+        token: &str,
+        action: Action,
+    ) -> Result<(GameId, Vec<GameEvent>), HostError> {
+        let (id, player) = self.seat(token)?;
+        let game = self.games.get_mut(&id).ok_or(HostError::UnknownGame(id))?;
+        if game.presence(&player) == Some(Presence::Forfeited) {
+            return Err(HostError::Forfeited(player));
+        }
+        Ok((id, game.act(&player, action, &self.policy)?))
+    }
+
+    /// Hands out a new reconnect token for the seat, replacing any earlier one
+    fn reconnect_token(&mut self, id: GameId, player: &PlayerRef) -> String {
+        self.reconnect_tokens
+            .retain(|_, (game, seat)| *game != id || seat != player);
+        let token = new_token(&mut rand::thread_rng());
+        self.reconnect_tokens
+            .insert(token.clone(), (id, player.clone()));
+        token
+    }
+
+    /// Marks the seat of `token` as connected, replaying its whole game
+    pub fn connect(&mut self, token: &str) -> Result<(Connection, Vec<GameEvent>), HostError> {
+        let (id, player) = self.seat(token)?;
+        self.resume_from(id, player, 0)
+    }
+
+    /// Takes a seat back after a dropped connection, replaying every event after `last_seq`
+    ///
+    /// Without `last_seq`, the events are replayed from the last one the player acknowledged.
+    pub fn resume(
+        &mut self,
+        reconnect_token: &str,
+        last_seq: Option<Seq>,
+    ) -> Result<(Connection, Vec<GameEvent>), HostError> {
+        let (id, player) = self
+            .reconnect_tokens
+            .get(reconnect_token)
+            .cloned()
+            .ok_or(HostError::UnknownToken)?;
+        let last_seq = match last_seq {
+            Some(seq) => seq,
+            None => self.game(id)?.acked[&player],
+        };
+        self.resume_from(id, player, last_seq)
+    }
+
+    fn resume_from(
+        &mut self,
+        id: GameId,
+        player: PlayerRef,
+        last_seq: Seq,
+    ) -> Result<(Connection, Vec<GameEvent>), HostError> {
+        let game = self.game_mut(id)?;
+        let missed = game
+            .events_since(last_seq)
+            .map(|(seq, event)| (seq, event.clone()))
+            .collect();
+        let events = game.connect(&player);
+        let reconnect_token = self.reconnect_token(id, &player);
+        Ok((
+            Connection {
+                game: id,
+                player,
+                reconnect_token,
+                missed,
+            },
+            events,
+        ))
+    }
+
+    /// Records that the player holding `token` has seen every event up to `seq`
+    pub fn ack(&mut self, token: &str, seq: Seq) -> Result<(), HostError> {
+        let (id, player) = self.seat(token)?;
+        let game = self.game_mut(id)?;
+        let acked = game
+            .acked
+            .get_mut(&player)
+            .expect("Every seat should have an acknowledged event");
+        *acked = (*acked).max(seq.min(game.events.len() as Seq));
+        Ok(())
+    }
+
+    /// Marks a seat as disconnected at `now`, which starts its grace period
+    pub fn disconnect(
+        &mut self,
+        id: GameId,
+        player: &PlayerRef,
+        now: Instant,
+    ) -> Result<Vec<GameEvent>, HostError> {
+        Ok(self.game_mut(id)?.disconnect(player, now))
+    }
+
+    /// Forfeits or auto-plays every seat whose grace period ran out by `now`
+    pub fn check_grace(&mut self, now: Instant) -> Vec<(GameId, Vec<GameEvent>)> {
+        self.games
+            .iter_mut()
+            .map(|(id, game)| (*id, game.check_grace(&self.policy, now)))
+            .filter(|(_, events)| !events.is_empty())
+            .collect()
+    }
+
+    /// Stops hosting a game, returning it
+    pub fn remove_game(&mut self, id: GameId) -> Result<GameSession, HostError> {
+        let mut game = self
+            .games
+            .shift_remove(&id)
+            .ok_or(HostError::UnknownGame(id))?;
+        self.tokens.retain(|_, (game_id, _)| *game_id != id);
+        self.reconnect_tokens
+            .retain(|_, (game_id, _)| *game_id != id);
+        Ok(game.session.take().expect("Session should be present"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{bet::Bet, simulation::factory, strategy::bots::Cautious};
+
+    #[test]
+    fn test_host() {
+        let mut host = Host::new();
+        let (id, seats) =
+            host.create_game([Player::new("A"), Player::new("B")], GameConfig::default());
+        let tokens = seats.values().collect::<Vec<_>>();
+        let bet = Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN));
+        assert!(matches!(
+            host.act(tokens[1], bet),
+            Err(HostError::NotYourTurn(_))
+        ));
+        assert_eq!(host.act("forged", bet), Err(HostError::UnknownToken));
+        let (game, events) = host.act(tokens[0], bet).unwrap();
+        assert_eq!(game, id);
+        assert_eq!(events.len(), 1);
+        let view = host.view(tokens[1]).unwrap();
+        assert!(view.is_my_turn());
+        assert_eq!(view.own_rolls().len(), 5);
+        host.remove_game(id).unwrap();
+        assert_eq!(host.view(tokens[1]), Err(HostError::UnknownToken));
+    }
+
+    #[test]
+    fn test_grace() {
+        let start = Instant::now();
+        let grace = Duration::from_secs(30);
+        let players = || [Player::new("A"), Player::new("B"), Player::new("C")];
+        let mut host = Host::new().with_grace(GracePolicy {
+            grace,
+            on_expiry: OnExpiry::Forfeit,
+        });
+        let (id, seats) = host.create_game(players(), GameConfig::default());
+        let (a, _) = host.connect(&seats[0]).unwrap();
+        host.connect(&seats[1]).unwrap();
+        assert_eq!(
+            host.disconnect(id, &a.player, start).unwrap(),
+            vec![GameEvent::Disconnected {
+                player: a.player.clone()
+            }]
+        );
+        assert!(host.check_grace(start + grace / 2).is_empty());
+        let (_, events) = host.check_grace(start + grace).remove(0);
+        assert_eq!(
+            events,
+            vec![GameEvent::Forfeited {
+                player: a.player.clone()
+            }]
+        );
+        // B opens the new round in A's place
+        assert!(host.view(&seats[1]).unwrap().is_my_turn());
+        let bet = Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN));
+        assert_eq!(
+            host.act(&seats[0], bet),
+            Err(HostError::Forfeited(a.player))
+        );
+
+        let mut host = Host::new().with_grace(GracePolicy {
+            grace,
+            on_expiry: OnExpiry::AutoPlay(factory(|_| Cautious::default())),
+        });
+        let (id, seats) = host.create_game(players(), GameConfig::default());
+        let (b, _) = host.connect(&seats[1]).unwrap();
+        host.disconnect(id, &b.player, start).unwrap();
+        host.check_grace(start + grace);
+        let (_, events) = host.act(&seats[0], bet).unwrap();
+        // the bot answers for B right away
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[1], GameEvent::Acted { player, .. } if *player == b.player));
+        assert_eq!(host.game(id).unwrap().last_seq(), 4);
+
+        // coming back replays what B missed, and hands out a new reconnect token
+        let (resumed, events) = host.resume(&b.reconnect_token, Some(2)).unwrap();
+        assert_eq!(resumed.missed.len(), 2);
+        assert_eq!(
+            events,
+            vec![GameEvent::Reconnected {
+                player: b.player.clone()
+            }]
+        );
+        assert!(host.resume(&b.reconnect_token, None).is_err());
+        assert_eq!(
+            host.game(id).unwrap().presence(&b.player),
+            Some(Presence::Connected)
+        );
+    }
+
+    #[test]
+    fn test_failed_forfeit_keeps_presence() {
+        let start = Instant::now();
+        let grace = Duration::from_secs(30);
+        let mut host = Host::new().with_grace(GracePolicy {
+            grace,
+            on_expiry: OnExpiry::Forfeit,
+        });
+        let players = ["A", "B", "C"].map(Player::new);
+        let config = GameConfig::new(NonZeroUsize::MIN, NonZeroUsize::new(6).unwrap());
+        let (id, seats) = host.create_game(players, config);
+        let bet = Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN));
+        host.act(&seats[0], bet).unwrap();
+        host.act(&seats[1], Action::CallFluff).unwrap();
+        // with one die each, whoever lost the call is out, so they can't forfeit anymore
+        let session = host.game(id).unwrap().session();
+        let out = session
+            .player_dice_counts()
+            .iter()
+            .find(|(_, dice)| **dice == 0)
+            .map(|(player, _)| player.clone())
+            .unwrap();
+        host.connect(&seats[&out]).unwrap();
+        host.disconnect(id, &out, start).unwrap();
+        assert!(host.check_grace(start + grace).is_empty());
+        assert_eq!(
+            host.game(id).unwrap().presence(&out),
+            Some(Presence::Disconnected { since: start })
+        );
+    }
+}
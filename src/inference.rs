@@ -0,0 +1,467 @@
+use std::num::NonZeroUsize;
+
+use indexmap::IndexMap;
+use thiserror::Error;
+
+use crate::{
+    bet::{Bet, WildRule},
+    game::{
+        round::{RollSet, Turn},
+        state::RoundState,
+        PlayerRef, Round,
+    },
+};
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Rolled a {roll}, which is higher than the max roll of {max_roll}")]
+pub struct RollTooHigh {
+    pub roll: NonZeroUsize,
+    pub max_roll: NonZeroUsize,
+}
+
+/// A hand of dice with the order of the rolls forgotten, as the count of each face
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Hand {
+    // face_counts[i] is how many dice show i + 1
+    face_counts: Vec<usize>,
+}
+
+impl Hand {
+    pub fn from_rolls(rolls: &[NonZeroUsize], max_roll: NonZeroUsize) -> Result<Self, RollTooHigh> {
+        let mut face_counts = vec![0; max_roll.get()];
+        for &roll in rolls {
+            *face_counts
+                .get_mut(roll.get() - 1)
+                .ok_or(RollTooHigh { roll, max_roll })? += 1;
+        }
+        Ok(Self { face_counts })
+    }
+
+    #[must_use]
+    pub fn face_counts(&self) -> &[usize] {
+        &self.face_counts
+    }
+
+    #[must_use]
+    pub fn dice_count(&self) -> usize {
+        self.face_counts.iter().sum()
+    }
+
+    /// How many dice in this hand count towards a bet on `bet_roll`
+    #[must_use]
+    pub fn matches(&self, bet_roll: NonZeroUsize, wild_rule: WildRule) -> usize {
+        self.face_counts
+            .iter()
+            .zip(1..)
+            .filter_map(|(count, face)| Some((count, NonZeroUsize::new(face)?)))
+            .filter(|(_, face)| wild_rule.matches(bet_roll, *face))
+            .map(|(count, _)| count)
+            .sum()
+    }
+
+    /// Every hand of `dice_count` dice with faces up to `max_roll`
+    fn all(dice_count: usize, max_roll: NonZeroUsize) -> Vec<Self> {
+        fn fill(remaining: usize, face_counts: &mut Vec<usize>, faces: usize, out: &mut Vec<Hand>) {
+            if face_counts.len() + 1 == faces {
+                face_counts.push(remaining);
+                out.push(Hand {
+                    face_counts: face_counts.clone(),
+                });
+                face_counts.pop();
+                return;
+            }
+            for count in 0..=remaining {
+                face_counts.push(count);
+                fill(remaining - count, face_counts, faces, out);
+                face_counts.pop();
+            }
+        }
+        let mut out = Vec::new();
+        fill(
+            dice_count,
+            &mut Vec::with_capacity(max_roll.get()),
+            max_roll.get(),
+            &mut out,
+        );
+        out
+    }
+
+    /// Probability of rolling exactly this hand with fair dice
+    fn prior(&self) -> f64 {
+        let faces = self.face_counts.len() as f64;
+        let mut remaining = 0;
+        let mut multinomial = 1.0;
+        for count in &self.face_counts {
+            for i in 1..=*count {
+                remaining += 1;
+                multinomial *= remaining as f64 / i as f64;
+            }
+        }
+        multinomial / faces.powi(self.dice_count() as i32)
+    }
+}
+
+/// What a bidder could know when they made a bid
+#[derive(Debug, Clone, Copy)]
+pub struct BidContext {
+    pub bet: Bet,
+    pub prev_bet: Option<Bet>,
+    /// Dice in play that the bidder can not see
+    pub unknown_dice: usize,
+    pub max_roll: NonZeroUsize,
+    pub wild_rule: WildRule,
+}
+
+/// How likely a player is to make a bid, given what they are holding
+pub trait BehaviorModel {
+    /// Relative likelihood of a player holding `hand` making the bid described by `context`
+    ///
+    /// Only ratios between hands matter, so this does not need to be normalized.
+    fn likelihood(&self, context: &BidContext, hand: &Hand) -> f64;
+}
+
+/// Bids say nothing about the bidder's hand, which leaves the posteriors at the prior
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Uninformative;
+
+impl BehaviorModel for Uninformative {
+    fn likelihood(&self, _: &BidContext, _: &Hand) -> f64 {
+        1.0
+    }
+}
+
+/// Players mostly make bids they believe are true, and bluff the rest of the time
+///
+/// The likelihood of a bid is `bluff_rate + (1 - bluff_rate) * p^sharpness`, where `p` is the
+/// probability of the bid being true from the bidder's point of view.
+#[derive(Debug, Clone, Copy)]
+pub struct Plausible {
+    pub bluff_rate: f64,
+    pub sharpness: f64,
+}
+
+impl Default for Plausible {
+    fn default() -> Self {
+        Self {
+            bluff_rate: 0.2,
+            sharpness: 1.0,
+        }
+    }
+}
+
+impl BehaviorModel for Plausible {
+    fn likelihood(&self, context: &BidContext, hand: &Hand) -> f64 {
+        let matching_faces = context
+            .wild_rule
+            .matching_faces(context.bet.roll, context.max_roll);
+        let p_true = binomial_at_least(
+            context.unknown_dice,
+            matching_faces as f64 / context.max_roll.get() as f64,
+            context
+                .bet
+                .count
+                .get()
+                .saturating_sub(hand.matches(context.bet.roll, context.wild_rule)),
+        );
+        self.bluff_rate + (1.0 - self.bluff_rate) * p_true.powf(self.sharpness)
+    }
+}
+
+fn binomial_distribution(n: usize, p: f64) -> Vec<f64> {
+    let mut dist = vec![1.0];
+    for _ in 0..n {
+        let mut next = vec![0.0; dist.len() + 1];
+        for (k, x) in dist.iter().enumerate() {
+            next[k] += x * (1.0 - p);
+            next[k + 1] += x * p;
+        }
+        dist = next;
+    }
+    dist
+}
+
+fn binomial_at_least(n: usize, p: f64, k: usize) -> f64 {
+    binomial_distribution(n, p).iter().skip(k).sum()
+}
+
+fn convolve(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut out = vec![0.0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            out[i + j] += x * y;
+        }
+    }
+    out
+}
+
+/// Posterior distribution over the hands a single opponent could be holding
+#[derive(Debug, Clone)]
+pub struct HandPosterior {
+    hands: Vec<(Hand, f64)>,
+}
+
+impl HandPosterior {
+    /// Every hand of `dice_count` fair dice, weighted by how likely it is to be rolled
+    ///
+    /// The number of hands grows quickly with `dice_count` and `max_roll`, so this is meant for
+    /// the dice counts of an actual game.
+    #[must_use]
+    pub fn prior(dice_count: usize, max_roll: NonZeroUsize) -> Self {
+        Self {
+            hands: Hand::all(dice_count, max_roll)
+                .into_iter()
+                .map(|hand| {
+                    let prior = hand.prior();
+                    (hand, prior)
+                })
+                .collect(),
+        }
+    }
+
+    /// Every possible hand along with its probability
+    #[must_use]
+    pub fn hands(&self) -> &[(Hand, f64)] {
+        &self.hands
+    }
+
+    /// Reweights every hand by `likelihood`, falling back to the previous weights if no hand could
+    /// have explained the evidence
+    pub fn update(&mut self, mut likelihood: impl FnMut(&Hand) -> f64) {
+        let updated = self
+            .hands
+            .iter()
+            .map(|(hand, weight)| weight * likelihood(hand))
+            .collect::<Vec<_>>();
+        let total: f64 = updated.iter().sum();
+        if total <= 0.0 || !total.is_finite() {
+            return;
+        }
+        for ((_, weight), new_weight) in self.hands.iter_mut().zip(updated) {
+            *weight = new_weight / total;
+        }
+    }
+
+    /// Expected number of dice showing each face, indexed by face - 1
+    #[must_use]
+    pub fn expected_face_counts(&self) -> Vec<f64> {
+        let faces = self.hands.first().map_or(0, |(x, _)| x.face_counts.len());
+        let mut expected = vec![0.0; faces];
+        for (hand, weight) in &self.hands {
+            for (x, count) in expected.iter_mut().zip(&hand.face_counts) {
+                *x += weight * *count as f64;
+            }
+        }
+        expected
+    }
+
+    /// Distribution of how many of this opponent's dice count towards a bet on `bet_roll`
+    #[must_use]
+    pub fn match_distribution(&self, bet_roll: NonZeroUsize, wild_rule: WildRule) -> Vec<f64> {
+        let dice_count = self.hands.first().map_or(0, |(x, _)| x.dice_count());
+        let mut dist = vec![0.0; dice_count + 1];
+        for (hand, weight) in &self.hands {
+            dist[hand.matches(bet_roll, wild_rule)] += weight;
+        }
+        dist
+    }
+}
+
+/// Tracks what one player can infer about their opponents' hands from the bids of a round
+#[derive(Debug, Clone)]
+pub struct Inference<M: BehaviorModel> {
+    model: M,
+    max_roll: NonZeroUsize,
+    wild_rule: WildRule,
+    viewer: PlayerRef,
+    own_rolls: RollSet,
+    total_dice: usize,
+    opponents: IndexMap<PlayerRef, HandPosterior>,
+    prev_bet: Option<Bet>,
+}
+
+impl<M: BehaviorModel> Inference<M> {
+    /// Starts inference for `viewer` in `round`, taking in every bid already made in it
+    ///
+    /// Returns [`None`] if `viewer` has no dice in `round`.
+    pub fn new<S: RoundState>(
+        model: M,
+        round: &Round<S>,
+        viewer: &PlayerRef,
+        max_roll: NonZeroUsize,
+        wild_rule: WildRule,
+    ) -> Option<Self> {
+        let own_rolls = round.players_rolls().get(viewer)?.clone();
+        let opponents = round
+            .players_rolls()
+            .iter()
+            .filter(|(player, _)| *player != viewer)
+            .map(|(player, rolls)| (player.clone(), HandPosterior::prior(rolls.len(), max_roll)))
+            .collect();
+        let mut inference = Self {
+            model,
+            max_roll,
+            wild_rule,
+            viewer: viewer.clone(),
+            own_rolls,
+            total_dice: round.dice_in_play(),
+            opponents,
+            prev_bet: None,
+        };
+        for turn in round.turns() {
+            inference.observe(turn);
+        }
+        Some(inference)
+    }
+
+    /// Takes in the next bid of the round
+    pub fn observe(&mut self, turn: &Turn) {
+        let context = BidContext {
+            bet: turn.bet,
+            prev_bet: self.prev_bet,
+            unknown_dice: 0,
+            max_roll: self.max_roll,
+            wild_rule: self.wild_rule,
+        };
+        self.prev_bet = Some(turn.bet);
+        if turn.player == self.viewer {
+            return;
+        }
+        let Some(posterior) = self.opponents.get_mut(&turn.player) else {
+            return;
+        };
+        let model = &self.model;
+        let total_dice = self.total_dice;
+        posterior.update(|hand| {
+            let context = BidContext {
+                unknown_dice: total_dice - hand.dice_count(),
+                ..context
+            };
+            model.likelihood(&context, hand)
+        });
+    }
+
+    #[must_use]
+    pub fn posterior(&self, player: &PlayerRef) -> Option<&HandPosterior> {
+        self.opponents.get(player)
+    }
+
+    #[must_use]
+    pub fn posteriors(&self) -> &IndexMap<PlayerRef, HandPosterior> {
+        &self.opponents
+    }
+
+    /// Distribution of the total number of dice matching `bet_roll`, indexed by count
+    #[must_use]
+    pub fn match_distribution(&self, bet_roll: NonZeroUsize) -> Vec<f64> {
+        let known_matches = self
+            .own_rolls
+            .iter()
+            .filter(|x| self.wild_rule.matches(bet_roll, **x))
+            .count();
+        let mut dist = vec![0.0; known_matches];
+        dist.push(1.0);
+        self.opponents.values().fold(dist, |dist, posterior| {
+            convolve(
+                &dist,
+                &posterior.match_distribution(bet_roll, self.wild_rule),
+            )
+        })
+    }
+
+    /// Probability that `bet` is true, refined by everything inferred from the bids so far
+    #[must_use]
+    pub fn bet_probability(&self, bet: &Bet) -> f64 {
+        self.match_distribution(bet.roll)
+            .iter()
+            .skip(bet.count.get())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_traits::ToPrimitive;
+
+    use super::*;
+    use crate::{
+        game::{Game, GameConfig},
+        player::Player,
+        probability,
+    };
+
+    fn game() -> Game {
+        Game::new(
+            [
+                Player::new("Unga"),
+                Player::new("Bunga"),
+                Player::new("Ooga"),
+            ],
+            GameConfig::new(NonZeroUsize::new(3).unwrap(), NonZeroUsize::new(6).unwrap()),
+        )
+    }
+
+    #[test]
+    fn test_uninformative_matches_exact_probability() {
+        let g = game();
+        let viewer = g.current_player().clone();
+        let own_rolls = g.curr_round().players_rolls()[&viewer].clone();
+        let inference = Inference::new(
+            Uninformative,
+            g.curr_round(),
+            &viewer,
+            g.config().max_roll(),
+            WildRule::OnesWild,
+        )
+        .unwrap();
+        for (count, roll) in itertools::iproduct!(1..=9, 1..=6) {
+            let bet = Bet::new(
+                NonZeroUsize::new(count).unwrap(),
+                NonZeroUsize::new(roll).unwrap(),
+            );
+            let exact = probability::bet_probability(
+                &bet,
+                &own_rolls,
+                6,
+                g.config().max_roll(),
+                WildRule::OnesWild,
+            );
+            assert!((inference.bet_probability(&bet) - exact.to_f64().unwrap()).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_hand_from_rolls() {
+        let [one, two, three] = [1, 2, 3].map(|x| NonZeroUsize::new(x).unwrap());
+        let hand = Hand::from_rolls(&[one, three, three], three).unwrap();
+        assert_eq!(hand.face_counts(), [1, 0, 2]);
+        assert_eq!(
+            Hand::from_rolls(&[one, three], two),
+            Err(RollTooHigh {
+                roll: three,
+                max_roll: two
+            })
+        );
+    }
+
+    #[test]
+    fn test_bids_shift_posterior() {
+        let g = game();
+        let bidder = g.current_player().clone();
+        let viewer = g.next_player();
+        let six = NonZeroUsize::new(6).unwrap();
+        let g = g.raise_bet(Bet::new(NonZeroUsize::new(5).unwrap(), six));
+        let prior = HandPosterior::prior(3, six).expected_face_counts();
+        let inference = Inference::new(
+            Plausible::default(),
+            g.curr_round(),
+            &viewer,
+            six,
+            WildRule::OnesWild,
+        )
+        .unwrap();
+        let posterior = inference.posterior(&bidder).unwrap().expected_face_counts();
+        assert!(posterior[5] > prior[5]);
+        assert!(posterior[0] > prior[0]);
+        assert!(posterior[2] < prior[2]);
+    }
+}
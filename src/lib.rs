@@ -2,5 +2,27 @@
 extern crate serde;
 
 pub mod bet;
+pub mod correspondence;
+pub mod evaluation;
 pub mod game;
+pub mod hint;
+pub mod host;
+pub mod inference;
+pub mod lobby;
 pub mod player;
+pub mod probability;
+#[cfg(feature = "protocol")]
+pub mod protocol;
+pub mod rating;
+#[cfg(feature = "rest")]
+pub mod rest;
+#[cfg(feature = "sealed")]
+pub mod sealed;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod simulation;
+pub mod solver;
+pub mod statistics;
+pub mod storage;
+pub mod strategy;
+pub mod tournament;
@@ -0,0 +1,479 @@
+use std::time::Instant;
+
+use indexmap::{IndexMap, IndexSet};
+use thiserror::Error;
+
+use crate::{
+    game::{session::Action, view::PlayerView, GameConfig, PlayerRef},
+    host::{Connection, GameEvent, GameId, GracePolicy, Host, HostError, Seq},
+    player::Player,
+};
+
+pub type RoomId = u64;
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub enum RoomState {
+    /// Players are joining and getting ready
+    Waiting,
+    Playing(GameId),
+    Finished {
+        game: GameId,
+        winner: PlayerRef,
+    },
+}
+
+/// Players waiting for, playing or watching a single game
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct Room {
+    pub id: RoomId,
+    pub config: GameConfig,
+    /// Most players the room lets in
+    pub capacity: usize,
+    /// Seated players in joining order, with whether they are ready
+    pub players: IndexMap<Player, bool>,
+    pub spectators: IndexSet<Player>,
+    pub state: RoomState,
+}
+
+impl Room {
+    #[must_use]
+    pub fn game(&self) -> Option<GameId> {
+        match self.state {
+            RoomState::Waiting => None,
+            RoomState::Playing(game) | RoomState::Finished { game, .. } => Some(game),
+        }
+    }
+
+    fn all_ready(&self) -> bool {
+        self.players.len() >= 2 && self.players.values().all(|ready| *ready)
+    }
+}
+
+/// Something that happened in a room, for the transport to tell the room's members about
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LobbyEvent {
+    Joined {
+        room: RoomId,
+        player: Player,
+    },
+    Spectating {
+        room: RoomId,
+        player: Player,
+    },
+    Left {
+        room: RoomId,
+        player: Player,
+    },
+    Ready {
+        room: RoomId,
+        player: Player,
+        ready: bool,
+    },
+    /// The game started, and every player can now get their seat token
+    Started {
+        room: RoomId,
+        game: GameId,
+    },
+    Game {
+        room: RoomId,
+        event: GameEvent,
+    },
+    Closed {
+        room: RoomId,
+    },
+}
+
+#[derive(Error, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub enum LobbyError {
+    #[error("No room has id {0}")]
+    UnknownRoom(RoomId),
+    #[error("{0} is already in the room")]
+    AlreadyInRoom(Player),
+    #[error("{0} is not in the room")]
+    NotInRoom(Player),
+    #[error("The room is full")]
+    RoomFull,
+    #[error("The room's game has already started")]
+    AlreadyStarted,
+    #[error("The room's game has not started yet")]
+    NotStarted,
+    #[error(transparent)]
+    Host(#[from] HostError),
+}
+
+/// Rooms of players getting together for games, independent of how they reach the lobby
+#[derive(Debug, Default)]
+pub struct Lobby {
+    rooms: IndexMap<RoomId, Room>,
+    host: Host,
+    /// Seat tokens of the games that have started, which only their own player should be given
+    tokens: IndexMap<(RoomId, Player), String>,
+    next_id: RoomId,
+}
+
+impl Lobby {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets what happens to players who stay disconnected from a started game
+    #[must_use]
+    pub fn with_grace(mut self, policy: GracePolicy) -> Self {
+        self.host = self.host.with_grace(policy);
+        self
+    }
+
+    #[must_use]
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    pub fn rooms(&self) -> impl Iterator<Item = &Room> {
+        self.rooms.values()
+    }
+
+    pub fn room(&self, id: RoomId) -> Result<&Room, LobbyError> {
+        self.rooms.get(&id).ok_or(LobbyError::UnknownRoom(id))
+    }
+
+    fn waiting_room(
+        rooms: &mut IndexMap<RoomId, Room>,
+        id: RoomId,
+    ) -> Result<&mut Room, LobbyError> {
+        let room = rooms.get_mut(&id).ok_or(LobbyError::UnknownRoom(id))?;
+        if room.state == RoomState::Waiting {
+            Ok(room)
+        } else {
+            Err(LobbyError::AlreadyStarted)
+        }
+    }
+
+    pub fn create_room(&mut self, config: GameConfig, capacity: usize) -> RoomId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.rooms.insert(
+            id,
+            Room {
+                id,
+                config,
+                capacity,
+                players: IndexMap::new(),
+                spectators: IndexSet::new(),
+                state: RoomState::Waiting,
+            },
+        );
+        id
+    }
+
+    pub fn join(&mut self, id: RoomId, player: Player) -> Result<Vec<LobbyEvent>, LobbyError> {
+        let room = Self::waiting_room(&mut self.rooms, id)?;
+        if room.players.contains_key(&player) || room.spectators.contains(&player) {
+            return Err(LobbyError::AlreadyInRoom(player));
+        }
+        if room.players.len() >= room.capacity {
+            return Err(LobbyError::RoomFull);
+        }
+        room.players.insert(player.clone(), false);
+        Ok(vec![LobbyEvent::Joined { room: id, player }])
+    }
+
+    /// Watches the room without a seat, which is allowed even after its game has started
+    pub fn spectate(&mut self, id: RoomId, player: Player) -> Result<Vec<LobbyEvent>, LobbyError> {
+        let room = self.rooms.get_mut(&id).ok_or(LobbyError::UnknownRoom(id))?;
+        if room.players.contains_key(&player) || !room.spectators.insert(player.clone()) {
+            return Err(LobbyError::AlreadyInRoom(player));
+        }
+        Ok(vec![LobbyEvent::Spectating { room: id, player }])
+    }
+
+    /// Leaves a room as a spectator, or as a player before its game has started
+    ///
+    /// A room that nobody is left in is closed.
+    pub fn leave(&mut self, id: RoomId, player: &Player) -> Result<Vec<LobbyEvent>, LobbyError> {
+        let room = self.rooms.get_mut(&id).ok_or(LobbyError::UnknownRoom(id))?;
+        if !room.spectators.shift_remove(player) {
+            if !room.players.contains_key(player) {
+                return Err(LobbyError::NotInRoom(player.clone()));
+            }
+            if room.state != RoomState::Waiting {
+                return Err(LobbyError::AlreadyStarted);
+            }
+            room.players.shift_remove(player);
+        }
+        let mut events = vec![LobbyEvent::Left {
+            room: id,
+            player: player.clone(),
+        }];
+        if room.players.is_empty() && room.spectators.is_empty() {
+            events.extend(self.close(id));
+        }
+        Ok(events)
+    }
+
+    /// Marks a player as ready or not, starting the game once at least 2 players are all ready
+    pub fn set_ready(
+        &mut self,
+        id: RoomId,
+        player: &Player,
+        ready: bool,
+    ) -> Result<Vec<LobbyEvent>, LobbyError> {
+        let room = Self::waiting_room(&mut self.rooms, id)?;
+        *room
+            .players
+            .get_mut(player)
+            .ok_or_else(|| LobbyError::NotInRoom(player.clone()))? = ready;
+        let mut events = vec![LobbyEvent::Ready {
+            room: id,
+            player: player.clone(),
+            ready,
+        }];
+        if room.all_ready() {
+            let players = room.players.keys().cloned().collect::<Vec<_>>();
+            let (game, seats) = self.host.create_game(players, room.config);
+            room.state = RoomState::Playing(game);
+            for (player, token) in seats {
+                self.tokens.insert((id, (*player).clone()), token);
+            }
+            events.push(LobbyEvent::Started { room: id, game });
+        }
+        Ok(events)
+    }
+
+    /// Token `player` plays their seat in the room's game with, once it has started
+    pub fn seat_token(&self, id: RoomId, player: &Player) -> Result<&str, LobbyError> {
+        self.room(id)?;
+        self.tokens
+            .get(&(id, player.clone()))
+            .map(String::as_str)
+            .ok_or_else(|| LobbyError::NotInRoom(player.clone()))
+    }
+
+    /// What a spectator of the room can see, which includes no rolls before they are revealed
+    pub fn spectator_view(&self, id: RoomId, spectator: &Player) -> Result<PlayerView, LobbyError> {
+        let room = self.room(id)?;
+        if !room.spectators.contains(spectator) {
+            return Err(LobbyError::NotInRoom(spectator.clone()));
+        }
+        let game = room.game().ok_or(LobbyError::NotStarted)?;
+        Ok(self
+            .host
+            .game(game)?
+            .session()
+            .view_for(&PlayerRef::new(spectator.clone())))
+    }
+
+    /// Applies `action` for the seat `token` belongs to, in whichever room it is
+    pub fn act(&mut self, token: &str, action: Action) -> Result<Vec<LobbyEvent>, LobbyError> {
+        let (game, events) = self.host.act(token, action)?;
+        Ok(self.room_events(game, events))
+    }
+
+    /// Marks the seat of `token` as connected, see [`Host::connect`]
+    pub fn connect(&mut self, token: &str) -> Result<(Connection, Vec<LobbyEvent>), LobbyError> {
+        let (connection, events) = self.host.connect(token)?;
+        let events = self.room_events(connection.game, events);
+        Ok((connection, events))
+    }
+
+    /// Takes a seat back after a dropped connection, see [`Host::resume`]
+    pub fn resume(
+        &mut self,
+        reconnect_token: &str,
+        last_seq: Option<Seq>,
+    ) -> Result<(Connection, Vec<LobbyEvent>), LobbyError> {
+        let (connection, events) = self.host.resume(reconnect_token, last_seq)?;
+        let events = self.room_events(connection.game, events);
+        Ok((connection, events))
+    }
+
+    /// Records that the player holding `token` has seen every event up to `seq`
+    pub fn ack(&mut self, token: &str, seq: Seq) -> Result<(), LobbyError> {
+        Ok(self.host.ack(token, seq)?)
+    }
+
+    /// Marks a player of the room's game as disconnected at `now`, which starts their grace period
+    pub fn disconnect(
+        &mut self,
+        id: RoomId,
+        player: &Player,
+        now: Instant,
+    ) -> Result<Vec<LobbyEvent>, LobbyError> {
+        let room = self.room(id)?;
+        if !room.players.contains_key(player) {
+            return Err(LobbyError::NotInRoom(player.clone()));
+        }
+        let game = room.game().ok_or(LobbyError::NotStarted)?;
+        let events = self
+            .host
+            .disconnect(game, &PlayerRef::new(player.clone()), now)?;
+        Ok(self.room_events(game, events))
+    }
+
+    /// Forfeits or auto-plays every seat whose grace period ran out by `now`
+    ///
+    /// Rooms whose game a forfeit ends are finished, like after [`Lobby::act`].
+    pub fn check_grace(&mut self, now: Instant) -> Vec<LobbyEvent> {
+        self.host
+            .check_grace(now)
+            .into_iter()
+            .flat_map(|(game, events)| self.room_events(game, events))
+            .collect()
+    }
+
+    /// Tells the room of `game` about its `events`, finishing the room if the game is over
+    fn room_events(&mut self, game: GameId, events: Vec<GameEvent>) -> Vec<LobbyEvent> {
+        let room = self
+            .rooms
+            .values_mut()
+            .find(|room| room.game() == Some(game))
+            .expect("Every hosted game should belong to a room");
+        for event in &events {
+            if let GameEvent::GameOver { winner } = event {
+                room.state = RoomState::Finished {
+                    game,
+                    winner: winner.clone(),
+                };
+            }
+        }
+        let id = room.id;
+        events
+            .into_iter()
+            .map(|event| LobbyEvent::Game { room: id, event })
+            .collect()
+    }
+
+    fn close(&mut self, id: RoomId) -> Vec<LobbyEvent> {
+        let Some(room) = self.rooms.shift_remove(&id) else {
+            return Vec::new();
+        };
+        if let Some(game) = room.game() {
+            // the game is only missing if it was already removed
+            let _ = self.host.remove_game(game);
+        }
+        self.tokens.retain(|(room_id, _), _| *room_id != id);
+        vec![LobbyEvent::Closed { room: id }]
+    }
+
+    /// Closes every room whose game is over, returning them
+    pub fn clean_up(&mut self) -> (Vec<Room>, Vec<LobbyEvent>) {
+        let finished = self
+            .rooms
+            .values()
+            .filter(|room| matches!(room.state, RoomState::Finished { .. }))
+            .cloned()
+            .collect::<Vec<_>>();
+        let events = finished
+            .iter()
+            .flat_map(|room| self.close(room.id))
+            .collect();
+        (finished, events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, time::Duration};
+
+    use super::*;
+    use crate::{bet::Bet, host::OnExpiry};
+
+    #[test]
+    fn test_lobby() {
+        let mut lobby = Lobby::new();
+        let [a, b, c, d] = ["A", "B", "C", "D"].map(Player::new);
+        let config = GameConfig::new(NonZeroUsize::MIN, NonZeroUsize::new(6).unwrap());
+        let id = lobby.create_room(config, 2);
+        lobby.join(id, a.clone()).unwrap();
+        assert_eq!(
+            lobby.join(id, a.clone()),
+            Err(LobbyError::AlreadyInRoom(a.clone()))
+        );
+        lobby.join(id, b.clone()).unwrap();
+        assert_eq!(lobby.join(id, c.clone()), Err(LobbyError::RoomFull));
+        lobby.spectate(id, d.clone()).unwrap();
+        assert_eq!(lobby.spectator_view(id, &d), Err(LobbyError::NotStarted));
+        lobby.set_ready(id, &a, true).unwrap();
+        assert_eq!(lobby.room(id).unwrap().state, RoomState::Waiting);
+        let events = lobby.set_ready(id, &b, true).unwrap();
+        assert!(matches!(events[1], LobbyEvent::Started { .. }));
+        assert_eq!(lobby.leave(id, &a), Err(LobbyError::AlreadyStarted));
+        assert!(lobby.spectator_view(id, &d).unwrap().own_rolls.is_none());
+
+        let [token_a, token_b] = [&a, &b].map(|x| lobby.seat_token(id, x).unwrap().to_owned());
+        lobby
+            .act(
+                &token_a,
+                Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN)),
+            )
+            .unwrap();
+        // with one die each, the first call ends the game
+        let events = lobby.act(&token_b, Action::CallFluff).unwrap();
+        assert!(matches!(
+            events.last(),
+            Some(LobbyEvent::Game {
+                event: GameEvent::GameOver { .. },
+                ..
+            })
+        ));
+        let (finished, events) = lobby.clean_up();
+        assert_eq!(finished.len(), 1);
+        assert_eq!(events, vec![LobbyEvent::Closed { room: id }]);
+        assert!(lobby.room(id).is_err());
+        assert!(lobby.host().seat(&token_a).is_err());
+    }
+
+    #[test]
+    fn test_grace_forfeit() {
+        let start = Instant::now();
+        let grace = Duration::from_secs(30);
+        let mut lobby = Lobby::new().with_grace(GracePolicy {
+            grace,
+            on_expiry: OnExpiry::Forfeit,
+        });
+        let [a, b] = ["A", "B"].map(Player::new);
+        let id = lobby.create_room(GameConfig::default(), 2);
+        for player in [&a, &b] {
+            lobby.join(id, player.clone()).unwrap();
+            lobby.set_ready(id, player, true).unwrap();
+        }
+        let [token_a, token_b] = [&a, &b].map(|x| lobby.seat_token(id, x).unwrap().to_owned());
+        lobby.connect(&token_a).unwrap();
+        let (connection, _) = lobby.connect(&token_b).unwrap();
+        assert_eq!(
+            lobby.disconnect(id, &a, start).unwrap(),
+            vec![LobbyEvent::Game {
+                room: id,
+                event: GameEvent::Disconnected {
+                    player: PlayerRef::new(a.clone())
+                }
+            }]
+        );
+        assert_eq!(
+            lobby.disconnect(id, &Player::new("C"), start),
+            Err(LobbyError::NotInRoom(Player::new("C")))
+        );
+        assert!(lobby.check_grace(start + grace / 2).is_empty());
+
+        // with only B left, A's forfeit ends the game
+        let events = lobby.check_grace(start + grace);
+        assert!(matches!(
+            events.last(),
+            Some(LobbyEvent::Game {
+                event: GameEvent::GameOver { .. },
+                ..
+            })
+        ));
+        assert_eq!(
+            lobby.room(id).unwrap().state,
+            RoomState::Finished {
+                game: connection.game,
+                winner: connection.player,
+            }
+        );
+        let (finished, _) = lobby.clean_up();
+        assert_eq!(finished.len(), 1);
+        assert!(lobby.room(id).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::num::NonZeroUsize;
+
+use num_bigint::{BigInt, BigUint};
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+
+use crate::bet::{Bet, WildRule};
+
+/// Exact distribution of how many dice match a bet's roll, out of a viewer's own dice and some dice
+/// they can not see
+///
+/// Each unknown die is assumed to be fair, so the matches among them are binomially distributed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchDistribution {
+    known_matches: usize,
+    // weights[j] / total_weight is the probability of exactly j matches among the unknown dice
+    weights: Vec<BigUint>,
+    total_weight: BigUint,
+}
+
+impl MatchDistribution {
+    #[must_use]
+    pub fn new(
+        bet_roll: NonZeroUsize,
+        own_rolls: &[NonZeroUsize],
+        unknown_dice: usize,
+        max_roll: NonZeroUsize,
+        wild_rule: WildRule,
+    ) -> Self {
+        let known_matches = own_rolls
+            .iter()
+            .filter(|x| wild_rule.matches(bet_roll, **x))
+            .count();
+        let matching = BigUint::from(wild_rule.matching_faces(bet_roll, max_roll));
+        let not_matching = BigUint::from(max_roll.get()) - &matching;
+        let mut binomial = BigUint::one();
+        let weights = (0..=unknown_dice)
+            .map(|j| {
+                let weight = &binomial * pow(&matching, j) * pow(&not_matching, unknown_dice - j);
+                binomial = binomial.clone() * (unknown_dice - j) / (j + 1);
+                weight
+            })
+            .collect();
+        Self {
+            known_matches,
+            weights,
+            total_weight: pow(&BigUint::from(max_roll.get()), unknown_dice),
+        }
+    }
+
+    /// Matches among the viewer's own dice, which is the lowest possible count
+    #[must_use]
+    pub const fn known_matches(&self) -> usize {
+        self.known_matches
+    }
+
+    /// Highest possible count, if every unknown die matches
+    #[must_use]
+    pub fn max_count(&self) -> usize {
+        self.known_matches + self.weights.len() - 1
+    }
+
+    fn ratio(&self, weight: BigUint) -> BigRational {
+        BigRational::new(
+            BigInt::from(weight),
+            BigInt::from(self.total_weight.clone()),
+        )
+    }
+
+    /// Probability that exactly `count` dice match
+    #[must_use]
+    pub fn probability_of(&self, count: usize) -> BigRational {
+        count
+            .checked_sub(self.known_matches)
+            .and_then(|j| self.weights.get(j))
+            .map_or_else(BigRational::zero, |weight| self.ratio(weight.clone()))
+    }
+
+    /// Probability that at least `count` dice match
+    #[must_use]
+    pub fn probability_at_least(&self, count: usize) -> BigRational {
+        let skip = count.saturating_sub(self.known_matches);
+        self.ratio(self.weights.iter().skip(skip).sum())
+    }
+
+    /// Every possible count along with its probability, from lowest to highest
+    pub fn iter(&self) -> impl Iterator<Item = (usize, BigRational)> + '_ {
+        self.weights
+            .iter()
+            .enumerate()
+            .map(|(j, weight)| (self.known_matches + j, self.ratio(weight.clone())))
+    }
+
+    #[must_use]
+    pub fn expected_count(&self) -> BigRational {
+        self.iter()
+            .map(|(count, probability)| probability * BigInt::from(count))
+            .sum()
+    }
+}
+
+fn pow(base: &BigUint, exp: usize) -> BigUint {
+    num_traits::pow(base.clone(), exp)
+}
+
+/// Exact probability that `bet` is true, i.e. that [`Bet::is_fluff`] is false, for a viewer with
+/// `own_rolls` who can not see `unknown_dice` other dice
+#[must_use]
+pub fn bet_probability(
+    bet: &Bet,
+    own_rolls: &[NonZeroUsize],
+    unknown_dice: usize,
+    max_roll: NonZeroUsize,
+    wild_rule: WildRule,
+) -> BigRational {
+    MatchDistribution::new(bet.roll, own_rolls, unknown_dice, max_roll, wild_rule)
+        .probability_at_least(bet.count.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+
+    fn rolls(x: &[usize]) -> Vec<NonZeroUsize> {
+        x.iter().map(|x| NonZeroUsize::new(*x).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_distribution_sums_to_one() {
+        let six = NonZeroUsize::new(6).unwrap();
+        for wild_rule in [WildRule::OnesWild, WildRule::NoWilds] {
+            let dist = MatchDistribution::new(six, &rolls(&[1, 6, 3]), 40, six, wild_rule);
+            let total: BigRational = dist.iter().map(|(_, x)| x).sum();
+            assert_eq!(total, BigRational::one());
+            assert_eq!(dist.probability_at_least(0), BigRational::one());
+        }
+    }
+
+    #[test]
+    fn test_bet_probability_against_enumeration() {
+        let max_roll = NonZeroUsize::new(4).unwrap();
+        let own_rolls = rolls(&[1, 3]);
+        let unknown_dice = 3;
+        for (wild_rule, count, roll) in
+            itertools::iproduct!([WildRule::OnesWild, WildRule::NoWilds], 1..=6, 1..=4)
+        {
+            let bet = Bet::new(
+                NonZeroUsize::new(count).unwrap(),
+                NonZeroUsize::new(roll).unwrap(),
+            );
+            let outcomes = (0..unknown_dice)
+                .map(|_| 1..=max_roll.get())
+                .multi_cartesian_product()
+                .collect::<Vec<_>>();
+            let true_outcomes = outcomes
+                .iter()
+                .filter(|unknown| {
+                    let all_rolls = own_rolls.iter().copied().chain(rolls(unknown));
+                    bet.count_matches_with(all_rolls, wild_rule) >= count
+                })
+                .count();
+            assert_eq!(
+                bet_probability(&bet, &own_rolls, unknown_dice, max_roll, wild_rule),
+                BigRational::new(true_outcomes.into(), outcomes.len().into())
+            );
+        }
+    }
+}
@@ -0,0 +1,401 @@
+//! Commit and reveal for rolls, so nobody has to trust whoever runs the game with the dice
+//!
+//! Before a round, every player commits to a hash of a secret [`RollSeed`] and a [`NonceShare`].
+//! Once everyone has committed, they all reveal their shares, the [`RoundNonce`] is hashed from
+//! them, and each player's rolls are derived from their seed and the nonce. Since every share was
+//! fixed before any was revealed, nobody could have picked a seed or share for good rolls. After
+//! the round is called, everyone reveals their seed, and [`RoundCommitments::verify`] checks that
+//! the rolls the game used match the commitments and that the call was judged honestly.
+
+use std::num::NonZeroUsize;
+
+use indexmap::IndexMap;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    game::{
+        round::RollSet,
+        state::{Called, InRound, NewRound},
+        Game, PlayerRef, Round,
+    },
+    player::Player,
+};
+
+#[derive(Error, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub enum ProtocolError {
+    #[error("{0} is not playing this round")]
+    UnknownPlayer(Player),
+    #[error("{0} already committed to a seed")]
+    AlreadyCommitted(PlayerRef),
+    #[error("{0} has not committed to a seed yet")]
+    NotCommitted(PlayerRef),
+    #[error("Nonce shares are already being revealed, so nobody can commit anymore")]
+    SharesRevealed,
+    #[error("{0} already revealed their nonce share")]
+    AlreadyRevealed(PlayerRef),
+    #[error("Not every nonce share has been revealed yet")]
+    NoNonce,
+    #[error("{0} did not reveal their seed")]
+    NotRevealed(PlayerRef),
+    #[error("The seed and nonce share {0} revealed do not match their commitment")]
+    WrongSeed(PlayerRef),
+    #[error("The rolls of {0} in the round do not match their seed")]
+    WrongRolls(PlayerRef),
+    #[error("The round was judged as {}, but the revealed rolls say otherwise", if *.claimed { "fluff" } else { "not fluff" })]
+    WrongCall { claimed: bool },
+}
+
+/// The secret a player's rolls for a round are derived from
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub struct RollSeed(#[serde(with = "hex::serde")] [u8; 32]);
+
+/// A player's part of the [`RoundNonce`], kept secret until everyone has committed
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub struct NonceShare(#[serde(with = "hex::serde")] [u8; 32]);
+
+/// The hash a player publishes of their [`RollSeed`] and [`NonceShare`] before the round
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub struct Commitment(#[serde(with = "hex::serde")] [u8; 32]);
+
+/// Randomness hashed from everyone's [`NonceShare`], which every player's rolls also depend on
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub struct RoundNonce(#[serde(with = "hex::serde")] [u8; 32]);
+
+impl RollSeed {
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Self(rng.gen())
+    }
+
+    /// The commitment to this seed and `share` for `player` in round `round_number`, so it can't
+    /// be reused for another player or round
+    #[must_use]
+    pub fn commit(&self, share: &NonceShare, player: &Player, round_number: usize) -> Commitment {
+        let mut hasher = Sha256::new();
+        hasher.update(b"fluff roll seed");
+        hasher.update((round_number as u64).to_le_bytes());
+        hasher.update((player.as_str().len() as u64).to_le_bytes());
+        hasher.update(player.as_str());
+        hasher.update(self.0);
+        hasher.update(share.0);
+        Commitment(hasher.finalize().into())
+    }
+
+    /// The `dice` rolls this seed gives with `nonce`, each between 1 and `max_roll`
+    ///
+    /// Every roll hashes the seed, nonce and its index, rejecting hashes that would make some
+    /// rolls more likely than others.
+    #[must_use]
+    pub fn rolls(&self, nonce: &RoundNonce, dice: usize, max_roll: NonZeroUsize) -> RollSet {
+        let sides = max_roll.get() as u64;
+        let limit = u64::MAX - u64::MAX % sides;
+        (0..dice as u64)
+            .map(|index| {
+                (0u64..)
+                    .find_map(|attempt| {
+                        let hash = Sha256::new()
+                            .chain_update(self.0)
+                            .chain_update(nonce.0)
+                            .chain_update(index.to_le_bytes())
+                            .chain_update(attempt.to_le_bytes())
+                            .finalize();
+                        let value = u64::from_le_bytes(
+                            hash[..8].try_into().expect("Hash should have 8 bytes"),
+                        );
+                        (value < limit).then(|| value % sides)
+                    })
+                    .and_then(|x| NonZeroUsize::new(x as usize + 1))
+                    .expect("A roll should eventually be accepted")
+            })
+            .collect()
+    }
+}
+
+impl NonceShare {
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Self(rng.gen())
+    }
+}
+
+/// Everyone's commitments for one round, and the nonce shares revealed once they are all in
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct RoundCommitments {
+    round_number: usize,
+    max_roll: NonZeroUsize,
+    dice_counts: IndexMap<PlayerRef, usize>,
+    commitments: IndexMap<PlayerRef, Commitment>,
+    shares: IndexMap<PlayerRef, NonceShare>,
+}
+
+impl RoundCommitments {
+    /// Commitments for the round `game` is about to play
+    #[must_use]
+    pub fn new(game: &Game<InRound<NewRound>>) -> Self {
+        Self {
+            round_number: game.round_number(),
+            max_roll: game.config().max_roll(),
+            dice_counts: game
+                .player_dice_counts()
+                .iter()
+                .filter(|(_, x)| **x != 0)
+                .map(|(player, x)| (player.clone(), *x))
+                .collect(),
+            commitments: IndexMap::new(),
+            shares: IndexMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub const fn round_number(&self) -> usize {
+        self.round_number
+    }
+
+    /// The nonce hashed from every player's share, once they have all been revealed
+    #[must_use]
+    pub fn nonce(&self) -> Option<RoundNonce> {
+        let mut hasher = Sha256::new();
+        hasher.update(b"fluff round nonce");
+        hasher.update((self.round_number as u64).to_le_bytes());
+        for player in self.dice_counts.keys() {
+            hasher.update(self.shares.get(player)?.0);
+        }
+        Some(RoundNonce(hasher.finalize().into()))
+    }
+
+    /// Whoever has yet to commit
+    pub fn missing(&self) -> impl Iterator<Item = &PlayerRef> {
+        self.dice_counts
+            .keys()
+            .filter(|x| !self.commitments.contains_key(*x))
+    }
+
+    pub fn commit(&mut self, player: &Player, commitment: Commitment) -> Result<(), ProtocolError> {
+        if !self.shares.is_empty() {
+            return Err(ProtocolError::SharesRevealed);
+        }
+        let (player, _) = self
+            .dice_counts
+            .get_key_value(player)
+            .ok_or_else(|| ProtocolError::UnknownPlayer(player.clone()))?;
+        if self.commitments.contains_key(player) {
+            return Err(ProtocolError::AlreadyCommitted(player.clone()));
+        }
+        self.commitments.insert(player.clone(), commitment);
+        Ok(())
+    }
+
+    /// Records the nonce share of `player`, which can only be revealed once every player has
+    /// committed
+    ///
+    /// Whether the share matches their commitment is only checked once their seed is revealed.
+    pub fn reveal_share(
+        &mut self,
+        player: &Player,
+        share: NonceShare,
+    ) -> Result<(), ProtocolError> {
+        if let Some(player) = self.missing().next() {
+            return Err(ProtocolError::NotCommitted(player.clone()));
+        }
+        let (player, _) = self
+            .dice_counts
+            .get_key_value(player)
+            .ok_or_else(|| ProtocolError::UnknownPlayer(player.clone()))?;
+        if self.shares.contains_key(player) {
+            return Err(ProtocolError::AlreadyRevealed(player.clone()));
+        }
+        self.shares.insert(player.clone(), share);
+        Ok(())
+    }
+
+    /// The rolls of `player` from their `seed`, checking it and their share against their
+    /// commitment
+    pub fn rolls_of(&self, player: &Player, seed: &RollSeed) -> Result<RollSet, ProtocolError> {
+        let nonce = self.nonce().ok_or(ProtocolError::NoNonce)?;
+        let (player, dice) = self
+            .dice_counts
+            .get_key_value(player)
+            .ok_or_else(|| ProtocolError::UnknownPlayer(player.clone()))?;
+        let commitment = self
+            .commitments
+            .get(player)
+            .ok_or_else(|| ProtocolError::NotCommitted(player.clone()))?;
+        let share = &self.shares[player];
+        if seed.commit(share, player, self.round_number) != *commitment {
+            return Err(ProtocolError::WrongSeed(player.clone()));
+        }
+        Ok(seed.rolls(&nonce, *dice, self.max_roll))
+    }
+
+    /// Every player's rolls from their revealed seeds, e.g. for [`Game::set_rolls`]
+    pub fn rolls(
+        &self,
+        seeds: &IndexMap<PlayerRef, RollSeed>,
+    ) -> Result<IndexMap<PlayerRef, RollSet>, ProtocolError> {
+        self.dice_counts
+            .keys()
+            .map(|player| {
+                let seed = seeds
+                    .get(player)
+                    .ok_or_else(|| ProtocolError::NotRevealed(player.clone()))?;
+                Ok((player.clone(), self.rolls_of(player, seed)?))
+            })
+            .collect()
+    }
+
+    /// Checks that `round` was played with the rolls everyone committed to, and that the call at
+    /// the end of it was judged by those rolls
+    ///
+    /// The nonce is hashed again from the revealed shares, so it can't have been swapped out.
+    pub fn verify(
+        &self,
+        round: &Round<Called>,
+        seeds: &IndexMap<PlayerRef, RollSeed>,
+    ) -> Result<(), ProtocolError> {
+        let rolls = self.rolls(seeds)?;
+        if let Some(player) = round
+            .players_rolls()
+            .keys()
+            .find(|x| !rolls.contains_key(*x))
+        {
+            return Err(ProtocolError::WrongRolls(player.clone()));
+        }
+        for (player, rolls) in &rolls {
+            if round.players_rolls().get(player) != Some(rolls) {
+                return Err(ProtocolError::WrongRolls(player.clone()));
+            }
+        }
+        let bet = round
+            .last_bet()
+            .expect("A called round should have a bet to call");
+        let was_fluff = bet.is_fluff(rolls.values().flat_map(|x| x.iter().copied()));
+        let claimed = round.state_data().was_fluff;
+        if was_fluff != claimed {
+            return Err(ProtocolError::WrongCall { claimed });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        bet::Bet,
+        game::{FluffCallTransition, GameConfig},
+    };
+
+    #[test]
+    fn test_commit_and_reveal() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let players = ["A", "B", "C"].map(Player::new);
+        let mut game = Game::new_with_rng(players.clone(), GameConfig::default(), &mut rng);
+        let mut commitments = RoundCommitments::new(&game);
+        let seeds = players
+            .iter()
+            .map(|x| (PlayerRef::new(x.clone()), RollSeed::random(&mut rng)))
+            .collect::<IndexMap<_, _>>();
+        let shares = seeds
+            .keys()
+            .map(|x| (x.clone(), NonceShare::random(&mut rng)))
+            .collect::<IndexMap<_, _>>();
+        let commit = |commitments: &mut RoundCommitments, player: &PlayerRef| {
+            let commitment = seeds[player].commit(&shares[player], player, 1);
+            commitments.commit(player, commitment)
+        };
+        for player in seeds.keys().take(2) {
+            commit(&mut commitments, player).unwrap();
+        }
+        let [a, _, c] = players.map(PlayerRef::new);
+        assert_eq!(
+            commitments.reveal_share(&a, shares[&a]),
+            Err(ProtocolError::NotCommitted(c.clone()))
+        );
+        // a commitment meant for another player doesn't hold for C
+        commitments
+            .commit(&c, seeds[&c].commit(&shares[&c], &a, 1))
+            .unwrap();
+        for (player, share) in &shares {
+            commitments.reveal_share(player, *share).unwrap();
+        }
+        let nonce = commitments.nonce().unwrap();
+        assert_eq!(
+            commitments.commit(&c, seeds[&c].commit(&shares[&c], &c, 1)),
+            Err(ProtocolError::SharesRevealed)
+        );
+        assert_eq!(
+            commitments.reveal_share(&a, shares[&a]),
+            Err(ProtocolError::AlreadyRevealed(a.clone()))
+        );
+        assert_eq!(
+            commitments.rolls(&seeds),
+            Err(ProtocolError::WrongSeed(c.clone()))
+        );
+
+        let mut commitments = RoundCommitments::new(&game);
+        for player in seeds.keys() {
+            commit(&mut commitments, player).unwrap();
+        }
+        assert!(commitments.missing().next().is_none());
+        for (player, share) in shares.iter().take(2) {
+            commitments.reveal_share(player, *share).unwrap();
+        }
+        assert_eq!(commitments.nonce(), None);
+        assert_eq!(commitments.rolls(&seeds), Err(ProtocolError::NoNonce));
+        // a share other than the committed one changes the nonce, but is caught with the seed
+        let mut swapped = commitments.clone();
+        swapped
+            .reveal_share(&c, NonceShare::random(&mut rng))
+            .unwrap();
+        commitments.reveal_share(&c, shares[&c]).unwrap();
+        assert_eq!(commitments.nonce(), Some(nonce));
+        assert_ne!(swapped.nonce(), commitments.nonce());
+        assert_eq!(
+            swapped.rolls(&seeds),
+            Err(ProtocolError::WrongSeed(c.clone()))
+        );
+        let rolls = commitments.rolls(&seeds).unwrap();
+        assert!(rolls.values().flat_map(|x| x.iter()).all(|x| x.get() <= 6));
+        let unverified = game.clone();
+        game.set_rolls(rolls).unwrap();
+
+        let bet = Bet::new(NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(3).unwrap());
+        let FluffCallTransition::NextRound(next) =
+            game.raise_bet(bet).call_fluff_with_rng(&mut rng)
+        else {
+            panic!("One call shouldn't end a game of 3 players with 5 dice each");
+        };
+        let round = next.round_history().last().unwrap();
+        assert_eq!(commitments.verify(round, &seeds), Ok(()));
+
+        // dice rolled some other way are caught
+        let FluffCallTransition::NextRound(next) =
+            unverified.raise_bet(bet).call_fluff_with_rng(&mut rng)
+        else {
+            panic!("One call shouldn't end a game of 3 players with 5 dice each");
+        };
+        assert_eq!(
+            commitments.verify(next.round_history().last().unwrap(), &seeds),
+            Err(ProtocolError::WrongRolls(a))
+        );
+
+        // a round judged the other way is caught
+        let mut tampered = serde_json::to_value(round).unwrap();
+        let claimed = !round.state_data().was_fluff;
+        tampered["state_data"]["was_fluff"] = claimed.into();
+        let tampered = serde_json::from_value(tampered).unwrap();
+        assert_eq!(
+            commitments.verify(&tampered, &seeds),
+            Err(ProtocolError::WrongCall { claimed })
+        );
+        // so are rolls that don't come from the seeds
+        let mut other_seeds = seeds.clone();
+        other_seeds[&c] = RollSeed::random(&mut rng);
+        assert_eq!(
+            commitments.verify(round, &other_seeds),
+            Err(ProtocolError::WrongSeed(c))
+        );
+    }
+}
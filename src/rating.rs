@@ -0,0 +1,228 @@
+//! Skill ratings for free-for-all games, using the Bradley-Terry model of Weng and Lin's
+//! "A Bayesian Approximation Method for Online Ranking", which treats a finishing order as every
+//! player having beaten everyone who finished behind them
+
+use std::{fs::File, io::BufReader, path::Path};
+
+use indexmap::IndexMap;
+
+use crate::{
+    game::{state::GameOver, Game},
+    player::Player,
+};
+
+const DEFAULT_MU: f64 = 25.0;
+const DEFAULT_SIGMA: f64 = DEFAULT_MU / 3.0;
+/// Lower bound on how much a single game can shrink the variance of a rating
+const KAPPA: f64 = 0.0001;
+
+/// A player's estimated skill, which is normally distributed with mean `mu` and standard
+/// deviation `sigma`
+#[derive(Debug, Serialize, Deserialize, PartialEq, Copy, Clone)]
+pub struct Rating {
+    pub mu: f64,
+    pub sigma: f64,
+    pub games: usize,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            mu: DEFAULT_MU,
+            sigma: DEFAULT_SIGMA,
+            games: 0,
+        }
+    }
+}
+
+impl Rating {
+    /// A skill the player is very likely to have at least, for ranking players without
+    /// favouring the ones with few games
+    #[must_use]
+    pub fn conservative(&self) -> f64 {
+        self.mu - 3.0 * self.sigma
+    }
+}
+
+/// Ratings of every player seen so far
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Ratings {
+    /// Spread of performances around a player's skill in a single game
+    pub beta: f64,
+    /// Uncertainty added to every rating before a game, so that ratings can follow players whose
+    /// skill changes over time
+    pub tau: f64,
+    players: IndexMap<Player, Rating>,
+}
+
+impl Default for Ratings {
+    fn default() -> Self {
+        Self {
+            beta: DEFAULT_SIGMA / 2.0,
+            tau: DEFAULT_SIGMA / 100.0,
+            players: IndexMap::new(),
+        }
+    }
+}
+
+impl Ratings {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rating of `player`, which is the default one for players without any games
+    #[must_use]
+    pub fn rating(&self, player: &Player) -> Rating {
+        self.players.get(player).copied().unwrap_or_default()
+    }
+
+    /// Every rated player, from the highest [`Rating::conservative`] rating to the lowest
+    #[must_use]
+    pub fn leaderboard(&self) -> Vec<(&Player, &Rating)> {
+        let mut leaderboard = self.players.iter().collect::<Vec<_>>();
+        leaderboard.sort_by(|a, b| b.1.conservative().total_cmp(&a.1.conservative()));
+        leaderboard
+    }
+
+    /// Probability of `a` finishing ahead of `b`
+    fn beats(&self, a: &Rating, b: &Rating) -> f64 {
+        let c = self.spread(a, b);
+        1.0 / (1.0 + ((b.mu - a.mu) / c).exp())
+    }
+
+    fn spread(&self, a: &Rating, b: &Rating) -> f64 {
+        (a.sigma.powi(2) + b.sigma.powi(2) + 2.0 * self.beta.powi(2)).sqrt()
+    }
+
+    /// Updates the ratings of everyone in `finishing_order`, from the winner to the first player
+    /// knocked out
+    pub fn update(&mut self, finishing_order: &[Player]) {
+        let before = finishing_order
+            .iter()
+            .map(|player| {
+                let rating = self.rating(player);
+                Rating {
+                    sigma: (rating.sigma.powi(2) + self.tau.powi(2)).sqrt(),
+                    ..rating
+                }
+            })
+            .collect::<Vec<_>>();
+        for (i, player) in finishing_order.iter().enumerate() {
+            let rating = before[i];
+            let variance = rating.sigma.powi(2);
+            let (mut omega, mut delta) = (0.0, 0.0);
+            for (q, other) in before.iter().enumerate() {
+                if q == i {
+                    continue;
+                }
+                let c = self.spread(&rating, other);
+                let p = self.beats(&rating, other);
+                let score = if i < q { 1.0 } else { 0.0 };
+                omega += variance / c * (score - p);
+                let gamma = rating.sigma / c;
+                delta += gamma * variance / c.powi(2) * p * (1.0 - p);
+            }
+            self.players.insert(
+                player.clone(),
+                Rating {
+                    mu: rating.mu + omega,
+                    sigma: (variance * (1.0 - delta).max(KAPPA)).sqrt(),
+                    games: rating.games + 1,
+                },
+            );
+        }
+    }
+
+    /// Updates the ratings of everyone who played `game`
+    pub fn record(&mut self, game: &Game<GameOver>) {
+        let finishing_order = game
+            .finishing_order()
+            .iter()
+            .map(|x| (**x).clone())
+            .collect::<Vec<_>>();
+        self.update(&finishing_order);
+    }
+
+    /// Probability of each player in `table` winning a game between them
+    ///
+    /// Uses the Plackett-Luce model, where a player's chance of finishing first grows
+    /// exponentially with their skill, which for 2 players is the chance of one beating the other.
+    #[must_use]
+    pub fn predict_win(&self, table: &[Player]) -> Vec<f64> {
+        let ratings = table.iter().map(|x| self.rating(x)).collect::<Vec<_>>();
+        let c = ratings
+            .iter()
+            .map(|x| x.sigma.powi(2) + self.beta.powi(2))
+            .sum::<f64>()
+            .sqrt();
+        let max_mu = ratings
+            .iter()
+            .map(|x| x.mu)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let strengths = ratings
+            .iter()
+            .map(|x| ((x.mu - max_mu) / c).exp())
+            .collect::<Vec<_>>();
+        let total = strengths.iter().sum::<f64>();
+        strengths.into_iter().map(|x| x / total).collect()
+    }
+
+    /// Reads ratings saved with [`Ratings::save`]
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        Ok(serde_json::to_writer_pretty(File::create(path)?, self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn players() -> Vec<Player> {
+        ["Ann", "Bob", "Cat", "Dan"].map(Player::new).to_vec()
+    }
+
+    #[test]
+    fn test_update() {
+        let mut ratings = Ratings::new();
+        let players = players();
+        for _ in 0..20 {
+            ratings.update(&players);
+        }
+        let [first, second, third, last] = [0, 1, 2, 3].map(|i| ratings.rating(&players[i]));
+        assert!(first.mu > second.mu && second.mu > third.mu && third.mu > last.mu);
+        assert!(first.sigma < DEFAULT_SIGMA);
+        assert_eq!(first.games, 20);
+        assert_eq!(ratings.leaderboard()[0].0, &players[0]);
+        let predicted = ratings.predict_win(&players);
+        assert!((predicted.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(predicted[0] > predicted[1] && predicted[2] > predicted[3]);
+        let unknown = ratings.predict_win(&[Player::new("Eve"), Player::new("Fay")]);
+        assert_eq!(unknown, vec![0.5, 0.5]);
+        // between 2 players, winning is finishing ahead of the other
+        let (a, b) = (ratings.rating(&players[0]), ratings.rating(&players[3]));
+        let pair = ratings.predict_win(&[players[0].clone(), players[3].clone()]);
+        assert!((pair[0] - ratings.beats(&a, &b)).abs() < 1e-9);
+        // and a third player only takes away from both chances
+        let trio = ratings.predict_win(&players[..3]);
+        assert!(trio[0] < ratings.predict_win(&players[..2])[0]);
+    }
+
+    #[test]
+    fn test_persistence() {
+        let mut ratings = Ratings::new();
+        ratings.update(&players());
+        let path = std::env::temp_dir().join(format!("fluff-ratings-{}.json", std::process::id()));
+        ratings.save(&path).unwrap();
+        let loaded = Ratings::load(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        for player in players() {
+            let (a, b) = (ratings.rating(&player), loaded.rating(&player));
+            assert!((a.mu - b.mu).abs() < 1e-9 && (a.sigma - b.sigma).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,369 @@
+//! Serves [`Correspondence`] games over HTTP, for players who take their turns whenever they like
+//!
+//! Every body is JSON, and every request except registering needs an `Authorization: Bearer
+//! <token>` header with the token the player registered with.
+//!
+//! | Request | Body | Response |
+//! | --- | --- | --- |
+//! | `POST /players` | `{"name": ...}` | `{"token": ...}` |
+//! | `POST /games` | `{"players": [...], "config": ...}` | `{"id": ...}` |
+//! | `GET /games` | | every [`GameSummary`] of the player's games |
+//! | `GET /games/<id>` | | the player's [`PlayerView`] |
+//! | `POST /games/<id>/actions` | `{"action": ...}` | the player's new [`PlayerView`] |
+//! | `GET /games/<id>/history` | | every [`LogEvent`] the player may see |
+//!
+//! Errors are answered with `{"error": ...}`, and bodies over 64 KiB are refused. After every
+//! change, the games are saved to the file the API was opened with, and a change that could not be
+//! saved is undone.
+//!
+//! [`GameSummary`]: crate::correspondence::GameSummary
+//! [`PlayerView`]: crate::game::view::PlayerView
+//! [`LogEvent`]: crate::game::log::LogEvent
+
+use std::{
+    io::{ErrorKind, Read},
+    path::PathBuf,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    correspondence::{Correspondence, CorrespondenceError},
+    game::{session::Action, GameConfig},
+    host::GameId,
+    player::Player,
+};
+
+/// Largest request body that is read, in bytes
+const MAX_BODY: u64 = 64 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+struct Registration {
+    name: Player,
+}
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+struct NewGame {
+    players: Vec<Player>,
+    #[serde(default)]
+    config: GameConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+struct PostedAction {
+    action: Action,
+}
+
+/// A response before it is sent, so the API can be used without any HTTP
+#[derive(Debug, PartialEq, Clone)]
+pub struct Reply {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Reply {
+    fn ok(body: impl Serialize) -> Self {
+        Self::with_status(200, body)
+    }
+
+    fn with_status(status: u16, body: impl Serialize) -> Self {
+        Self {
+            status,
+            body: serde_json::to_value(body).expect("Replies should serialize"),
+        }
+    }
+
+    fn error(status: u16, message: impl ToString) -> Self {
+        Self::with_status(status, json!({ "error": message.to_string() }))
+    }
+}
+
+impl From<CorrespondenceError> for Reply {
+    fn from(value: CorrespondenceError) -> Self {
+        let status = match value {
+            CorrespondenceError::UnknownToken => 401,
+            CorrespondenceError::NotInGame(_) => 403,
+            CorrespondenceError::UnknownGame(_) => 404,
+            CorrespondenceError::AlreadyRegistered(_) | CorrespondenceError::NotYourTurn(_) => 409,
+            CorrespondenceError::UnknownPlayer(_)
+            | CorrespondenceError::InvalidPlayers
+            | CorrespondenceError::Action(_) => 422,
+        };
+        Self::error(status, value)
+    }
+}
+
+fn parse<T: DeserializeOwned>(body: &str) -> Result<T, Reply> {
+    serde_json::from_str(body).map_err(|err| Reply::error(400, err))
+}
+
+/// Reads a request body of at most [`MAX_BODY`] bytes
+fn read_body(reader: impl Read) -> Result<String, Reply> {
+    let mut body = Vec::new();
+    reader
+        .take(MAX_BODY + 1)
+        .read_to_end(&mut body)
+        .map_err(|err| Reply::error(400, err))?;
+    if body.len() as u64 > MAX_BODY {
+        return Err(Reply::error(413, "The body is too large"));
+    }
+    String::from_utf8(body).map_err(|err| Reply::error(400, err))
+}
+
+/// Games answering requests one at a time, saved to a file after every change
+#[derive(Debug)]
+pub struct RestApi {
+    games: Correspondence,
+    path: Option<PathBuf>,
+}
+
+impl RestApi {
+    /// Keeps the games only in memory
+    #[must_use]
+    pub fn new(games: Correspondence) -> Self {
+        Self { games, path: None }
+    }
+
+    /// Loads the games saved at `path`, or starts without any if there is no such file
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let games = match Correspondence::load(&path) {
+            Ok(games) => games,
+            Err(err) if err.kind() == ErrorKind::NotFound => Correspondence::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            games,
+            path: Some(path),
+        })
+    }
+
+    #[must_use]
+    pub fn games(&self) -> &Correspondence {
+        &self.games
+    }
+
+    /// Answers a single request, where `token` is the bearer token it came with
+    pub fn handle(&mut self, method: &Method, url: &str, token: Option<&str>, body: &str) -> Reply {
+        match self.route(method, url, token.unwrap_or_default(), body) {
+            Ok(reply) | Err(reply) => reply,
+        }
+    }
+
+    fn route(
+        &mut self,
+        method: &Method,
+        url: &str,
+        token: &str,
+        body: &str,
+    ) -> Result<Reply, Reply> {
+        let path = url.split('?').next().unwrap_or_default();
+        let segments = path
+            .split('/')
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>();
+        let id = |segment: &str| -> Result<GameId, Reply> {
+            segment
+                .parse()
+                .map_err(|_| Reply::error(404, "Game ids are numbers"))
+        };
+        Ok(match (method, segments.as_slice()) {
+            (Method::Post, ["players"]) => {
+                let Registration { name } = parse(body)?;
+                let token = self.change(|games| games.register(name))?;
+                Reply::with_status(201, json!({ "token": token }))
+            }
+            (Method::Post, ["games"]) => {
+                let NewGame { players, config } = parse(body)?;
+                let id = self.change(|games| games.create_game(token, players, config))?;
+                Reply::with_status(201, json!({ "id": id }))
+            }
+            (Method::Get, ["games"]) => Reply::ok(self.games.games(token)?),
+            (Method::Get, ["games", game]) => Reply::ok(self.games.view(token, id(game)?)?),
+            (Method::Post, ["games", game, "actions"]) => {
+                let PostedAction { action } = parse(body)?;
+                let id = id(game)?;
+                let view = self.change(|games| games.act(token, id, action))?;
+                Reply::ok(view)
+            }
+            (Method::Get, ["games", game, "history"]) => {
+                Reply::ok(self.games.history(token, id(game)?)?)
+            }
+            (_, ["players"] | ["games", ..]) => Reply::error(405, "Method not allowed"),
+            _ => Reply::error(404, "Not found"),
+        })
+    }
+
+    /// Applies `change` to the games, keeping it only once it has been saved
+    fn change<T>(
+        &mut self,
+        change: impl FnOnce(&mut Correspondence) -> Result<T, CorrespondenceError>,
+    ) -> Result<T, Reply> {
+        let Some(path) = &self.path else {
+            return Ok(change(&mut self.games)?);
+        };
+        let mut games = self.games.clone();
+        let value = change(&mut games)?;
+        games.save(path).map_err(|err| Reply::error(500, err))?;
+        self.games = games;
+        Ok(value)
+    }
+
+    fn respond(&mut self, mut request: Request) -> std::io::Result<()> {
+        let reply = match read_body(request.as_reader()) {
+            Ok(body) => {
+                let token = request
+                    .headers()
+                    .iter()
+                    .find(|x| x.field.equiv("Authorization"))
+                    .and_then(|x| x.value.as_str().strip_prefix("Bearer "))
+                    .map(str::to_owned);
+                self.handle(request.method(), request.url(), token.as_deref(), &body)
+            }
+            Err(reply) => reply,
+        };
+        let content_type = Header::from_bytes("Content-Type", "application/json")
+            .expect("Content type header should be valid");
+        request.respond(
+            Response::from_string(reply.body.to_string())
+                .with_status_code(reply.status)
+                .with_header(content_type),
+        )
+    }
+
+    /// Answers every request to `server` in turn, until receiving one fails
+    pub fn serve(&mut self, server: &Server) -> std::io::Result<()> {
+        loop {
+            let request = server.recv()?;
+            // a failed response only matters to its own client
+            let _ = self.respond(request);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{Shutdown, TcpStream},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_api() {
+        let mut api = RestApi::new(Correspondence::new());
+        let mut register = |name: &str| {
+            let reply = api.handle(
+                &Method::Post,
+                "/players",
+                None,
+                &json!({ "name": name }).to_string(),
+            );
+            assert_eq!(reply.status, 201);
+            reply.body["token"].as_str().unwrap().to_owned()
+        };
+        let [a, b] = ["A", "B"].map(&mut register);
+        let reply = api.handle(
+            &Method::Post,
+            "/games",
+            Some(&a),
+            &json!({ "players": ["A", "B"] }).to_string(),
+        );
+        assert_eq!(reply.status, 201);
+        let id = reply.body["id"].as_u64().unwrap();
+
+        let reply = api.handle(&Method::Get, &format!("/games/{id}"), Some(&a), "");
+        assert_eq!(reply.body["current_player"], "A");
+        let action = json!({ "action": { "Raise": { "count": 1, "roll": 1 } } }).to_string();
+        let url = format!("/games/{id}/actions");
+        assert_eq!(
+            api.handle(&Method::Post, &url, Some(&b), &action).status,
+            409
+        );
+        assert_eq!(
+            api.handle(&Method::Post, &url, Some(&a), &action).status,
+            200
+        );
+        assert_eq!(
+            api.handle(&Method::Post, &url, Some(&a), "not json").status,
+            400
+        );
+        assert_eq!(
+            api.handle(&Method::Get, "/games", Some(&b), "").body[0]["my_turn"],
+            true
+        );
+        assert_eq!(
+            api.handle(&Method::Get, "/games", Some("forged"), "")
+                .status,
+            401
+        );
+        let history = api.handle(&Method::Get, &format!("/games/{id}/history"), Some(&b), "");
+        assert_eq!(history.body.as_array().unwrap().len(), 2);
+        assert_eq!(
+            api.handle(&Method::Delete, "/games", Some(&a), "").status,
+            405
+        );
+    }
+
+    #[test]
+    fn test_http() {
+        let path = std::env::temp_dir().join(format!("fluff-rest-{}.json", std::process::id()));
+        let mut api = RestApi::open(&path).unwrap();
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let body = json!({ "name": "A" }).to_string();
+            write!(
+                stream,
+                "POST /players HTTP/1.1\r\nHost: {addr}\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        api.respond(server.recv().unwrap()).unwrap();
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 201"));
+        assert!(response.contains("\"token\""));
+        // the new player was saved
+        assert_eq!(Correspondence::load(&path).unwrap(), *api.games());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_save() {
+        let path = std::env::temp_dir()
+            .join(format!("fluff-rest-missing-{}", std::process::id()))
+            .join("games.json");
+        let mut api = RestApi {
+            games: Correspondence::new(),
+            path: Some(path),
+        };
+        let body = json!({ "name": "A" }).to_string();
+        assert_eq!(
+            api.handle(&Method::Post, "/players", None, &body).status,
+            500
+        );
+        // the player is not registered, so registering again fails the same way
+        assert_eq!(*api.games(), Correspondence::new());
+        assert_eq!(
+            api.handle(&Method::Post, "/players", None, &body).status,
+            500
+        );
+    }
+
+    #[test]
+    fn test_body_limit() {
+        assert_eq!(read_body("{}".as_bytes()), Ok("{}".to_owned()));
+        let body = vec![b' '; MAX_BODY as usize + 1];
+        assert_eq!(read_body(body.as_slice()).unwrap_err().status, 413);
+    }
+}
@@ -0,0 +1,544 @@
+//! Keeps players' rolls secret in saved games and logs, so nobody can peek at them in a save file
+//!
+//! Every player who picks a passphrase gets a key derived from it with Argon2, and their rolls are
+//! only ever saved encrypted with that key. A loaded game only opens a player's rolls once they
+//! unlock them with their passphrase, which they would do at the start of their turn. Players
+//! without a passphrase, such as bots, have their rolls saved in the clear.
+//!
+//! Sealed rolls are bound to a random id of their game, along with their player and round, so they
+//! can not be moved into another game even if it uses the same passphrases.
+
+use std::{
+    fmt::{Debug, Formatter},
+    num::NonZeroUsize,
+};
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, Payload},
+    ChaCha20Poly1305, KeyInit,
+};
+use indexmap::IndexMap;
+use rand::Rng;
+use serde::{ser::Error as _, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::{
+    game::{
+        log::GameLog,
+        round::RollSet,
+        session::{Action, ActionError, GameSession},
+        view::PlayerView,
+        PlayerRef,
+    },
+    player::Player,
+};
+
+#[derive(Error, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub enum SealError {
+    #[error("{0} has no passphrase")]
+    NoPassphrase(Player),
+    #[error("Wrong passphrase for {0}")]
+    WrongPassphrase(Player),
+    #[error("{0} has to unlock their rolls first")]
+    Locked(PlayerRef),
+    #[error("A seeded log can not be sealed, since its seed gives away every roll")]
+    Seeded,
+    #[error("The sealed rolls of {0} were tampered with")]
+    Tampered(PlayerRef),
+    #[error(transparent)]
+    Action(#[from] ActionError),
+}
+
+/// Rolls encrypted with their player's key, for one round
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct SealedRolls {
+    #[serde(with = "hex::serde")]
+    nonce: [u8; 12],
+    #[serde(with = "hex::serde")]
+    ciphertext: Vec<u8>,
+}
+
+#[derive(Clone)]
+struct PlayerKey(ChaCha20Poly1305);
+
+impl Debug for PlayerKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PlayerKey(..)")
+    }
+}
+
+impl PlayerKey {
+    fn derive(passphrase: &str, salt: &[u8; 16]) -> Self {
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .expect("Argon2 should accept a 16 byte salt and a 32 byte key");
+        Self(ChaCha20Poly1305::new(&key.into()))
+    }
+
+    /// Seals `rolls` so they only open again for the same `context`, e.g. the same player and round
+    fn seal(&self, rolls: &RollSet, context: &str) -> SealedRolls {
+        let nonce: [u8; 12] = rand::thread_rng().gen();
+        let plaintext = serde_json::to_vec(rolls).expect("Rolls should serialize");
+        let ciphertext = self
+            .0
+            .encrypt(
+                &nonce.into(),
+                Payload {
+                    msg: &plaintext,
+                    aad: context.as_bytes(),
+                },
+            )
+            .expect("Encrypting a few rolls should not fail");
+        SealedRolls { nonce, ciphertext }
+    }
+
+    fn open(&self, sealed: &SealedRolls, context: &str) -> Option<RollSet> {
+        let plaintext = self
+            .0
+            .decrypt(
+                &sealed.nonce.into(),
+                Payload {
+                    msg: &sealed.ciphertext,
+                    aad: context.as_bytes(),
+                },
+            )
+            .ok()?;
+        serde_json::from_slice(&plaintext).ok()
+    }
+}
+
+/// What is kept of a passphrase: the salt its key is derived with, and something sealed with that
+/// key to check passphrases against
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+struct Lock {
+    #[serde(with = "hex::serde")]
+    salt: [u8; 16],
+    check: SealedRolls,
+}
+
+/// Random id of a sealed game, which every seal in it is bound to
+type SealedGameId = [u8; 16];
+
+fn context(game: &SealedGameId, player: &Player, round: usize) -> String {
+    format!("{}/{player}/{round}", hex::encode(game))
+}
+
+fn check_context(player: &Player) -> String {
+    format!("{player}/check")
+}
+
+/// Stands in for sealed rolls, so the game still knows how many dice were rolled
+fn placeholder(len: usize) -> RollSet {
+    vec![NonZeroUsize::MIN; len].into()
+}
+
+/// Every player's passphrase, of which only the salt ever gets saved
+///
+/// Keys are only known for players who set or unlocked their passphrase since the keyring was
+/// loaded.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Keyring {
+    locks: IndexMap<PlayerRef, Lock>,
+    #[serde(skip)]
+    keys: IndexMap<PlayerRef, PlayerKey>,
+}
+
+impl Keyring {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gives `player` a new passphrase, replacing any earlier one
+    pub fn set_passphrase(&mut self, player: PlayerRef, passphrase: &str) {
+        let salt = rand::thread_rng().gen();
+        let key = PlayerKey::derive(passphrase, &salt);
+        let check = key.seal(&placeholder(0), &check_context(&player));
+        self.locks.insert(player.clone(), Lock { salt, check });
+        self.keys.insert(player, key);
+    }
+
+    #[must_use]
+    pub fn has_passphrase(&self, player: &Player) -> bool {
+        self.locks.contains_key(player)
+    }
+
+    /// Whether `player` has a passphrase they haven't unlocked yet
+    #[must_use]
+    pub fn is_locked(&self, player: &Player) -> bool {
+        self.has_passphrase(player) && !self.keys.contains_key(player)
+    }
+
+    /// Checks `passphrase` against the one `player` set, remembering their key if it matches
+    pub fn unlock(&mut self, player: &Player, passphrase: &str) -> Result<(), SealError> {
+        let (player, lock) = self
+            .locks
+            .get_key_value(player)
+            .ok_or_else(|| SealError::NoPassphrase(player.clone()))?;
+        let key = PlayerKey::derive(passphrase, &lock.salt);
+        if key.open(&lock.check, &check_context(player)).is_none() {
+            return Err(SealError::WrongPassphrase((**player).clone()));
+        }
+        self.keys.insert(player.clone(), key);
+        Ok(())
+    }
+
+    /// The key of `player`, or [`None`] if they have no passphrase and their rolls stay in the
+    /// clear
+    fn key(&self, player: &PlayerRef) -> Result<Option<&PlayerKey>, SealError> {
+        match self.keys.get(player) {
+            Some(key) => Ok(Some(key)),
+            None if self.has_passphrase(player) => Err(SealError::Locked(player.clone())),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A log with the rolls of every player who has a passphrase sealed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SealedLog {
+    #[serde(with = "hex::serde")]
+    id: SealedGameId,
+    keyring: Keyring,
+    log: GameLog,
+    /// Sealed rolls of each round, in the order the rounds started
+    sealed: Vec<IndexMap<PlayerRef, SealedRolls>>,
+}
+
+impl SealedLog {
+    /// Seals `log`, which needs the key of every player with a passphrase
+    pub fn new(log: &GameLog, keyring: Keyring) -> Result<Self, SealError> {
+        if log.seed().is_some() {
+            return Err(SealError::Seeded);
+        }
+        let id = rand::thread_rng().gen();
+        let mut log = log.clone();
+        let mut sealed = Vec::new();
+        for (round, rolls) in log.round_rolls_mut().enumerate() {
+            let mut round_sealed = IndexMap::new();
+            for (player, rolls) in rolls.iter_mut() {
+                if let Some(key) = keyring.key(player)? {
+                    round_sealed.insert(
+                        player.clone(),
+                        key.seal(rolls, &context(&id, player, round)),
+                    );
+                    *rolls = placeholder(rolls.len());
+                }
+            }
+            sealed.push(round_sealed);
+        }
+        Ok(Self {
+            id,
+            keyring,
+            log,
+            sealed,
+        })
+    }
+
+    #[must_use]
+    pub fn keyring(&self) -> &Keyring {
+        &self.keyring
+    }
+
+    pub fn unlock(&mut self, player: &Player, passphrase: &str) -> Result<(), SealError> {
+        self.keyring.unlock(player, passphrase)
+    }
+
+    /// The log with every roll opened, once every player with a passphrase unlocked it
+    pub fn open(&self) -> Result<GameLog, SealError> {
+        let mut log = self.log.clone();
+        for ((round, rolls), sealed) in log.round_rolls_mut().enumerate().zip(&self.sealed) {
+            for (player, sealed) in sealed {
+                let key = self
+                    .keyring
+                    .key(player)?
+                    .ok_or_else(|| SealError::Tampered(player.clone()))?;
+                let opened = key
+                    .open(sealed, &context(&self.id, player, round))
+                    .ok_or_else(|| SealError::Tampered(player.clone()))?;
+                *rolls
+                    .get_mut(player)
+                    .ok_or_else(|| SealError::Tampered(player.clone()))? = opened;
+            }
+        }
+        Ok(log)
+    }
+}
+
+/// Rolls of the current round along with its number, or [`None`] if the game is over
+fn curr_rolls(session: &GameSession) -> Option<(usize, &IndexMap<PlayerRef, RollSet>)> {
+    match session {
+        GameSession::NewRound(g) => Some((g.round_number(), g.curr_round().players_rolls())),
+        GameSession::Betting(g) => Some((g.round_number(), g.curr_round().players_rolls())),
+        GameSession::GameOver(_) => None,
+    }
+}
+
+fn replace_rolls(session: &mut GameSession, player: &PlayerRef, rolls: RollSet) {
+    match session {
+        GameSession::NewRound(g) => g.replace_rolls(player, rolls),
+        GameSession::Betting(g) => g.replace_rolls(player, rolls),
+        GameSession::GameOver(_) => {}
+    }
+}
+
+/// A game whose rolls get sealed whenever it is saved, and that only opens a player's rolls once
+/// they unlock them
+///
+/// A player has to unlock their rolls before they can act, and every player has to before fluff
+/// can be called, since that reveals every roll.
+///
+/// Saving fails if a player with a passphrase has rolls that are neither sealed nor unlocked, which
+/// only a tampered save can lead to.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(from = "SavedSession")]
+pub struct SealedSession {
+    id: SealedGameId,
+    session: GameSession,
+    keyring: Keyring,
+    /// Sealed rolls of the current round that were not unlocked yet
+    locked: IndexMap<PlayerRef, SealedRolls>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedSession {
+    #[serde(with = "hex::serde")]
+    id: SealedGameId,
+    session: GameSession,
+    keyring: Keyring,
+    sealed: IndexMap<PlayerRef, SealedRolls>,
+}
+
+impl SealedSession {
+    /// The session as it is saved, with the rolls of every player who has a passphrase sealed
+    fn saved(&self) -> Result<SavedSession, SealError> {
+        let mut session = self.session.clone();
+        let mut sealed = self.locked.clone();
+        if let Some((round, rolls)) = curr_rolls(&self.session) {
+            for (player, rolls) in rolls {
+                if sealed.contains_key(player) {
+                    continue;
+                }
+                if let Some(key) = self.keyring.key(player)? {
+                    sealed.insert(
+                        player.clone(),
+                        key.seal(rolls, &context(&self.id, player, round)),
+                    );
+                    replace_rolls(&mut session, player, placeholder(rolls.len()));
+                }
+            }
+        }
+        Ok(SavedSession {
+            id: self.id,
+            session,
+            keyring: self.keyring.clone(),
+            sealed,
+        })
+    }
+}
+
+impl Serialize for SealedSession {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.saved()
+            .map_err(S::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl From<SavedSession> for SealedSession {
+    fn from(value: SavedSession) -> Self {
+        Self {
+            id: value.id,
+            session: value.session,
+            keyring: value.keyring,
+            locked: value.sealed,
+        }
+    }
+}
+
+impl SealedSession {
+    /// Starts keeping the rolls of `session` sealed, which needs the key of every player with a
+    /// passphrase
+    pub fn new(session: GameSession, keyring: Keyring) -> Result<Self, SealError> {
+        if let Some((_, rolls)) = curr_rolls(&session) {
+            for player in rolls.keys() {
+                keyring.key(player)?;
+            }
+        }
+        Ok(Self {
+            id: rand::thread_rng().gen(),
+            session,
+            keyring,
+            locked: IndexMap::new(),
+        })
+    }
+
+    /// The game, in which the rolls of players who are still locked are placeholders
+    #[must_use]
+    pub fn session(&self) -> &GameSession {
+        &self.session
+    }
+
+    #[must_use]
+    pub fn keyring(&self) -> &Keyring {
+        &self.keyring
+    }
+
+    /// Whether the rolls of `player` are still sealed
+    #[must_use]
+    pub fn is_locked(&self, player: &Player) -> bool {
+        self.locked.contains_key(player)
+    }
+
+    /// Checks the passphrase of `player` and opens their rolls if they are still sealed, which
+    /// they should do at the start of each of their turns
+    pub fn unlock(&mut self, player: &Player, passphrase: &str) -> Result<(), SealError> {
+        self.keyring.unlock(player, passphrase)?;
+        let Some((player, sealed)) = self.locked.swap_remove_entry(player) else {
+            return Ok(());
+        };
+        let round = curr_rolls(&self.session).map_or(0, |(x, _)| x);
+        let key = self
+            .keyring
+            .key(&player)?
+            .expect("Player was just unlocked");
+        let Some(rolls) = key.open(&sealed, &context(&self.id, &player, round)) else {
+            self.locked.insert(player.clone(), sealed);
+            return Err(SealError::Tampered(player));
+        };
+        replace_rolls(&mut self.session, &player, rolls);
+        Ok(())
+    }
+
+    pub fn view_for(&self, player: &PlayerRef) -> Result<PlayerView, SealError> {
+        if self.is_locked(player) {
+            return Err(SealError::Locked(player.clone()));
+        }
+        Ok(self.session.view_for(player))
+    }
+
+    /// Applies `action` for the current player, once they and, for a call, everyone else are
+    /// unlocked
+    pub fn act(&mut self, action: Action) -> Result<(), SealError> {
+        if let Some(current) = self.session.current_player() {
+            let locked = match action {
+                Action::CallFluff => self.locked.keys().next(),
+                Action::Raise(_) => self.locked.get_key_value(current).map(|(x, _)| x),
+            };
+            if let Some(player) = locked {
+                return Err(SealError::Locked(player.clone()));
+            }
+        }
+        self.session = self
+            .session
+            .clone()
+            .act(action)
+            .map_err(|rejected| rejected.error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bet::Bet,
+        game::{Game, GameConfig},
+    };
+
+    fn keyring(players: &[&str]) -> Keyring {
+        let mut keyring = Keyring::new();
+        for player in players {
+            keyring.set_passphrase(Player::new(*player).into(), &player.to_lowercase());
+        }
+        keyring
+    }
+
+    #[test]
+    fn test_sealed_session() {
+        let players = ["C", "A", "B"].map(Player::new);
+        let session = GameSession::from(Game::new(players.clone(), GameConfig::default()));
+        let sealed = SealedSession::new(session.clone(), keyring(&["A", "B"])).unwrap();
+        let saved = serde_json::to_string(&sealed).unwrap();
+        let mut loaded: SealedSession = serde_json::from_str(&saved).unwrap();
+        let [c, a, b] = players.map(PlayerRef::new);
+        assert!(loaded.is_locked(&a) && loaded.is_locked(&b) && !loaded.is_locked(&c));
+        assert_eq!(loaded.view_for(&a), Err(SealError::Locked(a.clone())));
+        assert_eq!(
+            loaded.view_for(&c).unwrap().own_rolls,
+            session.view_for(&c).own_rolls
+        );
+
+        let bet = Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN));
+        loaded.act(bet).unwrap();
+        assert_eq!(loaded.act(bet), Err(SealError::Locked(a.clone())));
+        assert_eq!(
+            loaded.unlock(&a, "b"),
+            Err(SealError::WrongPassphrase((*a).clone()))
+        );
+        loaded.unlock(&a, "a").unwrap();
+        assert_eq!(
+            loaded.view_for(&a).unwrap().own_rolls,
+            session.view_for(&a).own_rolls
+        );
+        // calling reveals every roll, so B has to unlock theirs first
+        assert_eq!(
+            loaded.act(Action::CallFluff),
+            Err(SealError::Locked(b.clone()))
+        );
+        loaded.unlock(&b, "b").unwrap();
+        loaded.act(Action::CallFluff).unwrap();
+        let played = session.act(bet).unwrap().act(Action::CallFluff).unwrap();
+        assert_eq!(loaded.session().round_history(), played.round_history());
+    }
+
+    #[test]
+    fn test_tampered_save() {
+        let players = ["A", "B"].map(Player::new);
+        let session = GameSession::from(Game::new(players.clone(), GameConfig::default()));
+        let keyring = keyring(&["A"]);
+        let save = |session: &GameSession| {
+            let sealed = SealedSession::new(session.clone(), keyring.clone()).unwrap();
+            serde_json::to_value(sealed).unwrap()
+        };
+        let [mut first, mut second] = [save(&session), save(&session)];
+
+        // rolls sealed in one game do not open in another, even with the same passphrase
+        second["sealed"]["A"] = first["sealed"]["A"].clone();
+        let mut moved: SealedSession = serde_json::from_value(second).unwrap();
+        assert_eq!(
+            moved.unlock(&players[0], "a"),
+            Err(SealError::Tampered(players[0].clone().into()))
+        );
+
+        // without its sealed rolls, the locked player's rolls can not be saved again
+        first["sealed"].as_object_mut().unwrap().clear();
+        let dropped: SealedSession = serde_json::from_value(first).unwrap();
+        assert!(serde_json::to_string(&dropped).is_err());
+    }
+
+    #[test]
+    fn test_sealed_log() {
+        let players = ["A", "B"].map(Player::new);
+        let (mut log, session) = GameLog::start(players.clone(), GameConfig::default());
+        let bet = Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN));
+        let session = log.act(session, bet).unwrap();
+        log.act(session, Action::CallFluff).unwrap();
+
+        let sealed = SealedLog::new(&log, keyring(&["A"])).unwrap();
+        let mut loaded: SealedLog =
+            serde_json::from_str(&serde_json::to_string(&sealed).unwrap()).unwrap();
+        let a = PlayerRef::new(players[0].clone());
+        assert_eq!(loaded.open(), Err(SealError::Locked(a)));
+        loaded.unlock(&players[0], "a").unwrap();
+        assert_eq!(loaded.open().unwrap(), log);
+
+        let (seeded, _) = GameLog::start_seeded(players, GameConfig::default(), 0);
+        assert!(matches!(
+            SealedLog::new(&seeded, Keyring::new()),
+            Err(SealError::Seeded)
+        ));
+    }
+}
@@ -0,0 +1,509 @@
+//! Serves a [`Host`] over WebSocket
+//!
+//! Every message is a JSON text frame. A client first sends [`ClientMessage::Join`] with its seat
+//! token, after which it is sent its [`PlayerView`] whenever the game changes, along with every
+//! [`GameEvent`]. Other players' rolls are never sent before they are revealed by a fluff call.
+//!
+//! Events are numbered, and a client that loses its connection can send [`ClientMessage::Resume`]
+//! with the reconnect token it was welcomed with to get every event it missed. Seats whose client
+//! stays away for longer than the host's [`GracePolicy`](crate::host::GracePolicy) are forfeited
+//! or played by a bot.
+
+use std::{
+    io::ErrorKind,
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Sender},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use tungstenite::{Message, WebSocket};
+
+use crate::{
+    game::{session::Action, view::PlayerView, PlayerRef},
+    host::{Connection, GameEvent, GameId, Host, HostError, Seq},
+};
+
+/// How often a connection checks for messages to send while waiting for its client
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Claims the seat `token` belongs to
+    Join {
+        token: String,
+    },
+    /// Takes a seat back after a dropped connection, getting every event after `last_seq`, or
+    /// after the last acknowledged one if it is missing
+    Resume {
+        reconnect_token: String,
+        last_seq: Option<Seq>,
+    },
+    /// Tells the server every event up to `seq` was seen
+    Ack {
+        seq: Seq,
+    },
+    Act {
+        action: Action,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Welcome {
+        game: GameId,
+        player: PlayerRef,
+        reconnect_token: String,
+    },
+    /// Boxed since it is much larger than the other messages
+    View {
+        view: Box<PlayerView>,
+    },
+    Event {
+        seq: Seq,
+        event: GameEvent,
+    },
+    Error {
+        message: String,
+    },
+}
+
+struct Client {
+    connection: u64,
+    game: GameId,
+    player: PlayerRef,
+    sender: Sender<ServerMessage>,
+}
+
+/// Accepts WebSocket clients for the games of a [`Host`], with a thread per connection
+#[derive(Clone, Default)]
+pub struct Server {
+    host: Arc<Mutex<Host>>,
+    clients: Arc<Mutex<Vec<Client>>>,
+    next_connection: Arc<AtomicU64>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().expect("Server lock should not be poisoned")
+}
+
+/// Why a connection ended early, boxed since it is quite large
+type ConnectionError = Box<tungstenite::Error>;
+
+fn send(socket: &mut WebSocket<TcpStream>, message: &ServerMessage) -> Result<(), ConnectionError> {
+    Ok(socket.send(Message::Text(
+        serde_json::to_string(message).expect("Messages should serialize"),
+    ))?)
+}
+
+impl Server {
+    #[must_use]
+    pub fn new(host: Host) -> Self {
+        Self {
+            host: Arc::new(Mutex::new(host)),
+            clients: Arc::default(),
+            next_connection: Arc::default(),
+        }
+    }
+
+    /// The hosted games, e.g. for creating more of them while serving
+    #[must_use]
+    pub fn host(&self) -> &Arc<Mutex<Host>> {
+        &self.host
+    }
+
+    /// Serves every connection to `listener`, until accepting fails
+    ///
+    /// Grace periods of disconnected seats are checked in the background while serving.
+    pub fn serve(&self, listener: &TcpListener) -> std::io::Result<()> {
+        let stopped = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                while !stopped.load(Ordering::Relaxed) {
+                    std::thread::sleep(POLL_INTERVAL);
+                    let mut host = lock(&self.host);
+                    for (game, events) in host.check_grace(Instant::now()) {
+                        self.broadcast(&host, game, &events);
+                    }
+                }
+            });
+            let result = loop {
+                let (stream, _) = match listener.accept() {
+                    Ok(accepted) => accepted,
+                    Err(err) => break Err(err),
+                };
+                let server = self.clone();
+                std::thread::spawn(move || {
+                    // a broken connection only matters to its own client
+                    let _ = server.handle(stream);
+                });
+            };
+            stopped.store(true, Ordering::Relaxed);
+            result
+        })
+    }
+
+    /// Sends every client of `game` the latest `events` and their new view
+    fn broadcast(&self, host: &Host, game: GameId, events: &[GameEvent]) {
+        let Ok(hosted) = host.game(game) else {
+            return;
+        };
+        if events.is_empty() {
+            return;
+        }
+        let first_seq = hosted.last_seq() + 1 - events.len() as Seq;
+        lock(&self.clients).retain(|client| {
+            if client.game != game {
+                return true;
+            }
+            (first_seq..)
+                .zip(events)
+                .map(|(seq, event)| ServerMessage::Event {
+                    seq,
+                    event: event.clone(),
+                })
+                .chain([ServerMessage::View {
+                    view: Box::new(hosted.session().view_for(&client.player)),
+                }])
+                .all(|message| client.sender.send(message).is_ok())
+        });
+    }
+
+    /// Welcomes a client to its seat, catching it up before telling everyone it is back
+    ///
+    /// Returns the seat token the client acts with.
+    fn welcome(
+        &self,
+        host: &Host,
+        connection: Connection,
+        events: &[GameEvent],
+        client: u64,
+        sender: &Sender<ServerMessage>,
+    ) -> Result<String, HostError> {
+        let Connection {
+            game,
+            player,
+            reconnect_token,
+            missed,
+        } = connection;
+        let hosted = host.game(game)?;
+        // the receiver lives as long as this connection
+        let _ = sender.send(ServerMessage::Welcome {
+            game,
+            player: player.clone(),
+            reconnect_token,
+        });
+        for (seq, event) in missed {
+            let _ = sender.send(ServerMessage::Event { seq, event });
+        }
+        let token = hosted
+            .token(&player)
+            .expect("A connected player should have a seat")
+            .to_owned();
+        if events.is_empty() {
+            let _ = sender.send(ServerMessage::View {
+                view: Box::new(hosted.session().view_for(&player)),
+            });
+        }
+        lock(&self.clients).push(Client {
+            connection: client,
+            game,
+            player,
+            sender: sender.clone(),
+        });
+        self.broadcast(host, game, events);
+        Ok(token)
+    }
+
+    fn respond(
+        &self,
+        message: ClientMessage,
+        token: &mut Option<String>,
+        client: u64,
+        sender: &Sender<ServerMessage>,
+    ) -> Result<(), HostError> {
+        let mut host = lock(&self.host);
+        match message {
+            ClientMessage::Join { token: new_token } => {
+                let (connection, events) = host.connect(&new_token)?;
+                *token = Some(self.welcome(&host, connection, &events, client, sender)?);
+            }
+            ClientMessage::Resume {
+                reconnect_token,
+                last_seq,
+            } => {
+                let (connection, events) = host.resume(&reconnect_token, last_seq)?;
+                *token = Some(self.welcome(&host, connection, &events, client, sender)?);
+            }
+            ClientMessage::Ack { seq } => {
+                host.ack(token.as_deref().ok_or(HostError::UnknownToken)?, seq)?;
+            }
+            ClientMessage::Act { action } => {
+                let token = token.as_deref().ok_or(HostError::UnknownToken)?;
+                let (game, events) = host.act(token, action)?;
+                self.broadcast(&host, game, &events);
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks the connection's seat as disconnected, unless the player connected again elsewhere
+    fn disconnect(&self, client: u64) {
+        let mut host = lock(&self.host);
+        let seat = {
+            let mut clients = lock(&self.clients);
+            let seat = clients
+                .iter()
+                .find(|x| x.connection == client)
+                .map(|x| (x.game, x.player.clone()));
+            clients.retain(|x| x.connection != client);
+            seat.filter(|(game, player)| {
+                !clients
+                    .iter()
+                    .any(|x| x.game == *game && x.player == *player)
+            })
+        };
+        if let Some((game, player)) = seat {
+            if let Ok(events) = host.disconnect(game, &player, Instant::now()) {
+                self.broadcast(&host, game, &events);
+            }
+        }
+    }
+
+    fn handle(&self, stream: TcpStream) -> Result<(), ConnectionError> {
+        let mut socket = tungstenite::accept(stream).map_err(|err| match err {
+            tungstenite::HandshakeError::Failure(err) => err,
+            tungstenite::HandshakeError::Interrupted(_) => {
+                tungstenite::Error::Io(ErrorKind::WouldBlock.into())
+            }
+        })?;
+        socket
+            .get_ref()
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .map_err(tungstenite::Error::from)?;
+        let client = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        let result = self.converse(&mut socket, client);
+        self.disconnect(client);
+        result
+    }
+
+    /// Answers the client's messages until the connection ends
+    fn converse(
+        &self,
+        socket: &mut WebSocket<TcpStream>,
+        client: u64,
+    ) -> Result<(), ConnectionError> {
+        let (sender, receiver) = mpsc::channel();
+        let mut token = None;
+        loop {
+            for message in receiver.try_iter() {
+                send(socket, &message)?;
+            }
+            match socket.read() {
+                Ok(Message::Text(text)) => {
+                    let response = match serde_json::from_str::<ClientMessage>(&text) {
+                        Ok(message) => self.respond(message, &mut token, client, &sender),
+                        Err(err) => {
+                            send(
+                                socket,
+                                &ServerMessage::Error {
+                                    message: err.to_string(),
+                                },
+                            )?;
+                            Ok(())
+                        }
+                    };
+                    if let Err(err) = response {
+                        send(
+                            socket,
+                            &ServerMessage::Error {
+                                message: err.to_string(),
+                            },
+                        )?;
+                    }
+                }
+                Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(err))
+                    if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpStream, num::NonZeroUsize};
+
+    use tungstenite::stream::MaybeTlsStream;
+
+    use super::*;
+    use crate::{bet::Bet, game::GameConfig, player::Player};
+
+    type Client = WebSocket<MaybeTlsStream<TcpStream>>;
+
+    fn send(client: &mut Client, message: &ClientMessage) {
+        client
+            .send(Message::Text(serde_json::to_string(message).unwrap()))
+            .unwrap();
+    }
+
+    fn receive(client: &mut Client) -> ServerMessage {
+        loop {
+            if let Message::Text(text) = client.read().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_loopback() {
+        let mut host = Host::new();
+        let (_, seats) = host.create_game(
+            [Player::new("A"), Player::new("B")],
+            GameConfig::new(NonZeroUsize::new(2).unwrap(), NonZeroUsize::new(6).unwrap()),
+        );
+        let server = Server::new(host);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || server.serve(&listener));
+
+        let mut clients = seats
+            .values()
+            .map(|token| {
+                let (mut client, _) = tungstenite::connect(format!("ws://{addr}")).unwrap();
+                send(
+                    &mut client,
+                    &ClientMessage::Join {
+                        token: token.clone(),
+                    },
+                );
+                assert!(matches!(
+                    receive(&mut client),
+                    ServerMessage::Welcome { .. }
+                ));
+                let ServerMessage::View { view } = receive(&mut client) else {
+                    panic!("A view should follow the welcome");
+                };
+                assert_eq!(view.own_rolls().len(), 2);
+                client
+            })
+            .collect::<Vec<_>>();
+
+        // acting out of turn is refused
+        send(
+            &mut clients[1],
+            &ClientMessage::Act {
+                action: Action::CallFluff,
+            },
+        );
+        assert!(matches!(
+            receive(&mut clients[1]),
+            ServerMessage::Error { .. }
+        ));
+
+        let bet = Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN));
+        send(&mut clients[0], &ClientMessage::Act { action: bet });
+        for client in &mut clients {
+            let ServerMessage::Event {
+                seq: 1,
+                event: GameEvent::Acted { action, .. },
+            } = receive(client)
+            else {
+                panic!("Every client should be told about the action");
+            };
+            assert_eq!(action, bet);
+            let ServerMessage::View { view } = receive(client) else {
+                panic!("Every client should get its new view");
+            };
+            assert_eq!(
+                view.last_bet(),
+                Some(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN))
+            );
+        }
+        send(
+            &mut clients[1],
+            &ClientMessage::Act {
+                action: Action::CallFluff,
+            },
+        );
+        receive(&mut clients[0]);
+        let ServerMessage::View { view } = receive(&mut clients[0]) else {
+            panic!("Every client should get its new view");
+        };
+        assert_eq!(view.round_history.len(), 1);
+        assert_eq!(view.round_number, 2);
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut host = Host::new();
+        let (_, seats) = host.create_game(
+            [Player::new("A"), Player::new("B")],
+            GameConfig::new(NonZeroUsize::new(2).unwrap(), NonZeroUsize::new(6).unwrap()),
+        );
+        let server = Server::new(host);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || server.serve(&listener));
+        let connect = |message: &ClientMessage| {
+            let (mut client, _) = tungstenite::connect(format!("ws://{addr}")).unwrap();
+            send(&mut client, message);
+            let ServerMessage::Welcome {
+                reconnect_token, ..
+            } = receive(&mut client)
+            else {
+                panic!("Joining should be welcomed");
+            };
+            (client, reconnect_token)
+        };
+        let join = |token: &String| ClientMessage::Join {
+            token: token.clone(),
+        };
+
+        let (mut a, reconnect_token) = connect(&join(&seats[0]));
+        receive(&mut a);
+        let (mut b, _) = connect(&join(&seats[1]));
+        receive(&mut b);
+        let bet = Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN));
+        send(&mut a, &ClientMessage::Act { action: bet });
+        receive(&mut a);
+        send(&mut a, &ClientMessage::Ack { seq: 1 });
+        a.close(None).unwrap();
+        // B sees the bet, its view, then A dropping
+        receive(&mut b);
+        receive(&mut b);
+        assert!(matches!(
+            receive(&mut b),
+            ServerMessage::Event {
+                seq: 2,
+                event: GameEvent::Disconnected { .. }
+            }
+        ));
+        receive(&mut b);
+        send(
+            &mut b,
+            &ClientMessage::Act {
+                action: Action::CallFluff,
+            },
+        );
+        receive(&mut b);
+
+        // A gets everything after the bet it acknowledged, then is back
+        let (mut a, _) = connect(&ClientMessage::Resume {
+            reconnect_token,
+            last_seq: None,
+        });
+        let mut seqs = Vec::new();
+        while let ServerMessage::Event { seq, .. } = receive(&mut a) {
+            seqs.push(seq);
+        }
+        assert_eq!(seqs, vec![2, 3, 4]);
+    }
+}
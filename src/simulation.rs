@@ -0,0 +1,376 @@
+use std::{
+    fmt::{Display, Formatter},
+    num::NonZeroUsize,
+    sync::Arc,
+};
+
+use rand::{rngs::StdRng, SeedableRng};
+use thiserror::Error;
+
+use crate::{
+    game::{log::mix_seed, session::GameSession, state::GameOver, Game, GameConfig},
+    player::Player,
+    strategy::{BotError, Driver, Strategy},
+};
+
+/// Builds a fresh strategy for a single game out of a seed, so every game is reproducible
+pub type StrategyFactory = Arc<dyn Fn(u64) -> Box<dyn Strategy> + Send + Sync>;
+
+/// Wraps a closure as a [`StrategyFactory`]
+pub fn factory<S: Strategy + 'static>(
+    f: impl Fn(u64) -> S + Send + Sync + 'static,
+) -> StrategyFactory {
+    Arc::new(move |seed| Box::new(f(seed)))
+}
+
+#[derive(Clone)]
+pub struct Seat {
+    pub player: Player,
+    pub strategy: StrategyFactory,
+}
+
+impl Seat {
+    pub fn new(player: Player, strategy: StrategyFactory) -> Self {
+        Self { player, strategy }
+    }
+}
+
+/// Plays many games between bots without any I/O
+///
+/// Every game is seeded from `seed` and its index alone, so the results do not depend on `threads`.
+#[derive(Clone)]
+pub struct Simulation {
+    pub config: GameConfig,
+    /// Players in seating order, so the first seat opens the first round of the first game
+    pub seats: Vec<Seat>,
+    pub seed: u64,
+    pub games: usize,
+    pub threads: NonZeroUsize,
+    /// Whether game `i` is seated starting from seat `i % seats.len()`, so every seat opens
+    /// equally often instead of the same one always going first
+    pub rotate_seats: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum SimulationError {
+    #[error("A simulation needs at least 2 players, but has {0}")]
+    TooFewPlayers(usize),
+    #[error("{0} has more than one seat")]
+    DuplicatePlayer(Player),
+    #[error("Game {game_index}: {source}")]
+    Bot { game_index: usize, source: BotError },
+}
+
+/// Outcome of a single simulated game
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub struct GameResult {
+    /// Index of the winner in [`Simulation::seats`], wherever they were seated in this game
+    pub winner_seat: usize,
+    pub rounds: usize,
+    pub turns: usize,
+}
+
+impl Simulation {
+    #[must_use]
+    pub fn new(config: GameConfig, seats: Vec<Seat>, seed: u64, games: usize) -> Self {
+        Self {
+            config,
+            seats,
+            seed,
+            games,
+            threads: NonZeroUsize::MIN,
+            rotate_seats: true,
+        }
+    }
+
+    #[must_use]
+    pub fn with_threads(self, threads: NonZeroUsize) -> Self {
+        Self { threads, ..self }
+    }
+
+    /// Seats every game in the order of `seats`, for callers that pick the seating themselves
+    #[must_use]
+    pub fn with_fixed_seats(self) -> Self {
+        Self {
+            rotate_seats: false,
+            ..self
+        }
+    }
+
+    /// Checks that there are enough seats for a game, each with a different player
+    fn check_seats(&self) -> Result<(), SimulationError> {
+        for (index, seat) in self.seats.iter().enumerate() {
+            if self.seats[..index].iter().any(|x| x.player == seat.player) {
+                return Err(SimulationError::DuplicatePlayer(seat.player.clone()));
+            }
+        }
+        if self.seats.len() < 2 {
+            return Err(SimulationError::TooFewPlayers(self.seats.len()));
+        }
+        Ok(())
+    }
+
+    /// Plays the game with index `game_index` from start to finish
+    pub fn play_game(&self, game_index: usize) -> Result<Game<GameOver>, SimulationError> {
+        self.check_seats()?;
+        let seed = mix_seed(self.seed, game_index as u64);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut seats = self.seats.iter().collect::<Vec<_>>();
+        if self.rotate_seats {
+            seats.rotate_left(game_index % self.seats.len());
+        }
+        let mut driver = Driver::new();
+        for (seat_index, seat) in self.seats.iter().enumerate() {
+            driver.add_bot(
+                seat.player.clone(),
+                (seat.strategy)(seed.rotate_left(seat_index as u32 + 1)),
+            );
+        }
+        let game = Game::new_with_rng(
+            seats.iter().map(|x| x.player.clone()),
+            self.config,
+            &mut rng,
+        );
+        match driver.advance_with(game.into(), |session, action| {
+            session.act_with_rng(action, &mut rng)
+        }) {
+            Ok(GameSession::GameOver(game)) => Ok(game),
+            Ok(_) => unreachable!("Every seat should be a bot, so the game should be over"),
+            Err(source) => Err(SimulationError::Bot { game_index, source }),
+        }
+    }
+
+    fn result_of(&self, game_index: usize) -> Result<GameResult, SimulationError> {
+        let game = self.play_game(game_index)?;
+        Ok(GameResult {
+            winner_seat: self
+                .seats
+                .iter()
+                .position(|x| x.player == **game.winner())
+                .expect("Winner should be seated"),
+            rounds: game.round_history().len(),
+            turns: game.round_history().iter().map(|x| x.turns().len()).sum(),
+        })
+    }
+
+    /// Plays every game, split across `threads`, and returns their results in order
+    pub fn results(&self) -> Result<Vec<GameResult>, SimulationError> {
+        self.check_seats()?;
+        let threads = self.threads.get().min(self.games.max(1));
+        let chunk_size = self.games.div_ceil(threads);
+        std::thread::scope(|scope| {
+            let handles = (0..threads)
+                .map(|thread_index| {
+                    let start = thread_index * chunk_size;
+                    let end = (start + chunk_size).min(self.games);
+                    scope.spawn(move || {
+                        (start..end)
+                            .map(|i| self.result_of(i))
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut results = Vec::with_capacity(self.games);
+            for handle in handles {
+                results.extend(handle.join().expect("Simulation thread panicked")?);
+            }
+            Ok(results)
+        })
+    }
+
+    pub fn run(&self) -> Result<SimulationReport, SimulationError> {
+        Ok(SimulationReport::new(
+            self.seats.iter().map(|x| x.player.clone()).collect(),
+            &self.results()?,
+        ))
+    }
+}
+
+/// 95% Wilson score interval for `successes` out of `trials`
+#[must_use]
+pub fn wilson_interval(successes: usize, trials: usize) -> (f64, f64) {
+    const Z: f64 = 1.96;
+    if trials == 0 {
+        return (0.0, 1.0);
+    }
+    let n = trials as f64;
+    let p = successes as f64 / n;
+    let denominator = 1.0 + Z * Z / n;
+    let center = (p + Z * Z / (2.0 * n)) / denominator;
+    let half_width = Z * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt() / denominator;
+    (
+        (center - half_width).max(0.0),
+        (center + half_width).min(1.0),
+    )
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SeatReport {
+    pub player: Player,
+    pub wins: usize,
+    pub win_rate: f64,
+    /// 95% confidence interval of the win rate
+    pub confidence_interval: (f64, f64),
+    /// Win rate above what every seat would get if they were all equal
+    ///
+    /// This only measures the strategy's strength if seats were rotated between games, since
+    /// otherwise it also includes the advantage of where it was seated.
+    pub advantage: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SimulationReport {
+    pub games: usize,
+    pub seats: Vec<SeatReport>,
+    pub average_rounds: f64,
+    pub average_turns: f64,
+}
+
+impl SimulationReport {
+    #[must_use]
+    pub fn new(players: Vec<Player>, results: &[GameResult]) -> Self {
+        let games = results.len();
+        let fair_share = 1.0 / players.len().max(1) as f64;
+        let seats = players
+            .into_iter()
+            .enumerate()
+            .map(|(seat, player)| {
+                let wins = results.iter().filter(|x| x.winner_seat == seat).count();
+                let win_rate = if games == 0 {
+                    0.0
+                } else {
+                    wins as f64 / games as f64
+                };
+                SeatReport {
+                    player,
+                    wins,
+                    win_rate,
+                    confidence_interval: wilson_interval(wins, games),
+                    advantage: win_rate - fair_share,
+                }
+            })
+            .collect();
+        let average = |f: fn(&GameResult) -> usize| {
+            results.iter().map(f).sum::<usize>() as f64 / games.max(1) as f64
+        };
+        Self {
+            games,
+            seats,
+            average_rounds: average(|x| x.rounds),
+            average_turns: average(|x| x.turns),
+        }
+    }
+}
+
+impl Display for SimulationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} games, {:.2} rounds and {:.2} turns on average",
+            self.games, self.average_rounds, self.average_turns
+        )?;
+        for (seat, report) in self.seats.iter().enumerate() {
+            writeln!(
+                f,
+                "Seat {seat} ({}): {} wins, {:.1}% [{:.1}%, {:.1}%], advantage {:+.1}%",
+                report.player,
+                report.wins,
+                report.win_rate * 100.0,
+                report.confidence_interval.0 * 100.0,
+                report.confidence_interval.1 * 100.0,
+                report.advantage * 100.0,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::bots::{Bluffer, Cautious, RandomLegal};
+
+    fn simulation() -> Simulation {
+        Simulation::new(
+            GameConfig::new(NonZeroUsize::new(3).unwrap(), NonZeroUsize::new(6).unwrap()),
+            vec![
+                Seat::new(Player::new("Random"), factory(RandomLegal::new)),
+                Seat::new(Player::new("Cautious"), factory(|_| Cautious::default())),
+                Seat::new(Player::new("Bluffer"), factory(Bluffer::new)),
+            ],
+            1234,
+            30,
+        )
+    }
+
+    #[test]
+    fn test_results_do_not_depend_on_threads() {
+        let single = simulation().results().unwrap();
+        let multi = simulation()
+            .with_threads(NonZeroUsize::new(4).unwrap())
+            .results()
+            .unwrap();
+        assert_eq!(single, multi);
+        let report = SimulationReport::new(
+            simulation().seats.into_iter().map(|x| x.player).collect(),
+            &single,
+        );
+        assert_eq!(report.seats.iter().map(|x| x.wins).sum::<usize>(), 30);
+    }
+
+    #[test]
+    fn test_seats_rotate_between_games() {
+        let simulation = simulation();
+        for game_index in 0..6 {
+            let game = simulation.play_game(game_index).unwrap();
+            let opener = &game.round_history()[0].turns()[0].player;
+            assert_eq!(**opener, simulation.seats[game_index % 3].player);
+        }
+        let fixed = simulation.with_fixed_seats();
+        let game = fixed.play_game(1).unwrap();
+        assert_eq!(
+            *game.round_history()[0].turns()[0].player,
+            fixed.seats[0].player
+        );
+    }
+
+    #[test]
+    fn test_too_few_players() {
+        let mut simulation = Simulation::new(GameConfig::default(), Vec::new(), 0, 3);
+        assert!(matches!(
+            simulation.run(),
+            Err(SimulationError::TooFewPlayers(0))
+        ));
+        assert!(matches!(
+            simulation.play_game(0),
+            Err(SimulationError::TooFewPlayers(0))
+        ));
+        let seat = Seat::new(Player::new("Random"), factory(RandomLegal::new));
+        simulation.seats.push(seat.clone());
+        assert!(matches!(
+            simulation.run(),
+            Err(SimulationError::TooFewPlayers(1))
+        ));
+        // two seats with the same player would be a single player in the game
+        simulation.seats.push(seat);
+        assert!(matches!(
+            simulation.run(),
+            Err(SimulationError::DuplicatePlayer(_))
+        ));
+    }
+
+    #[test]
+    fn test_consecutive_seeds_differ() {
+        let rounds = |seed: u64| {
+            let simulation = Simulation {
+                seed,
+                ..simulation()
+            };
+            (0..4)
+                .map(|i| simulation.play_game(i).unwrap().round_history().to_vec())
+                .collect::<Vec<_>>()
+        };
+        let (first, second) = (rounds(1), rounds(2));
+        assert!(first[1..].iter().all(|x| !second.contains(x)));
+    }
+}
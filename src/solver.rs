@@ -0,0 +1,600 @@
+use std::{collections::HashMap, num::NonZeroUsize, sync::Arc};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use thiserror::Error;
+
+use crate::{
+    bet::Bet,
+    game::{session::Action, view::PlayerView, GameConfig},
+    strategy::{bots::Cautious, Strategy},
+};
+
+/// Everything a player knows when they act in a 2 player round, with their rolls sorted
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct InfoSet {
+    pub own_dice: usize,
+    pub opponent_dice: usize,
+    pub own_rolls: Vec<NonZeroUsize>,
+    pub history: Vec<Bet>,
+}
+
+impl InfoSet {
+    /// The info set of the viewer, if the game has exactly 2 players with dice and it is the
+    /// viewer's turn
+    #[must_use]
+    pub fn from_view(view: &PlayerView) -> Option<Self> {
+        if !view.is_my_turn() {
+            return None;
+        }
+        let mut alive = view.player_dice_counts.iter().filter(|(_, x)| **x != 0);
+        let (first, second) = (alive.next()?, alive.next()?);
+        if alive.next().is_some() {
+            return None;
+        }
+        let opponent_dice = if *first.0 == view.viewer {
+            *second.1
+        } else {
+            *first.1
+        };
+        let mut own_rolls = view.own_rolls().to_vec();
+        own_rolls.sort();
+        Some(Self {
+            own_dice: own_rolls.len(),
+            opponent_dice,
+            own_rolls,
+            history: view.turns.iter().map(|x| x.bet).collect(),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct StrategyEntry {
+    pub info_set: InfoSet,
+    pub actions: Vec<(Action, f64)>,
+}
+
+/// Probabilities of every action in every info set, as solved by [`Solver`]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+#[serde(from = "Vec<StrategyEntry>", into = "Vec<StrategyEntry>")]
+pub struct StrategyTable {
+    entries: HashMap<InfoSet, Vec<(Action, f64)>>,
+}
+
+impl From<Vec<StrategyEntry>> for StrategyTable {
+    fn from(value: Vec<StrategyEntry>) -> Self {
+        Self {
+            entries: value.into_iter().map(|x| (x.info_set, x.actions)).collect(),
+        }
+    }
+}
+
+impl From<StrategyTable> for Vec<StrategyEntry> {
+    fn from(value: StrategyTable) -> Self {
+        value
+            .entries
+            .into_iter()
+            .map(|(info_set, actions)| StrategyEntry { info_set, actions })
+            .collect()
+    }
+}
+
+impl StrategyTable {
+    #[must_use]
+    pub fn get(&self, info_set: &InfoSet) -> Option<&[(Action, f64)]> {
+        self.entries.get(info_set).map(Vec::as_slice)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&InfoSet, &[(Action, f64)])> {
+        self.entries.iter().map(|(k, v)| (k, v.as_slice()))
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolveError {
+    #[error("A round with {bets} possible bets is too big to solve, at most {max} are supported")]
+    TooLarge { bets: usize, max: usize },
+}
+
+/// A sorted hand one player could roll, and the probability of rolling it
+#[derive(Debug, Clone)]
+pub(crate) struct ChanceHand {
+    pub rolls: Vec<NonZeroUsize>,
+    pub weight: f64,
+    // matches[roll - 1] is how many of these dice count towards a bet on roll
+    matches: Vec<usize>,
+}
+
+pub(crate) fn chance_hands(dice: usize, max_roll: NonZeroUsize) -> Vec<ChanceHand> {
+    fn fill(
+        dice: usize,
+        min_roll: usize,
+        max_roll: NonZeroUsize,
+        rolls: &mut Vec<NonZeroUsize>,
+        out: &mut Vec<Vec<NonZeroUsize>>,
+    ) {
+        if rolls.len() == dice {
+            out.push(rolls.clone());
+            return;
+        }
+        for roll in (min_roll..=max_roll.get()).filter_map(NonZeroUsize::new) {
+            rolls.push(roll);
+            fill(dice, roll.get(), max_roll, rolls, out);
+            rolls.pop();
+        }
+    }
+    let mut all = Vec::new();
+    fill(dice, 1, max_roll, &mut Vec::with_capacity(dice), &mut all);
+    all.into_iter()
+        .map(|rolls| {
+            // multinomial coefficient of the sorted rolls over max_roll^dice
+            let mut weight = 1.0;
+            let mut run = 0;
+            for (i, roll) in rolls.iter().enumerate() {
+                run = if i > 0 && rolls[i - 1] == *roll {
+                    run + 1
+                } else {
+                    1
+                };
+                weight *= (i + 1) as f64 / run as f64;
+            }
+            weight /= (max_roll.get() as f64).powi(dice as i32);
+            let matches = (1..=max_roll.get())
+                .filter_map(NonZeroUsize::new)
+                .map(|bet_roll| {
+                    Bet::new(NonZeroUsize::MIN, bet_roll).count_matches(rolls.iter().copied())
+                })
+                .collect();
+            ChanceHand {
+                rolls,
+                weight,
+                matches,
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub(crate) struct Node {
+    pub history: Vec<Bet>,
+    /// 0 for whoever opened the round, 1 for the other player
+    pub player: usize,
+    pub actions: Vec<Action>,
+    /// Node reached by each action, or [`None`] for calling fluff
+    pub children: Vec<Option<usize>>,
+    // indexed by hand * actions.len() + action
+    regrets: Vec<f64>,
+    strategy_sum: Vec<f64>,
+}
+
+/// The betting tree of a single 2 player round where the opener has `dice[0]` dice and the other
+/// player has `dice[1]`
+#[derive(Debug)]
+pub(crate) struct RoundTree {
+    pub dice: [usize; 2],
+    pub hands: [Vec<ChanceHand>; 2],
+    pub nodes: Vec<Node>,
+    /// Value for the opener of winning and of losing this round
+    pub payoffs: (f64, f64),
+}
+
+impl RoundTree {
+    pub fn new(dice: [usize; 2], max_roll: NonZeroUsize, payoffs: (f64, f64)) -> Self {
+        let hands = dice.map(|x| chance_hands(x, max_roll));
+        let bets = (1..=dice[0] + dice[1])
+            .flat_map(|count| (1..=max_roll.get()).map(move |roll| (count, roll)))
+            .filter_map(|(count, roll)| {
+                Some(Bet::new(
+                    NonZeroUsize::new(count)?,
+                    NonZeroUsize::new(roll)?,
+                ))
+            })
+            .collect::<Vec<_>>();
+        let mut tree = Self {
+            dice,
+            hands,
+            nodes: Vec::new(),
+            payoffs,
+        };
+        tree.build(Vec::new(), &bets);
+        tree
+    }
+
+    fn build(&mut self, history: Vec<Bet>, bets: &[Bet]) -> usize {
+        let index = self.nodes.len();
+        let player = history.len() % 2;
+        let raises = bets
+            .iter()
+            .filter(|bet| history.last().is_none_or(|prev| *bet > prev))
+            .copied()
+            .collect::<Vec<_>>();
+        let mut actions = raises.iter().map(|x| Action::Raise(*x)).collect::<Vec<_>>();
+        if !history.is_empty() {
+            actions.push(Action::CallFluff);
+        }
+        let size = self.hands[player].len() * actions.len();
+        self.nodes.push(Node {
+            history: history.clone(),
+            player,
+            actions,
+            children: Vec::new(),
+            regrets: vec![0.0; size],
+            strategy_sum: vec![0.0; size],
+        });
+        let mut children = raises
+            .into_iter()
+            .map(|bet| {
+                let mut child_history = history.clone();
+                child_history.push(bet);
+                Some(self.build(child_history, bets))
+            })
+            .collect::<Vec<_>>();
+        if !history.is_empty() {
+            children.push(None);
+        }
+        self.nodes[index].children = children;
+        index
+    }
+
+    /// Value for the opener of calling fluff on the last bet of `history`, for each pair of hands
+    pub fn showdown(&self, history: &[Bet], opener_hand: usize, other_hand: usize) -> f64 {
+        let bet = history.last().expect("Fluff can only be called on a bet");
+        let matches = self.hands[0][opener_hand].matches[bet.roll.get() - 1]
+            + self.hands[1][other_hand].matches[bet.roll.get() - 1];
+        let bet_is_true = matches >= bet.count.get();
+        let opener_bet = (history.len() - 1).is_multiple_of(2);
+        if bet_is_true == opener_bet {
+            self.payoffs.0
+        } else {
+            self.payoffs.1
+        }
+    }
+
+    fn terminal_values(&self, history: &[Bet], reach: &[Vec<f64>; 2]) -> [Vec<f64>; 2] {
+        let mut values = [
+            vec![0.0; self.hands[0].len()],
+            vec![0.0; self.hands[1].len()],
+        ];
+        for i in 0..self.hands[0].len() {
+            for j in 0..self.hands[1].len() {
+                let u = self.showdown(history, i, j);
+                values[0][i] += reach[1][j] * u;
+                values[1][j] -= reach[0][i] * u;
+            }
+        }
+        values
+    }
+
+    /// Current strategy of `node` for `hand` from regret matching
+    fn current_strategy(&self, node: usize, hand: usize) -> Vec<f64> {
+        let node = &self.nodes[node];
+        let n = node.actions.len();
+        let regrets = &node.regrets[hand * n..(hand + 1) * n];
+        let total: f64 = regrets.iter().sum();
+        if total > 0.0 {
+            regrets.iter().map(|x| x / total).collect()
+        } else {
+            vec![1.0 / n as f64; n]
+        }
+    }
+
+    pub fn average_strategy(&self, node: usize, hand: usize) -> Vec<f64> {
+        let node = &self.nodes[node];
+        let n = node.actions.len();
+        let sums = &node.strategy_sum[hand * n..(hand + 1) * n];
+        let total: f64 = sums.iter().sum();
+        if total > 0.0 {
+            sums.iter().map(|x| x / total).collect()
+        } else {
+            vec![1.0 / n as f64; n]
+        }
+    }
+
+    /// Counterfactual values of every hand of both players at `node`, where the players follow
+    /// `strategy`
+    pub fn values(
+        &self,
+        node: usize,
+        reach: &[Vec<f64>; 2],
+        strategy: &impl Fn(&Self, usize, usize) -> Vec<f64>,
+    ) -> [Vec<f64>; 2] {
+        let player = self.nodes[node].player;
+        let strategies = (0..self.hands[player].len())
+            .map(|hand| strategy(self, node, hand))
+            .collect::<Vec<_>>();
+        let mut values = [
+            vec![0.0; self.hands[0].len()],
+            vec![0.0; self.hands[1].len()],
+        ];
+        for (action, child) in self.nodes[node].children.iter().enumerate() {
+            let mut child_reach = reach.clone();
+            for (hand, x) in child_reach[player].iter_mut().enumerate() {
+                *x *= strategies[hand][action];
+            }
+            let child_values = match child {
+                Some(child) => self.values(*child, &child_reach, strategy),
+                None => self.terminal_values(&self.nodes[node].history, &child_reach),
+            };
+            for (hand, x) in values[player].iter_mut().enumerate() {
+                *x += strategies[hand][action] * child_values[player][hand];
+            }
+            for (x, child_x) in values[1 - player].iter_mut().zip(&child_values[1 - player]) {
+                *x += child_x;
+            }
+        }
+        values
+    }
+
+    /// One iteration of CFR+, returning the counterfactual values of both players
+    fn iterate(&mut self, node: usize, reach: &[Vec<f64>; 2], weight: f64) -> [Vec<f64>; 2] {
+        let player = self.nodes[node].player;
+        let hands = self.hands[player].len();
+        let n = self.nodes[node].actions.len();
+        let strategies = (0..hands)
+            .map(|hand| self.current_strategy(node, hand))
+            .collect::<Vec<_>>();
+        let mut values = [
+            vec![0.0; self.hands[0].len()],
+            vec![0.0; self.hands[1].len()],
+        ];
+        let mut action_values = vec![vec![0.0; n]; hands];
+        for action in 0..n {
+            let mut child_reach = reach.clone();
+            for (hand, x) in child_reach[player].iter_mut().enumerate() {
+                *x *= strategies[hand][action];
+            }
+            let child_values = match self.nodes[node].children[action] {
+                Some(child) => self.iterate(child, &child_reach, weight),
+                None => self.terminal_values(&self.nodes[node].history, &child_reach),
+            };
+            for hand in 0..hands {
+                action_values[hand][action] = child_values[player][hand];
+                values[player][hand] += strategies[hand][action] * child_values[player][hand];
+            }
+            for (x, child_x) in values[1 - player].iter_mut().zip(&child_values[1 - player]) {
+                *x += child_x;
+            }
+        }
+        let node = &mut self.nodes[node];
+        for hand in 0..hands {
+            for action in 0..n {
+                let i = hand * n + action;
+                node.regrets[i] =
+                    (node.regrets[i] + action_values[hand][action] - values[player][hand]).max(0.0);
+                node.strategy_sum[i] += weight * reach[player][hand] * strategies[hand][action];
+            }
+        }
+        values
+    }
+
+    fn chance_reach(&self) -> [Vec<f64>; 2] {
+        [0, 1].map(|p| self.hands[p].iter().map(|x| x.weight).collect())
+    }
+
+    /// Expected value for the opener when both players follow `strategy`
+    pub fn value(&self, strategy: &impl Fn(&Self, usize, usize) -> Vec<f64>) -> f64 {
+        let values = self.values(0, &self.chance_reach(), strategy);
+        values[0]
+            .iter()
+            .zip(&self.hands[0])
+            .map(|(v, hand)| v * hand.weight)
+            .sum()
+    }
+
+    pub fn info_set(&self, node: usize, hand: usize) -> InfoSet {
+        let player = self.nodes[node].player;
+        InfoSet {
+            own_dice: self.dice[player],
+            opponent_dice: self.dice[1 - player],
+            own_rolls: self.hands[player][hand].rolls.clone(),
+            history: self.nodes[node].history.clone(),
+        }
+    }
+}
+
+/// Approximates a Nash equilibrium of 2 player games with counterfactual regret minimization
+///
+/// Each round is solved on its own, starting from the smallest dice counts, with winning or losing
+/// the round worth the value of the round that follows it. Values range from -1 for a certain loss
+/// to 1 for a certain win.
+#[derive(Debug, Clone, Copy)]
+pub struct Solver {
+    pub config: GameConfig,
+    pub iterations: usize,
+}
+
+/// Most bets a round can have for [`Solver`] to be willing to solve it
+pub const MAX_BETS: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub strategy: StrategyTable,
+    /// Value of a round for whoever opens it, keyed by the dice of the opener and then the other
+    /// player
+    pub values: HashMap<(usize, usize), f64>,
+}
+
+impl Solution {
+    /// Value of a whole game for whoever opens the first round
+    #[must_use]
+    pub fn game_value(&self, config: &GameConfig) -> f64 {
+        let max_dice = config.max_dice().get();
+        self.values[&(max_dice, max_dice)]
+    }
+}
+
+impl Solver {
+    #[must_use]
+    pub const fn new(config: GameConfig, iterations: usize) -> Self {
+        Self { config, iterations }
+    }
+
+    /// Payoffs for the opener of a round with `dice`, given the values of the smaller rounds
+    pub(crate) fn payoffs(dice: [usize; 2], values: &HashMap<(usize, usize), f64>) -> (f64, f64) {
+        let win = if dice[1] == 1 {
+            1.0
+        } else {
+            values[&(dice[0], dice[1] - 1)]
+        };
+        let lose = if dice[0] == 1 {
+            -1.0
+        } else {
+            -values[&(dice[1], dice[0] - 1)]
+        };
+        (win, lose)
+    }
+
+    /// Every round that can come up, from the fewest dice to the most
+    pub(crate) fn rounds(config: &GameConfig) -> Result<Vec<[usize; 2]>, SolveError> {
+        let max_dice = config.max_dice().get();
+        let bets = 2 * max_dice * config.max_roll().get();
+        if bets > MAX_BETS {
+            return Err(SolveError::TooLarge {
+                bets,
+                max: MAX_BETS,
+            });
+        }
+        let mut rounds = (1..=max_dice)
+            .flat_map(|a| (1..=max_dice).map(move |b| [a, b]))
+            .collect::<Vec<_>>();
+        rounds.sort_by_key(|[a, b]| a + b);
+        Ok(rounds)
+    }
+
+    pub(crate) fn tree(
+        config: &GameConfig,
+        dice: [usize; 2],
+        values: &HashMap<(usize, usize), f64>,
+    ) -> RoundTree {
+        RoundTree::new(dice, config.max_roll(), Self::payoffs(dice, values))
+    }
+
+    pub fn solve(&self) -> Result<Solution, SolveError> {
+        let mut values = HashMap::new();
+        let mut strategy = StrategyTable::default();
+        for dice in Self::rounds(&self.config)? {
+            let mut tree = Self::tree(&self.config, dice, &values);
+            let reach = tree.chance_reach();
+            for iteration in 1..=self.iterations {
+                tree.iterate(0, &reach, iteration as f64);
+            }
+            values.insert(
+                (dice[0], dice[1]),
+                tree.value(&|tree, node, hand| tree.average_strategy(node, hand)),
+            );
+            for node in 0..tree.nodes.len() {
+                for hand in 0..tree.hands[tree.nodes[node].player].len() {
+                    let actions = tree.nodes[node]
+                        .actions
+                        .iter()
+                        .copied()
+                        .zip(tree.average_strategy(node, hand))
+                        .collect();
+                    strategy.entries.insert(tree.info_set(node, hand), actions);
+                }
+            }
+        }
+        Ok(Solution { strategy, values })
+    }
+}
+
+/// Plays by sampling from a solved [`StrategyTable`], falling back to [`Cautious`] in spots the
+/// table does not cover
+#[derive(Debug, Clone)]
+pub struct SolvedStrategy {
+    table: Arc<StrategyTable>,
+    rng: StdRng,
+}
+
+impl SolvedStrategy {
+    #[must_use]
+    pub fn new(table: Arc<StrategyTable>, seed: u64) -> Self {
+        Self {
+            table,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Strategy for SolvedStrategy {
+    fn act(&mut self, view: &PlayerView) -> Action {
+        let Some(actions) = InfoSet::from_view(view).and_then(|x| self.table.get(&x)) else {
+            return Cautious::default().act(view);
+        };
+        let mut x = self.rng.gen::<f64>();
+        for (action, probability) in actions {
+            if x < *probability {
+                return *action;
+            }
+            x -= probability;
+        }
+        actions
+            .last()
+            .expect("Solved info sets should have actions")
+            .0
+    }
+
+    fn policy(&mut self, view: &PlayerView) -> Vec<(Action, f64)> {
+        match InfoSet::from_view(view).and_then(|x| self.table.get(&x)) {
+            Some(actions) => actions.to_vec(),
+            None => Cautious::default().policy(view),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chance_hands_sum_to_one() {
+        let max_roll = NonZeroUsize::new(4).unwrap();
+        for dice in 1..=3 {
+            let total: f64 = chance_hands(dice, max_roll).iter().map(|x| x.weight).sum();
+            assert!((total - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_solve_one_die() {
+        let config = GameConfig::new(NonZeroUsize::MIN, NonZeroUsize::new(3).unwrap());
+        let solution = Solver::new(config, 2000).solve().unwrap();
+        let value = solution.game_value(&config);
+        assert!((-1.0..=1.0).contains(&value));
+        // holding a 3 means a bet of one 3 is certainly true, so calling it only ever loses
+        let three = NonZeroUsize::new(3).unwrap();
+        let info_set = InfoSet {
+            own_dice: 1,
+            opponent_dice: 1,
+            own_rolls: vec![three],
+            history: vec![Bet::new(NonZeroUsize::MIN, three)],
+        };
+        let call = solution
+            .strategy
+            .get(&info_set)
+            .unwrap()
+            .iter()
+            .find(|(a, _)| *a == Action::CallFluff)
+            .unwrap()
+            .1;
+        assert!(call < 0.05);
+        let ser = serde_json::to_string(&solution.strategy).unwrap();
+        let de: StrategyTable = serde_json::from_str(&ser).unwrap();
+        assert_eq!(de.len(), solution.strategy.len());
+        for (info_set, actions) in solution.strategy.iter() {
+            for ((a, p), (de_a, de_p)) in actions.iter().zip(de.get(info_set).unwrap()) {
+                assert_eq!(a, de_a);
+                assert!((p - de_p).abs() < 1e-12);
+            }
+        }
+    }
+}
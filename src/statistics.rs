@@ -0,0 +1,325 @@
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+
+use crate::{
+    bet::WildRule,
+    game::{
+        state::{Called, GameOver},
+        Game, GameConfig, Round,
+    },
+    player::Player,
+    probability::bet_probability,
+};
+
+/// Counts for one player that can be added up over any number of games
+#[derive(Debug, Serialize, Deserialize, Default, Eq, PartialEq, Clone)]
+pub struct PlayerStats {
+    pub games: usize,
+    pub rounds: usize,
+    /// Rounds where the player made the first bet
+    pub openings: usize,
+    pub bets: usize,
+    /// Bets that were more likely false than true given only the bettor's own rolls
+    pub bluffs: usize,
+    /// Bets on top of another bet
+    pub raises: usize,
+    /// Increase in count over the previous bet, added up over every raise
+    pub raised_count: usize,
+    pub calls: usize,
+    /// Calls on bets that turned out to be fluff
+    pub correct_calls: usize,
+    pub dice_lost: usize,
+    /// Dice lost by other players in rounds this player won
+    pub dice_won: usize,
+    /// Number of games in which the player was knocked out in each round, indexed from 0
+    pub knocked_out_in_round: Vec<usize>,
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+impl PlayerStats {
+    #[must_use]
+    pub fn bluff_rate(&self) -> f64 {
+        ratio(self.bluffs, self.bets)
+    }
+
+    #[must_use]
+    pub fn call_accuracy(&self) -> f64 {
+        ratio(self.correct_calls, self.calls)
+    }
+
+    #[must_use]
+    pub fn average_raise_size(&self) -> f64 {
+        ratio(self.raised_count, self.raises)
+    }
+
+    #[must_use]
+    pub fn opening_rate(&self) -> f64 {
+        ratio(self.openings, self.rounds)
+    }
+
+    /// Share of games in which the player was still in at the start of each round, up to the
+    /// round in which they were last knocked out
+    #[must_use]
+    pub fn survival_curve(&self) -> Vec<f64> {
+        let mut alive = self.games;
+        let mut curve = Vec::with_capacity(self.knocked_out_in_round.len() + 1);
+        for knocked_out in &self.knocked_out_in_round {
+            curve.push(ratio(alive, self.games));
+            // rounds added without their game can knock out more players than were counted
+            alive = alive.saturating_sub(*knocked_out);
+        }
+        curve.push(ratio(alive, self.games));
+        curve
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.games += other.games;
+        self.rounds += other.rounds;
+        self.openings += other.openings;
+        self.bets += other.bets;
+        self.bluffs += other.bluffs;
+        self.raises += other.raises;
+        self.raised_count += other.raised_count;
+        self.calls += other.calls;
+        self.correct_calls += other.correct_calls;
+        self.dice_lost += other.dice_lost;
+        self.dice_won += other.dice_won;
+        if self.knocked_out_in_round.len() < other.knocked_out_in_round.len() {
+            self.knocked_out_in_round
+                .resize(other.knocked_out_in_round.len(), 0);
+        }
+        for (x, y) in self
+            .knocked_out_in_round
+            .iter_mut()
+            .zip(&other.knocked_out_in_round)
+        {
+            *x += y;
+        }
+    }
+}
+
+/// Statistics of every player over any number of games
+#[derive(Debug, Serialize, Deserialize, Default, Eq, PartialEq, Clone)]
+pub struct Statistics {
+    pub games: usize,
+    pub players: IndexMap<Player, PlayerStats>,
+}
+
+impl Statistics {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn of_game(round_history: &[Round<Called>], config: &GameConfig) -> Self {
+        let mut statistics = Self::new();
+        statistics.add_game(round_history, config);
+        statistics
+    }
+
+    /// Adds the rounds of a single game, which do not need to include the whole game
+    pub fn add_game(&mut self, round_history: &[Round<Called>], config: &GameConfig) {
+        self.games += 1;
+        if let Some(first) = round_history.first() {
+            for player in first.players_rolls().keys() {
+                self.stats_of(player).games += 1;
+            }
+        }
+        self.add_rounds(round_history, 0, config);
+    }
+
+    /// Adds a whole finished game, including players who forfeited and dice taken away outside of
+    /// any round, which the round history alone does not show
+    pub fn add_finished_game(&mut self, game: &Game<GameOver>) {
+        self.games += 1;
+        for player in game.player_dice_counts().keys() {
+            self.stats_of(player).games += 1;
+        }
+        self.add_rounds(game.round_history(), 0, game.config());
+        for (player, dice_left) in game.player_dice_counts() {
+            let lost_in_rounds = game
+                .round_history()
+                .iter()
+                .filter(|x| x.state_data().loser() == player)
+                .count();
+            let lost = game.config().max_dice().get() - dice_left;
+            self.stats_of(player).dice_lost += lost.saturating_sub(lost_in_rounds);
+        }
+        for forfeit in game.forfeits() {
+            let stats = self.stats_of(&forfeit.player);
+            if stats.knocked_out_in_round.len() <= forfeit.rounds_played {
+                stats
+                    .knocked_out_in_round
+                    .resize(forfeit.rounds_played + 1, 0);
+            }
+            stats.knocked_out_in_round[forfeit.rounds_played] += 1;
+        }
+    }
+
+    /// Adds rounds of a game that was already added, starting from round index `first_round`
+    pub fn add_rounds(
+        &mut self,
+        rounds: &[Round<Called>],
+        first_round: usize,
+        config: &GameConfig,
+    ) {
+        let half = BigRational::new(BigInt::from(1), BigInt::from(2));
+        for (round_index, round) in (first_round..).zip(rounds) {
+            let dice_in_play = round.dice_in_play();
+            for player in round.players_rolls().keys() {
+                self.stats_of(player).rounds += 1;
+            }
+            let mut prev_bet = None;
+            for (turn_index, turn) in round.turns().iter().enumerate() {
+                let own_rolls = &round.players_rolls()[&turn.player];
+                let probability = bet_probability(
+                    &turn.bet,
+                    own_rolls,
+                    dice_in_play - own_rolls.len(),
+                    config.max_roll(),
+                    WildRule::OnesWild,
+                );
+                let stats = self.stats_of(&turn.player);
+                if turn_index == 0 {
+                    stats.openings += 1;
+                }
+                stats.bets += 1;
+                stats.bluffs += usize::from(probability < half);
+                if let Some(prev) = prev_bet.replace(turn.bet) {
+                    stats.raises += 1;
+                    stats.raised_count += turn.bet.count.get().saturating_sub(prev.count.get());
+                }
+            }
+            let Called {
+                caller, was_fluff, ..
+            } = round.state_data();
+            let stats = self.stats_of(caller);
+            stats.calls += 1;
+            stats.correct_calls += usize::from(*was_fluff);
+            let loser = round.state_data().loser();
+            self.stats_of(round.state_data().winner()).dice_won += 1;
+            let knocked_out = round.players_rolls()[loser].len() == 1;
+            let stats = self.stats_of(loser);
+            stats.dice_lost += 1;
+            if knocked_out {
+                if stats.knocked_out_in_round.len() <= round_index {
+                    stats.knocked_out_in_round.resize(round_index + 1, 0);
+                }
+                stats.knocked_out_in_round[round_index] += 1;
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.games += other.games;
+        for (player, stats) in &other.players {
+            self.stats_of(player).merge(stats);
+        }
+    }
+
+    fn stats_of(&mut self, player: &Player) -> &mut PlayerStats {
+        if !self.players.contains_key(player) {
+            self.players.insert(player.clone(), PlayerStats::default());
+        }
+        &mut self.players[player]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{
+        bet::Bet,
+        game::{round::RollSet, FluffCallTransition, Game, PlayerRef},
+    };
+
+    #[test]
+    fn test_statistics() {
+        let config = GameConfig::new(NonZeroUsize::MIN, NonZeroUsize::new(6).unwrap());
+        let mut game = Game::new([Player::new("A"), Player::new("B")], config);
+        let rolls = |x: usize| -> RollSet { [NonZeroUsize::new(x).unwrap()].into() };
+        game.set_rolls(
+            [("A", 3), ("B", 5)]
+                .map(|(player, roll)| (PlayerRef::new(Player::new(player)), rolls(roll)))
+                .into_iter()
+                .collect(),
+        )
+        .unwrap();
+        let bet = |count, roll| {
+            Bet::new(
+                NonZeroUsize::new(count).unwrap(),
+                NonZeroUsize::new(roll).unwrap(),
+            )
+        };
+        let mut game = game.raise_bet(bet(1, 3));
+        // B has no 4, so two 4s is a bluff
+        game.raise_bet(bet(2, 4)).unwrap();
+        let FluffCallTransition::GameOver(game) = game.call_fluff() else {
+            panic!("A one die game should end after one round");
+        };
+        let statistics = Statistics::of_game(game.round_history(), &config);
+        let a = &statistics.players[&Player::new("A")];
+        let b = &statistics.players[&Player::new("B")];
+        assert_eq!(
+            (a.openings, a.bets, a.bluffs, a.calls, a.correct_calls),
+            (1, 1, 0, 1, 1)
+        );
+        assert_eq!((b.bets, b.bluffs, b.raises, b.raised_count), (1, 1, 1, 1));
+        assert_eq!((a.dice_won, b.dice_lost), (1, 1));
+        assert_eq!(b.survival_curve(), vec![1.0, 0.0]);
+        assert_eq!(a.survival_curve(), vec![1.0]);
+
+        let mut total = statistics.clone();
+        total.merge(&statistics);
+        assert_eq!(total.games, 2);
+        assert_eq!(
+            total.players[&Player::new("B")].knocked_out_in_round,
+            vec![2]
+        );
+        assert_eq!(total.players[&Player::new("A")].call_accuracy(), 1.0);
+
+        // rounds without their game don't count anyone as having played
+        let mut rounds_only = Statistics::new();
+        rounds_only.add_rounds(game.round_history(), 0, &config);
+        assert_eq!(
+            rounds_only.players[&Player::new("B")].survival_curve(),
+            vec![0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_forfeits() {
+        let config = GameConfig::new(NonZeroUsize::new(2).unwrap(), NonZeroUsize::new(6).unwrap());
+        let [a, b, c] = ["A", "B", "C"].map(Player::new);
+        let game = Game::new([a.clone(), b.clone(), c.clone()], config);
+        let FluffCallTransition::NextRound(game) = game.forfeit_die(&a) else {
+            panic!("A die less should not end the game");
+        };
+        let FluffCallTransition::NextRound(game) = game.forfeit(&b) else {
+            panic!("One forfeit should not end a game of 3");
+        };
+        let FluffCallTransition::GameOver(game) = game.forfeit(&c) else {
+            panic!("The second forfeit should end a game of 3");
+        };
+        assert!(game.round_history().is_empty());
+        let mut statistics = Statistics::new();
+        statistics.add_finished_game(&game);
+        assert_eq!(statistics.games, 1);
+        let stats = |player: &Player| &statistics.players[player];
+        assert!([&a, &b, &c].iter().all(|x| stats(x).games == 1));
+        assert_eq!([&a, &b, &c].map(|x| stats(x).dice_lost), [1, 2, 2]);
+        assert_eq!(stats(&b).survival_curve(), vec![1.0, 0.0]);
+        assert_eq!(stats(&a).survival_curve(), vec![1.0]);
+    }
+}
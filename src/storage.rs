@@ -0,0 +1,272 @@
+//! Keeps games around between runs, whichever state they are in
+
+use thiserror::Error;
+
+use crate::{
+    game::{
+        log::GameLog,
+        session::{Action, ActionError, GameSession, RejectedAction},
+        state::GameOver,
+        Game,
+    },
+    host::GameId,
+    player::Player,
+    statistics::Statistics,
+};
+
+pub mod json;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("No game has id {0}")]
+    UnknownGame(GameId),
+    #[error("Game id {0} is too large for the storage")]
+    IdOutOfRange(GameId),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// A game as it is saved, along with its log if it has one
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct StoredGame {
+    pub session: GameSession,
+    #[serde(default)]
+    pub log: Option<GameLog>,
+}
+
+impl From<GameSession> for StoredGame {
+    fn from(session: GameSession) -> Self {
+        Self { session, log: None }
+    }
+}
+
+/// Somewhere games can be saved after every transition and loaded back in the state they were in
+///
+/// Games are kept apart by whether they are over, so the ones still in progress can be listed
+/// quickly and the finished ones gathered for statistics.
+pub trait Storage {
+    /// Saves `game` as `id`, replacing any earlier save of it
+    fn save(&mut self, id: GameId, game: &StoredGame) -> Result<(), StorageError>;
+
+    fn load(&self, id: GameId) -> Result<StoredGame, StorageError>;
+
+    fn remove(&mut self, id: GameId) -> Result<(), StorageError>;
+
+    /// Ids of every saved game that is not over yet, in increasing order
+    fn in_progress(&self) -> Result<Vec<GameId>, StorageError>;
+
+    /// Every saved game that is over, by increasing id
+    fn finished(&self) -> Result<Vec<(GameId, Game<GameOver>)>, StorageError>;
+
+    /// Statistics over every finished game
+    fn statistics(&self) -> Result<Statistics, StorageError> {
+        let mut statistics = Statistics::new();
+        for (_, game) in self.finished()? {
+            statistics.add_finished_game(&game);
+        }
+        Ok(statistics)
+    }
+
+    /// An id no saved game has yet
+    fn next_id(&self) -> Result<GameId, StorageError> {
+        let in_progress = self.in_progress()?.last().copied();
+        let finished = self.finished()?.last().map(|(id, _)| *id);
+        Ok(in_progress.max(finished).map_or(0, |id| id + 1))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AutosaveError {
+    #[error(transparent)]
+    Action(#[from] ActionError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+/// A game that is saved to a [`Storage`] after every transition, so it can always be picked up
+/// again from its latest state
+#[derive(Debug)]
+pub struct Autosave<S> {
+    storage: S,
+    id: GameId,
+    /// Only [`None`] while a transition is being applied
+    game: Option<StoredGame>,
+}
+
+impl<S: Storage> Autosave<S> {
+    /// Saves `game` as `id` and keeps saving it after every transition
+    pub fn new(mut storage: S, id: GameId, game: StoredGame) -> Result<Self, StorageError> {
+        storage.save(id, &game)?;
+        Ok(Self {
+            storage,
+            id,
+            game: Some(game),
+        })
+    }
+
+    /// Picks up the game saved as `id`
+    pub fn resume(storage: S, id: GameId) -> Result<Self, StorageError> {
+        let game = storage.load(id)?;
+        Ok(Self {
+            storage,
+            id,
+            game: Some(game),
+        })
+    }
+
+    #[must_use]
+    pub fn id(&self) -> GameId {
+        self.id
+    }
+
+    #[must_use]
+    pub fn game(&self) -> &StoredGame {
+        self.game
+            .as_ref()
+            .expect("Game should only be missing while a transition is applied")
+    }
+
+    #[must_use]
+    pub fn session(&self) -> &GameSession {
+        &self.game().session
+    }
+
+    #[must_use]
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    #[must_use]
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+
+    /// Applies `action`, recording it in the log if there is one, and saves the game
+    pub fn act(&mut self, action: Action) -> Result<(), AutosaveError> {
+        self.transition(|log, session| match log {
+            Some(log) => log.act(session, action),
+            None => session.act(action),
+        })
+    }
+
+    /// Knocks `player` out, recording it in the log if there is one, and saves the game
+    pub fn forfeit(&mut self, player: &Player) -> Result<(), AutosaveError> {
+        self.transition(|log, session| match log {
+            Some(log) => log.forfeit(session, player),
+            None => session.forfeit(player),
+        })
+    }
+
+    /// Saves the game only if `apply` succeeds, leaving it as it was otherwise
+    fn transition(
+        &mut self,
+        apply: impl FnOnce(Option<&mut GameLog>, GameSession) -> Result<GameSession, RejectedAction>,
+    ) -> Result<(), AutosaveError> {
+        let StoredGame { session, mut log } = self
+            .game
+            .take()
+            .expect("Game should only be missing while a transition is applied");
+        let (session, result) = match apply(log.as_mut(), session) {
+            Ok(session) => (session, Ok(())),
+            Err(rejected) => (*rejected.session, Err(rejected.error)),
+        };
+        let game = self.game.insert(StoredGame { session, log });
+        result?;
+        self.storage.save(self.id, game)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{
+        bet::Bet,
+        game::{session::Action, GameConfig},
+        player::Player,
+    };
+
+    /// Checks that `storage`, which should start out empty, keeps games apart by their state
+    pub(crate) fn check_storage(storage: &mut dyn Storage) {
+        let config = GameConfig::new(NonZeroUsize::MIN, NonZeroUsize::new(6).unwrap());
+        let (mut log, mut session) = GameLog::start([Player::new("A"), Player::new("B")], config);
+        let stored = |log: &GameLog, session: &GameSession| StoredGame {
+            session: session.clone(),
+            log: Some(log.clone()),
+        };
+        assert_eq!(storage.next_id().unwrap(), 0);
+        storage.save(0, &stored(&log, &session)).unwrap();
+        let other = Game::new([Player::new("C"), Player::new("D")], config);
+        storage.save(1, &GameSession::from(other).into()).unwrap();
+        assert_eq!(storage.in_progress().unwrap(), [0, 1]);
+        assert_eq!(storage.next_id().unwrap(), 2);
+
+        // a bet moves the game into Betting, which loads back as such
+        let bet = Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN));
+        session = log.act(session, bet).unwrap();
+        storage.save(0, &stored(&log, &session)).unwrap();
+        let loaded = storage.load(0).unwrap();
+        assert!(matches!(loaded.session, GameSession::Betting(_)));
+        assert_eq!(loaded, stored(&log, &session));
+
+        // with one die each, the call ends the game
+        session = log.act(session, Action::CallFluff).unwrap();
+        storage.save(0, &stored(&log, &session)).unwrap();
+        assert_eq!(storage.in_progress().unwrap(), [1]);
+        let finished = storage.finished().unwrap();
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].0, 0);
+        assert_eq!(storage.statistics().unwrap().games, 1);
+        assert_eq!(storage.next_id().unwrap(), 2);
+
+        storage.remove(1).unwrap();
+        assert!(storage.in_progress().unwrap().is_empty());
+        assert!(matches!(storage.load(1), Err(StorageError::UnknownGame(1))));
+    }
+
+    #[test]
+    fn test_autosave() {
+        let dir = std::env::temp_dir().join(format!("fluff-autosave-{}", std::process::id()));
+        let config = GameConfig::new(NonZeroUsize::MIN, NonZeroUsize::new(6).unwrap());
+        let players = [Player::new("A"), Player::new("B")];
+        let (log, session) = GameLog::start(players.clone(), config);
+        let storage = json::JsonDirectory::open(&dir).unwrap();
+        let mut game = Autosave::new(
+            storage,
+            0,
+            StoredGame {
+                session,
+                log: Some(log),
+            },
+        )
+        .unwrap();
+        assert_eq!(game.storage().load(0).unwrap(), *game.game());
+
+        let bet = Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::MIN));
+        game.act(bet).unwrap();
+        assert_eq!(game.storage().load(0).unwrap(), *game.game());
+
+        // a rejected action is not saved and leaves the game as it was
+        let saved = game.game().clone();
+        assert!(matches!(game.act(bet), Err(AutosaveError::Action(_))));
+        assert_eq!(*game.game(), saved);
+        assert_eq!(game.storage().load(0).unwrap(), saved);
+
+        let mut game = Autosave::resume(game.into_storage(), 0).unwrap();
+        assert_eq!(*game.game(), saved);
+        game.forfeit(&players[0]).unwrap();
+        assert!(game.session().is_over());
+        assert!(game.storage().in_progress().unwrap().is_empty());
+        assert_eq!(game.storage().load(0).unwrap().log, game.game().log);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    game::{session::GameSession, state::GameOver, Game},
+    host::GameId,
+    storage::{Storage, StorageError, StoredGame},
+};
+
+/// Games saved as one JSON file each, in a folder for games in progress and one for finished games
+#[derive(Debug, Clone)]
+pub struct JsonDirectory {
+    root: PathBuf,
+}
+
+impl JsonDirectory {
+    /// Uses `root` for saving games, creating it if needed
+    pub fn open(root: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let storage = Self { root: root.into() };
+        std::fs::create_dir_all(storage.folder(false))?;
+        std::fs::create_dir_all(storage.folder(true))?;
+        Ok(storage)
+    }
+
+    #[must_use]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn folder(&self, finished: bool) -> PathBuf {
+        self.root
+            .join(if finished { "finished" } else { "in-progress" })
+    }
+
+    fn path(&self, id: GameId, finished: bool) -> PathBuf {
+        self.folder(finished).join(format!("{id}.json"))
+    }
+
+    fn read(path: &Path) -> Result<Option<StoredGame>, StorageError> {
+        match File::open(path) {
+            Ok(file) => Ok(Some(serde_json::from_reader(BufReader::new(file))?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn remove_file(path: &Path) -> std::io::Result<()> {
+        match std::fs::remove_file(path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Ids of every game in one of the folders, in increasing order
+    fn ids(&self, finished: bool) -> Result<Vec<GameId>, StorageError> {
+        let mut ids = Vec::new();
+        for entry in std::fs::read_dir(self.folder(finished))? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|x| x == "json") {
+                if let Some(id) = path
+                    .file_stem()
+                    .and_then(|x| x.to_str())
+                    .and_then(|x| x.parse().ok())
+                {
+                    ids.push(id);
+                }
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+}
+
+impl Storage for JsonDirectory {
+    fn save(&mut self, id: GameId, game: &StoredGame) -> Result<(), StorageError> {
+        let finished = game.session.is_over();
+        let path = self.path(id, finished);
+        // written next to the old save first, so a crash never leaves half a game behind
+        let partial = path.with_extension("partial");
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        serde_json::to_writer(&mut writer, game)?;
+        writer.flush()?;
+        std::fs::rename(partial, path)?;
+        Self::remove_file(&self.path(id, !finished))?;
+        Ok(())
+    }
+
+    /// Loads the finished save of a game if there is one, since games never go back to being in
+    /// progress and a crash while finishing one can leave its old save behind
+    fn load(&self, id: GameId) -> Result<StoredGame, StorageError> {
+        match Self::read(&self.path(id, true))? {
+            Some(game) => Ok(game),
+            None => Self::read(&self.path(id, false))?.ok_or(StorageError::UnknownGame(id)),
+        }
+    }
+
+    fn remove(&mut self, id: GameId) -> Result<(), StorageError> {
+        Self::remove_file(&self.path(id, false))?;
+        Self::remove_file(&self.path(id, true))?;
+        Ok(())
+    }
+
+    fn in_progress(&self) -> Result<Vec<GameId>, StorageError> {
+        let finished = self.ids(true)?;
+        let mut ids = self.ids(false)?;
+        ids.retain(|id| finished.binary_search(id).is_err());
+        Ok(ids)
+    }
+
+    fn finished(&self) -> Result<Vec<(GameId, Game<GameOver>)>, StorageError> {
+        self.ids(true)?
+            .into_iter()
+            .filter_map(|id| match self.load(id) {
+                Ok(StoredGame {
+                    session: GameSession::GameOver(game),
+                    ..
+                }) => Some(Ok((id, game))),
+                // only a game that was just saved again could have moved or not be over
+                Ok(_) | Err(StorageError::UnknownGame(_)) => None,
+                Err(err) => Some(Err(err)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{game::GameConfig, player::Player, storage::tests::check_storage};
+
+    #[test]
+    fn test_json_directory() {
+        let root = std::env::temp_dir().join(format!("fluff-storage-{}", std::process::id()));
+        let mut storage = JsonDirectory::open(&root).unwrap();
+        check_storage(&mut storage);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_stale_in_progress_save() {
+        let root = std::env::temp_dir().join(format!("fluff-stale-{}", std::process::id()));
+        let mut storage = JsonDirectory::open(&root).unwrap();
+        let config = GameConfig::new(NonZeroUsize::MIN, NonZeroUsize::new(6).unwrap());
+        let session = GameSession::from(Game::new([Player::new("A"), Player::new("B")], config));
+        storage.save(0, &session.clone().into()).unwrap();
+        let stale = root.join("stale.json");
+        std::fs::copy(storage.path(0, false), &stale).unwrap();
+
+        // as if the game finished but the crash came before its old save was removed
+        let session = session.forfeit(&Player::new("A")).unwrap();
+        storage.save(0, &session.into()).unwrap();
+        std::fs::rename(stale, storage.path(0, false)).unwrap();
+        assert!(storage.load(0).unwrap().session.is_over());
+        assert!(storage.in_progress().unwrap().is_empty());
+        assert_eq!(storage.finished().unwrap().len(), 1);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{
+    game::{session::GameSession, state::GameOver, Game},
+    host::GameId,
+    storage::{Storage, StorageError, StoredGame},
+};
+
+/// Games saved as JSON in a single table of an SQLite database
+#[derive(Debug)]
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path`, creating it if needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        Self::new(Connection::open(path)?)
+    }
+
+    /// A database that only lives as long as the storage, e.g. for tests
+    pub fn in_memory() -> Result<Self, StorageError> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    fn new(connection: Connection) -> Result<Self, StorageError> {
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS games (
+                id INTEGER PRIMARY KEY,
+                finished INTEGER NOT NULL,
+                game TEXT NOT NULL
+            )",
+            (),
+        )?;
+        Ok(Self { connection })
+    }
+}
+
+/// SQLite integers are signed, so ids that do not fit in one cannot be saved
+fn to_sql(id: GameId) -> Result<i64, StorageError> {
+    i64::try_from(id).map_err(|_| StorageError::IdOutOfRange(id))
+}
+
+impl Storage for SqliteStorage {
+    fn save(&mut self, id: GameId, game: &StoredGame) -> Result<(), StorageError> {
+        self.connection.execute(
+            "INSERT INTO games (id, finished, game) VALUES (?1, ?2, ?3)
+                ON CONFLICT (id) DO UPDATE SET finished = ?2, game = ?3",
+            params![
+                to_sql(id)?,
+                game.session.is_over(),
+                serde_json::to_string(game)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn load(&self, id: GameId) -> Result<StoredGame, StorageError> {
+        let game = self
+            .connection
+            .query_row(
+                "SELECT game FROM games WHERE id = ?1",
+                [to_sql(id)?],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .ok_or(StorageError::UnknownGame(id))?;
+        Ok(serde_json::from_str(&game)?)
+    }
+
+    fn remove(&mut self, id: GameId) -> Result<(), StorageError> {
+        self.connection
+            .execute("DELETE FROM games WHERE id = ?1", [to_sql(id)?])?;
+        Ok(())
+    }
+
+    fn in_progress(&self) -> Result<Vec<GameId>, StorageError> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT id FROM games WHERE finished = 0 ORDER BY id")?;
+        let ids = statement
+            .query_map((), |row| row.get::<_, i64>(0))?
+            .map(|id| Ok(id? as GameId))
+            .collect();
+        ids
+    }
+
+    fn finished(&self) -> Result<Vec<(GameId, Game<GameOver>)>, StorageError> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT id, game FROM games WHERE finished = 1 ORDER BY id")?;
+        let games = statement
+            .query_map((), |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .filter_map(|row| {
+                let (id, game) = match row {
+                    Ok(row) => row,
+                    Err(err) => return Some(Err(err.into())),
+                };
+                match serde_json::from_str::<StoredGame>(&game) {
+                    Ok(StoredGame {
+                        session: GameSession::GameOver(game),
+                        ..
+                    }) => Some(Ok((id as GameId, game))),
+                    Ok(_) => None,
+                    Err(err) => Some(Err(err.into())),
+                }
+            })
+            .collect();
+        games
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::check_storage;
+
+    #[test]
+    fn test_sqlite() {
+        check_storage(&mut SqliteStorage::in_memory().unwrap());
+    }
+
+    #[test]
+    fn test_id_out_of_range() {
+        let mut storage = SqliteStorage::in_memory().unwrap();
+        let game = StoredGame::from(GameSession::from(Game::new(
+            ["A", "B"].map(crate::player::Player::new),
+            crate::game::GameConfig::default(),
+        )));
+        assert!(matches!(
+            storage.save(GameId::MAX, &game),
+            Err(StorageError::IdOutOfRange(GameId::MAX))
+        ));
+        assert!(matches!(
+            storage.load(GameId::MAX),
+            Err(StorageError::IdOutOfRange(GameId::MAX))
+        ));
+    }
+}
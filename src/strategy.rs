@@ -0,0 +1,186 @@
+use indexmap::IndexMap;
+use thiserror::Error;
+
+use crate::{
+    game::{
+        log::GameLog,
+        session::{Action, GameSession, RejectedAction},
+        view::PlayerView,
+        PlayerRef,
+    },
+    player::Player,
+};
+
+pub mod adaptive;
+pub mod bots;
+pub mod process;
+
+/// Decides what a player does on their turn
+pub trait Strategy {
+    /// Picks an action for `view.viewer`, which is only called when it is their turn
+    fn act(&mut self, view: &PlayerView) -> Action;
+
+    /// Probability of every action [`Strategy::act`] could pick, for evaluating the strategy
+    ///
+    /// Strategies that pick randomly should override this, since by default it assumes whatever
+    /// [`Strategy::act`] picks is certain.
+    fn policy(&mut self, view: &PlayerView) -> Vec<(Action, f64)> {
+        vec![(self.act(view), 1.0)]
+    }
+
+    /// Shows the strategy how the game ended, e.g. to learn from the rounds after its last turn
+    fn game_over(&mut self, _view: &PlayerView) {}
+}
+
+impl<S: Strategy + ?Sized> Strategy for Box<S> {
+    fn act(&mut self, view: &PlayerView) -> Action {
+        (**self).act(view)
+    }
+
+    fn policy(&mut self, view: &PlayerView) -> Vec<(Action, f64)> {
+        (**self).policy(view)
+    }
+
+    fn game_over(&mut self, view: &PlayerView) {
+        (**self).game_over(view);
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Bot {player} tried {action:?}, which was rejected: {}", rejected.error)]
+pub struct BotError {
+    pub player: PlayerRef,
+    pub action: Action,
+    pub rejected: RejectedAction,
+}
+
+/// Plays the turns of every bot in a game, leaving the rest to whoever is driving the game
+#[derive(Default)]
+pub struct Driver {
+    bots: IndexMap<PlayerRef, Box<dyn Strategy>>,
+    /// Whether the bots were already shown the end of the current game
+    finished: bool,
+}
+
+impl Driver {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_bot(mut self, player: Player, strategy: impl Strategy + 'static) -> Self {
+        self.add_bot(player, strategy);
+        self
+    }
+
+    pub fn add_bot(&mut self, player: Player, strategy: impl Strategy + 'static) {
+        self.bots.insert(player.into(), Box::new(strategy));
+    }
+
+    #[must_use]
+    pub fn is_bot(&self, player: &Player) -> bool {
+        self.bots.contains_key(player)
+    }
+
+    /// The action of the bot whose turn it is, or [`None`] if it is a human's turn or the game is
+    /// over
+    pub fn bot_action(&mut self, session: &GameSession) -> Option<Action> {
+        let player = session.current_player()?;
+        let bot = self.bots.get_mut(player)?;
+        Some(bot.act(&session.view_for(player)))
+    }
+
+    /// Calls [`Strategy::game_over`] on every bot if `session` is over and they were not told yet
+    pub fn finish(&mut self, session: &GameSession) {
+        if !session.is_over() {
+            self.finished = false;
+            return;
+        }
+        if std::mem::replace(&mut self.finished, true) {
+            return;
+        }
+        for (player, bot) in &mut self.bots {
+            bot.game_over(&session.view_for(player));
+        }
+    }
+
+    /// Plays bot turns until it is a human's turn or the game is over
+    pub fn advance(&mut self, session: GameSession) -> Result<GameSession, BotError> {
+        self.advance_with(session, GameSession::act)
+    }
+
+    /// Same as [`Driver::advance`], but records every bot action in `log`
+    pub fn advance_logged(
+        &mut self,
+        log: &mut GameLog,
+        session: GameSession,
+    ) -> Result<GameSession, BotError> {
+        self.advance_with(session, |session, action| log.act(session, action))
+    }
+
+    /// Same as [`Driver::advance`], but applies each bot action with `apply`, e.g. to record or
+    /// show it
+    ///
+    /// Once the game is over, the bots are shown how it ended with [`Driver::finish`].
+    pub fn advance_with(
+        &mut self,
+        mut session: GameSession,
+        mut apply: impl FnMut(GameSession, Action) -> Result<GameSession, RejectedAction>,
+    ) -> Result<GameSession, BotError> {
+        while let Some(action) = self.bot_action(&session) {
+            let player = session
+                .current_player()
+                .expect("Only unfinished games have bot actions")
+                .clone();
+            session = apply(session, action).map_err(|rejected| BotError {
+                player,
+                action,
+                rejected,
+            })?;
+        }
+        self.finish(&session);
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bots::*, *};
+    use crate::game::GameConfig;
+
+    #[test]
+    fn test_bots_finish_game() {
+        let mut driver = Driver::new()
+            .with_bot(Player::new("Unga"), RandomLegal::new(1))
+            .with_bot(Player::new("Bunga"), Cautious::default())
+            .with_bot(Player::new("Ooga"), Bluffer::new(2));
+        let (mut log, session) = GameLog::start(
+            ["Unga", "Bunga", "Ooga"].map(Player::new),
+            GameConfig::default(),
+        );
+        let session = driver.advance_logged(&mut log, session).unwrap();
+        assert!(session.is_over());
+        assert_eq!(crate::game::log::replay(&log).unwrap(), session);
+    }
+
+    #[test]
+    fn test_driver_stops_for_humans() {
+        let mut driver = Driver::new().with_bot(Player::new("Bunga"), Cautious::default());
+        let (_, session) =
+            GameLog::start(["Unga", "Bunga"].map(Player::new), GameConfig::default());
+        let session = driver.advance(session).unwrap();
+        assert_eq!(session.current_player().unwrap().as_str(), "Unga");
+        let session = session
+            .act(Action::Raise(crate::bet::Bet::new(
+                std::num::NonZeroUsize::MIN,
+                std::num::NonZeroUsize::MIN,
+            )))
+            .unwrap();
+        let session = driver.advance(session).unwrap();
+        assert!(matches!(
+            session.current_player().map(|x| x.as_str()),
+            Some("Unga") | None
+        ));
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    game::{session::Action, view::PlayerView, PlayerRef},
+    player::Player,
+    statistics::Statistics,
+    strategy::{
+        bots::{bet_probabilities, probability_of},
+        Strategy,
+    },
+};
+
+/// How often a player is assumed to bluff before anything is known about them
+const PRIOR_BLUFF_RATE: f64 = 0.25;
+/// How often a player's calls are assumed to be right before anything is known about them
+const PRIOR_CALL_ACCURACY: f64 = 0.5;
+/// How many observations the priors are worth
+const PRIOR_WEIGHT: f64 = 8.0;
+
+/// What has been learned about every opponent so far, across any number of games
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq, Clone)]
+pub struct OpponentModel {
+    pub statistics: Statistics,
+}
+
+impl OpponentModel {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Estimated share of `player`'s bets that are bluffs, which starts from a prior and moves
+    /// towards their observed bluff rate as more of their bets are seen
+    #[must_use]
+    pub fn bluff_rate(&self, player: &Player) -> f64 {
+        self.statistics
+            .players
+            .get(player)
+            .map_or(PRIOR_BLUFF_RATE, |x| {
+                (x.bluffs as f64 + PRIOR_BLUFF_RATE * PRIOR_WEIGHT) / (x.bets as f64 + PRIOR_WEIGHT)
+            })
+    }
+
+    /// Estimated share of `player`'s fluff calls that are right
+    #[must_use]
+    pub fn call_accuracy(&self, player: &Player) -> f64 {
+        self.statistics
+            .players
+            .get(player)
+            .map_or(PRIOR_CALL_ACCURACY, |x| {
+                (x.correct_calls as f64 + PRIOR_CALL_ACCURACY * PRIOR_WEIGHT)
+                    / (x.calls as f64 + PRIOR_WEIGHT)
+            })
+    }
+
+    /// Reads a model saved with [`OpponentModel::save`]
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        Ok(serde_json::to_writer_pretty(File::create(path)?, self)?)
+    }
+}
+
+/// Plays like [`Cautious`](super::bots::Cautious), but calls fluff more readily on players who
+/// have been seen to bluff a lot and less readily on players who rarely bluff, and raises higher
+/// against players whose calls are often wrong
+///
+/// It learns from the rounds in every view it is given and from the end of the game, so the same
+/// bot should be kept for a whole game. The model can be shared between bots in different games,
+/// e.g. every game of a simulation, to learn across games, but not between bots in the same game,
+/// since each of them would add the same rounds to it.
+#[derive(Debug, Clone)]
+pub struct Adaptive {
+    pub model: Arc<Mutex<OpponentModel>>,
+    /// Call threshold against a player who bluffs as often as the prior assumes
+    pub base_threshold: f64,
+    /// How far the call threshold and raise margin move per unit of difference from the prior
+    /// bluff rate and call accuracy
+    pub sensitivity: f64,
+    /// Rounds of the current game already learned from
+    learned_rounds: usize,
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Self::new(Arc::default())
+    }
+}
+
+impl Adaptive {
+    #[must_use]
+    pub fn new(model: Arc<Mutex<OpponentModel>>) -> Self {
+        Self {
+            model,
+            base_threshold: 0.5,
+            sensitivity: 1.0,
+            learned_rounds: 0,
+        }
+    }
+
+    /// Probability below which a bet by `better` gets called
+    #[must_use]
+    pub fn call_threshold(&self, better: &Player) -> f64 {
+        let bluff_rate = self
+            .model
+            .lock()
+            .expect("Model lock should not be poisoned")
+            .bluff_rate(better);
+        (self.base_threshold + self.sensitivity * (bluff_rate - PRIOR_BLUFF_RATE)).clamp(0.05, 0.95)
+    }
+
+    /// How much less likely than the safest raise a raise before `caller` is allowed to be, which
+    /// is 0 unless `caller` calls fluff less accurately than the prior assumes
+    #[must_use]
+    pub fn raise_margin(&self, caller: &Player) -> f64 {
+        let call_accuracy = self
+            .model
+            .lock()
+            .expect("Model lock should not be poisoned")
+            .call_accuracy(caller);
+        (self.sensitivity * (PRIOR_CALL_ACCURACY - call_accuracy)).clamp(0.0, 0.5)
+    }
+
+    /// The highest raise within [`Adaptive::raise_margin`] of the safest one, against whoever
+    /// decides whether to call it
+    fn raise(&self, view: &PlayerView, probabilities: &[Vec<f64>]) -> Action {
+        let raises = view
+            .legal_actions()
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Raise(bet) => Some((probability_of(probabilities, &bet), bet)),
+                Action::CallFluff => None,
+            })
+            .collect::<Vec<_>>();
+        let Some(safest) = raises.iter().map(|(p, _)| *p).max_by(f64::total_cmp) else {
+            return Action::CallFluff;
+        };
+        let margin = next_player(view).map_or(0.0, |x| self.raise_margin(x));
+        raises
+            .into_iter()
+            .filter(|(p, _)| *p >= safest - margin)
+            .map(|(_, bet)| bet)
+            .max()
+            .map_or(Action::CallFluff, Action::Raise)
+    }
+
+    fn learn(&mut self, view: &PlayerView) {
+        // a shorter history than already seen can only be a new game
+        if view.round_history.len() < self.learned_rounds {
+            self.learned_rounds = 0;
+        }
+        let new_rounds = &view.round_history[self.learned_rounds..];
+        let statistics = &mut self
+            .model
+            .lock()
+            .expect("Model lock should not be poisoned")
+            .statistics;
+        if self.learned_rounds == 0 && !new_rounds.is_empty() {
+            statistics.add_game(new_rounds, &view.config);
+        } else {
+            statistics.add_rounds(new_rounds, self.learned_rounds, &view.config);
+        }
+        self.learned_rounds = view.round_history.len();
+    }
+}
+
+/// Whoever has the turn after the viewer, if the viewer raises
+fn next_player(view: &PlayerView) -> Option<&PlayerRef> {
+    let in_play = view
+        .player_dice_counts
+        .iter()
+        .filter(|(_, dice)| **dice != 0)
+        .map(|(player, _)| player)
+        .collect::<Vec<_>>();
+    let index = in_play.iter().position(|x| **x == view.viewer)?;
+    in_play
+        .get((index + 1) % in_play.len())
+        .copied()
+        .filter(|x| **x != view.viewer)
+}
+
+impl Strategy for Adaptive {
+    fn act(&mut self, view: &PlayerView) -> Action {
+        self.learn(view);
+        let probabilities = bet_probabilities(view);
+        if let Some(turn) = view.turns.last() {
+            if probability_of(&probabilities, &turn.bet) < self.call_threshold(&turn.player) {
+                return Action::CallFluff;
+            }
+        }
+        self.raise(view, &probabilities)
+    }
+
+    fn game_over(&mut self, view: &PlayerView) {
+        self.learn(view);
+        self.learned_rounds = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{
+        game::{session::GameSession, Game, GameConfig},
+        strategy::{
+            bots::{Bluffer, Cautious},
+            Driver,
+        },
+    };
+
+    #[test]
+    fn test_learns_across_games() {
+        let config = GameConfig::new(NonZeroUsize::new(3).unwrap(), NonZeroUsize::new(6).unwrap());
+        let [learner, bluffer, cautious] = ["Learner", "Bluffer", "Cautious"].map(Player::new);
+        let model = Arc::new(Mutex::new(OpponentModel::new()));
+        for seed in 0..10 {
+            let mut driver = Driver::new()
+                .with_bot(learner.clone(), Adaptive::new(Arc::clone(&model)))
+                .with_bot(bluffer.clone(), Bluffer::new(seed))
+                .with_bot(cautious.clone(), Cautious::default());
+            let game = Game::new([&learner, &bluffer, &cautious].map(Clone::clone), config);
+            let GameSession::GameOver(_) = driver.advance(game.into()).unwrap() else {
+                panic!("A game between bots should be played to the end");
+            };
+        }
+        let adaptive = Adaptive::new(Arc::clone(&model));
+        assert!(adaptive.call_threshold(&bluffer) > adaptive.call_threshold(&cautious));
+        let model = model.lock().unwrap();
+        assert!(model.statistics.players[&bluffer].bets > 0);
+        assert!(model.bluff_rate(&bluffer) > model.bluff_rate(&cautious));
+
+        let path = std::env::temp_dir().join(format!("fluff-model-{}.json", std::process::id()));
+        model.save(&path).unwrap();
+        let loaded = OpponentModel::load(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.statistics, model.statistics);
+    }
+
+    #[test]
+    fn test_learns_from_game_end() {
+        let config = GameConfig::new(NonZeroUsize::new(2).unwrap(), NonZeroUsize::new(6).unwrap());
+        let [learner, other] = ["Learner", "Other"].map(Player::new);
+        let model = Arc::new(Mutex::new(OpponentModel::new()));
+        let mut driver = Driver::new()
+            .with_bot(learner.clone(), Adaptive::new(Arc::clone(&model)))
+            .with_bot(other.clone(), Bluffer::new(3));
+        let game = Game::new([learner, other], config);
+        let GameSession::GameOver(game) = driver.advance(game.into()).unwrap() else {
+            panic!("A game between bots should be played to the end");
+        };
+        // every round ends with one call, including the last one after the learner's last turn
+        let model = model.lock().unwrap();
+        let calls = model
+            .statistics
+            .players
+            .values()
+            .map(|x| x.calls)
+            .sum::<usize>();
+        assert_eq!(calls, game.round_history().len());
+        assert_eq!(model.statistics.games, 1);
+    }
+
+    #[test]
+    fn test_raises_higher_against_bad_callers() {
+        let config = GameConfig::new(NonZeroUsize::new(3).unwrap(), NonZeroUsize::new(6).unwrap());
+        let players = ["Learner", "Caller"].map(Player::new);
+        let session = GameSession::from(Game::new(players.clone(), config));
+        let viewer = session.current_player().unwrap().clone();
+        let caller = players.iter().find(|x| **x != *viewer).unwrap();
+        let view = session.view_for(&viewer);
+
+        let mut adaptive = Adaptive::default();
+        assert_eq!(adaptive.raise_margin(caller), 0.0);
+        let Action::Raise(safe) = adaptive.act(&view) else {
+            panic!("Opening should be a raise");
+        };
+        let mut model = OpponentModel::new();
+        let stats = model.statistics.players.entry(caller.clone()).or_default();
+        stats.calls = 20;
+        let mut adaptive = Adaptive::new(Arc::new(Mutex::new(model)));
+        assert!(adaptive.raise_margin(caller) > 0.3);
+        let Action::Raise(bold) = adaptive.act(&view) else {
+            panic!("Opening should be a raise");
+        };
+        assert!(bold > safe);
+    }
+}
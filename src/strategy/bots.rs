@@ -0,0 +1,188 @@
+use std::num::NonZeroUsize;
+
+use num_traits::ToPrimitive;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    bet::{Bet, WildRule},
+    game::{session::Action, view::PlayerView},
+    probability::MatchDistribution,
+    strategy::Strategy,
+};
+
+/// Probability of each bet being true from the viewer's point of view, indexed by roll - 1 and then
+/// by count
+pub(crate) fn bet_probabilities(view: &PlayerView) -> Vec<Vec<f64>> {
+    let max_roll = view.config.max_roll();
+    (1..=max_roll.get())
+        .filter_map(NonZeroUsize::new)
+        .map(|roll| {
+            let dist = MatchDistribution::new(
+                roll,
+                view.own_rolls(),
+                view.unknown_dice(),
+                max_roll,
+                WildRule::OnesWild,
+            );
+            (0..=view.total_dice_in_play() + 1)
+                .map(|count| {
+                    dist.probability_at_least(count)
+                        .to_f64()
+                        .expect("Probabilities should fit in an f64")
+                })
+                .collect()
+        })
+        .collect()
+}
+
+pub(crate) fn probability_of(probabilities: &[Vec<f64>], bet: &Bet) -> f64 {
+    probabilities
+        .get(bet.roll.get() - 1)
+        .map_or(0.0, |x| x.get(bet.count.get()).copied().unwrap_or(0.0))
+}
+
+/// Picks uniformly between every action in [`PlayerView::legal_actions`]
+#[derive(Debug, Clone)]
+pub struct RandomLegal {
+    rng: StdRng,
+}
+
+impl RandomLegal {
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Strategy for RandomLegal {
+    fn act(&mut self, view: &PlayerView) -> Action {
+        *view
+            .legal_actions()
+            .choose(&mut self.rng)
+            .expect("There should be a legal action on the bot's turn")
+    }
+
+    fn policy(&mut self, view: &PlayerView) -> Vec<(Action, f64)> {
+        let actions = view.legal_actions();
+        let probability = 1.0 / actions.len() as f64;
+        actions.into_iter().map(|x| (x, probability)).collect()
+    }
+}
+
+/// Calls fluff whenever the current bet is less likely than `threshold` to be true, and otherwise
+/// raises to whichever bet is most likely to be true
+#[derive(Debug, Clone, Copy)]
+pub struct Cautious {
+    pub threshold: f64,
+}
+
+impl Default for Cautious {
+    fn default() -> Self {
+        Self { threshold: 0.5 }
+    }
+}
+
+impl Strategy for Cautious {
+    fn act(&mut self, view: &PlayerView) -> Action {
+        let probabilities = bet_probabilities(view);
+        if let Some(prev) = view.last_bet() {
+            if probability_of(&probabilities, &prev) < self.threshold {
+                return Action::CallFluff;
+            }
+        }
+        view.legal_actions()
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Raise(bet) => Some((probability_of(&probabilities, &bet), bet)),
+                _ => None,
+            })
+            .max_by(|(p_a, a), (p_b, b)| p_a.total_cmp(p_b).then(a.cmp(b)))
+            .map_or(Action::CallFluff, |(_, bet)| Action::Raise(bet))
+    }
+}
+
+/// Bets on whatever roll it has the most of, jumping the count to bluff some of the time, and only
+/// calls fluff on bets that are very unlikely to be true
+#[derive(Debug, Clone)]
+pub struct Bluffer {
+    pub call_threshold: f64,
+    pub bluff_rate: f64,
+    rng: StdRng,
+}
+
+impl Bluffer {
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        Self {
+            call_threshold: 0.2,
+            bluff_rate: 0.35,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Bluffer {
+    /// The action to take without bluffing
+    fn honest_action(&self, view: &PlayerView) -> Action {
+        let prev = view.last_bet();
+        if let Some(prev) = prev {
+            if probability_of(&bet_probabilities(view), &prev) < self.call_threshold {
+                return Action::CallFluff;
+            }
+        }
+        let max_roll = view.config.max_roll();
+        let roll = (1..=max_roll.get())
+            .filter_map(NonZeroUsize::new)
+            .max_by_key(|roll| {
+                (
+                    view.own_rolls()
+                        .iter()
+                        .filter(|x| WildRule::OnesWild.matches(*roll, **x))
+                        .count(),
+                    *roll,
+                )
+            })
+            .expect("Max roll should be at least 1");
+        Action::Raise(match prev {
+            Some(prev) => {
+                let same_count = Bet::new(prev.count, roll);
+                if same_count.is_raised_from(&prev).is_ok() {
+                    same_count
+                } else {
+                    Bet::new(prev.count.saturating_add(1), roll)
+                }
+            }
+            None => Bet::new(NonZeroUsize::MIN, roll),
+        })
+    }
+}
+
+fn bluff(action: Action) -> Action {
+    match action {
+        Action::Raise(bet) => Action::Raise(Bet::new(bet.count.saturating_add(1), bet.roll)),
+        Action::CallFluff => Action::CallFluff,
+    }
+}
+
+impl Strategy for Bluffer {
+    fn act(&mut self, view: &PlayerView) -> Action {
+        let action = self.honest_action(view);
+        if action != Action::CallFluff && self.rng.gen_bool(self.bluff_rate) {
+            bluff(action)
+        } else {
+            action
+        }
+    }
+
+    fn policy(&mut self, view: &PlayerView) -> Vec<(Action, f64)> {
+        match self.honest_action(view) {
+            Action::CallFluff => vec![(Action::CallFluff, 1.0)],
+            action => vec![
+                (action, 1.0 - self.bluff_rate),
+                (bluff(action), self.bluff_rate),
+            ],
+        }
+    }
+}
@@ -0,0 +1,337 @@
+//! Bots that run as separate processes and talk to the engine over stdin and stdout
+//!
+//! The protocol is line oriented: every message is a single line of JSON. Whenever it is the bot's
+//! turn, the engine writes an [`EngineMessage::Act`] to the bot's stdin, with the bot's
+//! [`PlayerView`] and its legal actions:
+//!
+//! ```text
+//! {"type":"act","id":1,"view":{...},"legal_actions":[{"Raise":{"count":1,"roll":2}},...]}
+//! ```
+//!
+//! The bot answers on stdout with a [`BotMessage`] carrying the same `id`:
+//!
+//! ```text
+//! {"id":1,"action":{"Raise":{"count":1,"roll":2}}}
+//! {"id":2,"action":"CallFluff"}
+//! ```
+//!
+//! Answers that are late, malformed or illegal are recorded as [`Fault`]s and the engine plays a
+//! fallback strategy's action instead, telling the bot with an [`EngineMessage::Rejected`]. Lines
+//! with an outdated `id` are ignored, so a bot that missed a deadline can catch up. A bot that
+//! exits or closes stdout is considered crashed and the fallback plays the rest of its turns. The
+//! bot's stderr is passed through untouched, so it can be used for debugging output.
+
+use std::{
+    io::{BufRead, BufReader, BufWriter, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::{
+    bet::RaiseError,
+    game::{session::Action, view::PlayerView},
+    strategy::{bots::Cautious, Strategy},
+};
+
+/// A message the engine sends to a bot
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EngineMessage {
+    /// Asks the bot to act, which it should answer with a [`BotMessage`] with the same `id`
+    Act {
+        id: u64,
+        view: Box<PlayerView>,
+        legal_actions: Vec<Action>,
+    },
+    /// Tells the bot its answer to the request `id` was not used
+    Rejected { id: u64, reason: String },
+}
+
+/// A bot's answer to an [`EngineMessage::Act`]
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub struct BotMessage {
+    pub id: u64,
+    pub action: Action,
+}
+
+/// Something that went wrong while asking a bot to act
+#[derive(Error, Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub enum Fault {
+    #[error("Bot did not answer within {0:?}")]
+    Timeout(Duration),
+    #[error("Bot sent a line that is not a valid answer: {0}")]
+    Malformed(String),
+    #[error("Bot tried {action:?}, which is illegal: {reason}")]
+    Illegal { action: Action, reason: String },
+    #[error("Bot process stopped: {0}")]
+    Crashed(String),
+}
+
+/// A [`Strategy`] backed by a child process speaking the protocol described in this module
+pub struct ProcessBot {
+    child: Child,
+    stdin: Option<BufWriter<ChildStdin>>,
+    lines: Receiver<std::io::Result<String>>,
+    next_id: u64,
+    time_limit: Duration,
+    fallback: Box<dyn Strategy>,
+    crashed: bool,
+    faults: Vec<Fault>,
+}
+
+impl ProcessBot {
+    /// Spawns `command` with piped stdin and stdout, and a time limit of 1 second per turn
+    pub fn spawn(mut command: Command) -> std::io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().map(BufWriter::new);
+        let stdout = child.stdout.take().expect("Stdout should be piped");
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            child,
+            stdin,
+            lines,
+            next_id: 0,
+            time_limit: Duration::from_secs(1),
+            fallback: Box::new(Cautious::default()),
+            crashed: false,
+            faults: Vec::new(),
+        })
+    }
+
+    #[must_use]
+    pub fn with_time_limit(mut self, time_limit: Duration) -> Self {
+        self.time_limit = time_limit;
+        self
+    }
+
+    /// Sets what plays a turn when the bot fails to, which is [`Cautious`] by default
+    #[must_use]
+    pub fn with_fallback(mut self, fallback: impl Strategy + 'static) -> Self {
+        self.fallback = Box::new(fallback);
+        self
+    }
+
+    /// Everything that went wrong so far, in order
+    #[must_use]
+    pub fn faults(&self) -> &[Fault] {
+        &self.faults
+    }
+
+    #[must_use]
+    pub fn has_crashed(&self) -> bool {
+        self.crashed
+    }
+
+    fn send(&mut self, message: &EngineMessage) -> Result<(), Fault> {
+        let stdin = self
+            .stdin
+            .as_mut()
+            .ok_or_else(|| Fault::Crashed("stdin is closed".to_owned()))?;
+        let line = serde_json::to_string(message).expect("Messages should serialize");
+        writeln!(stdin, "{line}")
+            .and_then(|()| stdin.flush())
+            .map_err(|err| Fault::Crashed(err.to_string()))
+    }
+
+    /// Waits until `deadline` for the answer to request `id`, skipping blank lines and stale answers
+    fn receive(&mut self, id: u64, deadline: Instant) -> Result<Action, Fault> {
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let line = match self.lines.recv_timeout(remaining) {
+                Ok(Ok(line)) => line,
+                Ok(Err(err)) => return Err(Fault::Crashed(err.to_string())),
+                Err(RecvTimeoutError::Timeout) => return Err(Fault::Timeout(self.time_limit)),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Fault::Crashed(match self.child.try_wait() {
+                        Ok(Some(status)) => status.to_string(),
+                        _ => "stdout was closed".to_owned(),
+                    }))
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<BotMessage>(&line) {
+                Ok(message) if message.id == id => return Ok(message.action),
+                Ok(message) if message.id < id => continue,
+                Ok(_) | Err(_) => return Err(Fault::Malformed(line)),
+            }
+        }
+    }
+
+    fn request(&mut self, view: &PlayerView) -> Result<Action, Fault> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let deadline = Instant::now() + self.time_limit;
+        let legal_actions = view.legal_actions();
+        self.send(&EngineMessage::Act {
+            id,
+            view: Box::new(view.clone()),
+            legal_actions: legal_actions.clone(),
+        })?;
+        let action = self.receive(id, deadline)?;
+        check(view, &legal_actions, action).map_err(|reason| Fault::Illegal { action, reason })?;
+        Ok(action)
+    }
+}
+
+/// Whether `action` is one of the `legal_actions` of `view`, with the reason it is not otherwise
+fn check(view: &PlayerView, legal_actions: &[Action], action: Action) -> Result<(), String> {
+    match (action, view.last_bet()) {
+        (Action::CallFluff, None) => {
+            Err("Fluff can not be called before anyone has bet".to_owned())
+        }
+        (Action::CallFluff, Some(_)) => Ok(()),
+        (Action::Raise(bet), _) if bet.roll > view.config.max_roll() => {
+            Err(format!("{bet} is above the max roll"))
+        }
+        (Action::Raise(bet), Some(prev)) => bet
+            .is_raised_from(&prev)
+            .map_err(|err: RaiseError| err.to_string()),
+        (Action::Raise(_), None) => Ok(()),
+    }?;
+    match action {
+        _ if legal_actions.contains(&action) => Ok(()),
+        Action::Raise(bet) => Err(format!("{bet} is more dice than are in play")),
+        Action::CallFluff => Err("Fluff can not be called now".to_owned()),
+    }
+}
+
+impl Strategy for ProcessBot {
+    fn act(&mut self, view: &PlayerView) -> Action {
+        if !self.crashed {
+            match self.request(view) {
+                Ok(action) => return action,
+                Err(fault) => {
+                    if let Fault::Crashed(_) = fault {
+                        self.crashed = true;
+                        self.stdin = None;
+                    } else {
+                        // the bot may have died without telling us, which is not worth a fault
+                        let _ = self.send(&EngineMessage::Rejected {
+                            id: self.next_id,
+                            reason: fault.to_string(),
+                        });
+                    }
+                    self.faults.push(fault);
+                }
+            }
+        }
+        self.fallback.act(view)
+    }
+}
+
+impl Drop for ProcessBot {
+    fn drop(&mut self) {
+        self.stdin = None;
+        // the process may well have exited already
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::{
+        bet::Bet,
+        game::{session::GameSession, Game, GameConfig},
+        player::Player,
+    };
+
+    /// A bot that opens with one 2 and calls fluff on everything else
+    const CALLER: &str = r#"
+        while read -r line; do
+            id=$(printf '%s' "$line" | sed -n 's/^{"type":"act","id":\([0-9]*\).*/\1/p')
+            [ -z "$id" ] && continue
+            case "$line" in
+                *'"turns":[]'*) echo "{\"id\":$id,\"action\":{\"Raise\":{\"count\":1,\"roll\":2}}}" ;;
+                *) echo "{\"id\":$id,\"action\":\"CallFluff\"}" ;;
+            esac
+        done
+    "#;
+
+    fn bot(script: &str) -> ProcessBot {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        ProcessBot::spawn(command)
+            .unwrap()
+            .with_time_limit(Duration::from_millis(500))
+    }
+
+    fn views() -> (PlayerView, PlayerView) {
+        let players = [Player::new("A"), Player::new("B")];
+        let config = GameConfig::new(NonZeroUsize::new(2).unwrap(), NonZeroUsize::new(6).unwrap());
+        let session = GameSession::from(Game::new(players, config));
+        let player = session.current_player().unwrap().clone();
+        let opening = session.view_for(&player);
+        let bet = Bet::new(NonZeroUsize::MIN, NonZeroUsize::new(3).unwrap());
+        let session = session.act(Action::Raise(bet)).unwrap();
+        let player = session.current_player().unwrap().clone();
+        (opening, session.view_for(&player))
+    }
+
+    #[test]
+    fn test_protocol() {
+        let (opening, betting) = views();
+        let mut bot = bot(CALLER);
+        assert_eq!(
+            bot.act(&opening),
+            Action::Raise(Bet::new(NonZeroUsize::MIN, NonZeroUsize::new(2).unwrap()))
+        );
+        assert_eq!(bot.act(&betting), Action::CallFluff);
+        assert!(bot.faults().is_empty());
+    }
+
+    #[test]
+    fn test_faults() {
+        let (opening, betting) = views();
+
+        // one 2 is not a raise from one 3
+        let mut illegal = bot(&CALLER.replace("*'\"turns\":[]'*", "*"));
+        illegal.act(&betting);
+        assert!(matches!(illegal.faults(), [Fault::Illegal { .. }]));
+        // and there are only 4 dice to bet on
+        let mut greedy = bot(&CALLER.replace("\\\"count\\\":1", "\\\"count\\\":5"));
+        let fallback = greedy.fallback.act(&opening);
+        assert_eq!(greedy.act(&opening), fallback);
+        assert!(matches!(greedy.faults(), [Fault::Illegal { .. }]));
+
+        let mut garbage = bot("while read -r line; do echo nonsense; done");
+        garbage.act(&opening);
+        assert!(matches!(garbage.faults(), [Fault::Malformed(_)]));
+
+        let mut slow = bot("sleep 5");
+        slow.act(&opening);
+        assert!(matches!(slow.faults(), [Fault::Timeout(_)]));
+        assert!(!slow.has_crashed());
+
+        // blank lines don't buy the bot more time
+        let mut stalling = bot("while true; do echo; sleep 0.1; done");
+        let start = Instant::now();
+        stalling.act(&opening);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(matches!(stalling.faults(), [Fault::Timeout(_)]));
+
+        let mut crashing = bot("exit 3");
+        crashing.act(&opening);
+        crashing.act(&betting);
+        assert!(crashing.has_crashed());
+        assert!(matches!(crashing.faults(), [Fault::Crashed(_)]));
+    }
+}
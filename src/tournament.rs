@@ -0,0 +1,681 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter},
+    num::NonZeroUsize,
+};
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use thiserror::Error;
+
+use crate::{
+    game::GameConfig,
+    player::Player,
+    simulation::{Seat, Simulation, SimulationError},
+};
+
+/// How tables are drawn up from the entrants
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
+pub enum Format {
+    /// Every combination of entrants plays at a table once
+    RoundRobin,
+    /// Entrants with similar points so far share tables, for a fixed number of rounds
+    ///
+    /// Nobody meets the same opponent twice if the standings allow it, and whoever does not fit
+    /// at a full table gets a [`Bye`].
+    Swiss { rounds: usize },
+    /// Only the best entrant of each table goes on to the next round, until one is left
+    ///
+    /// Whoever does not fit at a full table gets a [`Bye`] and goes on as well.
+    Knockout,
+}
+
+#[derive(Error, Debug)]
+pub enum TournamentError {
+    #[error("A tournament with tables of {table_size} needs at least that many entrants, not {entrants}")]
+    NotEnoughEntrants { entrants: usize, table_size: usize },
+    #[error("Tables need at least 2 seats")]
+    TableTooSmall,
+    #[error("{0} entered more than once")]
+    DuplicateEntrant(Player),
+    #[error("Table {table}: {source}")]
+    Simulation {
+        table: usize,
+        source: SimulationError,
+    },
+}
+
+/// One finished game of a tournament, with entrants referred to by their index
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+pub struct GameRecord {
+    /// Round of the tournament, which is always 0 in a round robin
+    pub stage: usize,
+    pub table: usize,
+    /// Entrants in seating order
+    pub seats: Vec<usize>,
+    /// Entrants from the winner to the first one knocked out
+    pub finishing_order: Vec<usize>,
+}
+
+impl GameRecord {
+    /// Share of the other players at the table that `entrant` outlasted
+    fn points_of(&self, entrant: usize) -> f64 {
+        let position = self
+            .finishing_order
+            .iter()
+            .position(|x| *x == entrant)
+            .expect("Entrant should have played");
+        let others = self.finishing_order.len() - 1;
+        (others - position) as f64 / others as f64
+    }
+}
+
+/// A round of a Swiss or knockout tournament an entrant sat out, scored as if they had won every
+/// game of it
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Bye {
+    pub stage: usize,
+    pub entrant: usize,
+    pub points: f64,
+}
+
+/// Draws up tables going down the standings, backtracking so nobody meets an earlier opponent
+struct Pairing<'a> {
+    /// Entrants from the best one down
+    order: &'a [usize],
+    table_size: usize,
+    /// Every pair of entrants who already shared a table
+    met: &'a HashSet<(usize, usize)>,
+    seated: Vec<bool>,
+    tables: Vec<Vec<usize>>,
+    /// Seatings left to try before giving up
+    budget: usize,
+}
+
+impl<'a> Pairing<'a> {
+    fn new(order: &'a [usize], table_size: usize, met: &'a HashSet<(usize, usize)>) -> Self {
+        Self {
+            order,
+            table_size,
+            met,
+            seated: vec![false; order.len()],
+            tables: Vec::new(),
+            budget: 100_000,
+        }
+    }
+
+    /// Seats everyone left, starting a table with the best of them
+    fn seat_rest(&mut self) -> bool {
+        let Some(head) = self.seated.iter().position(|x| !x) else {
+            return true;
+        };
+        self.seated[head] = true;
+        let seated = self.fill(&mut vec![self.order[head]], head + 1);
+        self.seated[head] = false;
+        seated
+    }
+
+    /// Fills `table` with entrants from `from` on in the order, none of whom met before
+    fn fill(&mut self, table: &mut Vec<usize>, from: usize) -> bool {
+        if table.len() == self.table_size {
+            self.tables.push(table.clone());
+            if self.seat_rest() {
+                return true;
+            }
+            self.tables.pop();
+            return false;
+        }
+        for i in from..self.order.len() {
+            let entrant = self.order[i];
+            if self.seated[i] || table.iter().any(|x| self.met.contains(&(*x, entrant))) {
+                continue;
+            }
+            if self.budget == 0 {
+                return false;
+            }
+            self.budget -= 1;
+            self.seated[i] = true;
+            table.push(entrant);
+            if self.fill(table, i + 1) {
+                return true;
+            }
+            table.pop();
+            self.seated[i] = false;
+        }
+        false
+    }
+}
+
+/// Schedules and plays games between many strategies
+#[derive(Clone)]
+pub struct Tournament {
+    pub config: GameConfig,
+    pub entrants: Vec<Seat>,
+    pub format: Format,
+    pub table_size: usize,
+    /// Games played at every table, with the seating rotated by one seat after each game
+    pub games_per_table: usize,
+    pub seed: u64,
+    pub threads: NonZeroUsize,
+}
+
+impl Tournament {
+    /// A tournament of 2 player tables, with each pairing playing one game in each seat order
+    #[must_use]
+    pub fn new(config: GameConfig, entrants: Vec<Seat>, format: Format, seed: u64) -> Self {
+        Self {
+            config,
+            entrants,
+            format,
+            table_size: 2,
+            games_per_table: 2,
+            seed,
+            threads: NonZeroUsize::MIN,
+        }
+    }
+
+    #[must_use]
+    pub fn with_tables(self, table_size: usize, games_per_table: usize) -> Self {
+        Self {
+            table_size,
+            games_per_table,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn with_threads(self, threads: NonZeroUsize) -> Self {
+        Self { threads, ..self }
+    }
+
+    /// Plays every game at `table`, which is numbered across the whole tournament for seeding
+    fn play_table(
+        &self,
+        stage: usize,
+        table: usize,
+        entrants: &[usize],
+    ) -> Result<Vec<GameRecord>, TournamentError> {
+        (0..self.games_per_table)
+            .map(|game_index| {
+                let mut seats = entrants.to_vec();
+                seats.rotate_left(game_index % entrants.len());
+                let simulation = Simulation::new(
+                    self.config,
+                    seats.iter().map(|x| self.entrants[*x].clone()).collect(),
+                    self.seed.wrapping_add((table as u64) << 32),
+                    self.games_per_table,
+                )
+                .with_fixed_seats();
+                let game = simulation
+                    .play_game(game_index)
+                    .map_err(|source| TournamentError::Simulation { table, source })?;
+                let finishing_order = game
+                    .finishing_order()
+                    .iter()
+                    .map(|player| {
+                        *seats
+                            .iter()
+                            .find(|x| self.entrants[**x].player == **player)
+                            .expect("Every player should be seated")
+                    })
+                    .collect();
+                Ok(GameRecord {
+                    stage,
+                    table,
+                    seats,
+                    finishing_order,
+                })
+            })
+            .collect()
+    }
+
+    /// Plays `tables`, split across `threads`, numbering them from `first_table`
+    fn play_stage(
+        &self,
+        stage: usize,
+        first_table: usize,
+        tables: &[Vec<usize>],
+    ) -> Result<Vec<GameRecord>, TournamentError> {
+        let threads = self.threads.get().min(tables.len().max(1));
+        let chunk_size = tables.len().div_ceil(threads);
+        std::thread::scope(|scope| {
+            let handles = tables
+                .chunks(chunk_size.max(1))
+                .enumerate()
+                .map(|(chunk_index, chunk)| {
+                    scope.spawn(move || {
+                        let mut records = Vec::new();
+                        for (i, table) in chunk.iter().enumerate() {
+                            let table_index = first_table + chunk_index * chunk_size + i;
+                            records.extend(self.play_table(stage, table_index, table)?);
+                        }
+                        Ok::<_, TournamentError>(records)
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut records = Vec::new();
+            for handle in handles {
+                records.extend(handle.join().expect("Tournament thread panicked")?);
+            }
+            Ok(records)
+        })
+    }
+
+    fn round_robin_tables(&self) -> Vec<Vec<usize>> {
+        fn combinations(
+            start: usize,
+            n: usize,
+            k: usize,
+            current: &mut Vec<usize>,
+        ) -> Vec<Vec<usize>> {
+            if current.len() == k {
+                return vec![current.clone()];
+            }
+            let mut tables = Vec::new();
+            for i in start..n {
+                current.push(i);
+                tables.extend(combinations(i + 1, n, k, current));
+                current.pop();
+            }
+            tables
+        }
+        combinations(0, self.entrants.len(), self.table_size, &mut Vec::new())
+    }
+
+    /// Splits `order` into full tables, leaving out whoever does not fit
+    fn tables_of(&self, order: &[usize]) -> Vec<Vec<usize>> {
+        order
+            .chunks_exact(self.table_size)
+            .map(<[usize]>::to_vec)
+            .collect()
+    }
+
+    /// Tables for round `stage` of a Swiss tournament, and who gets a bye
+    ///
+    /// The lowest ranked entrants with the fewest byes so far sit out, and the rest are paired
+    /// down the standings without rematches if possible, or in order of the standings otherwise.
+    /// Entrants on the same points are ranked randomly.
+    fn swiss_tables(
+        &self,
+        stage: usize,
+        records: &[GameRecord],
+        byes: &[Bye],
+    ) -> (Vec<Vec<usize>>, Vec<usize>) {
+        let points = points(self.entrants.len(), records, byes);
+        let mut order = (0..self.entrants.len()).collect::<Vec<_>>();
+        order.shuffle(&mut StdRng::seed_from_u64(
+            self.seed.wrapping_add(stage as u64),
+        ));
+        order.sort_by(|a, b| points[*b].total_cmp(&points[*a]));
+
+        let bye_count = |entrant: usize| byes.iter().filter(|x| x.entrant == entrant).count();
+        let mut by_bye_priority = order.iter().rev().copied().collect::<Vec<_>>();
+        by_bye_priority.sort_by_key(|x| bye_count(*x));
+        let sitting_out = by_bye_priority[..self.entrants.len() % self.table_size].to_vec();
+        order.retain(|x| !sitting_out.contains(x));
+
+        let met = records
+            .iter()
+            .flat_map(|record| {
+                record
+                    .seats
+                    .iter()
+                    .flat_map(|a| record.seats.iter().map(move |b| (*a, *b)))
+            })
+            .collect::<HashSet<_>>();
+        let mut pairing = Pairing::new(&order, self.table_size, &met);
+        let tables = if pairing.seat_rest() {
+            pairing.tables
+        } else {
+            self.tables_of(&order)
+        };
+        (tables, sitting_out)
+    }
+
+    /// Plays the whole tournament
+    pub fn run(&self) -> Result<Leaderboard, TournamentError> {
+        if self.table_size < 2 {
+            return Err(TournamentError::TableTooSmall);
+        }
+        for (index, entrant) in self.entrants.iter().enumerate() {
+            if self.entrants[..index]
+                .iter()
+                .any(|x| x.player == entrant.player)
+            {
+                return Err(TournamentError::DuplicateEntrant(entrant.player.clone()));
+            }
+        }
+        if self.entrants.len() < self.table_size {
+            return Err(TournamentError::NotEnoughEntrants {
+                entrants: self.entrants.len(),
+                table_size: self.table_size,
+            });
+        }
+        let mut records = Vec::new();
+        let mut byes = Vec::new();
+        let mut knocked_out = vec![None; self.entrants.len()];
+        let mut table_count = 0;
+        match self.format {
+            Format::RoundRobin => {
+                records = self.play_stage(0, 0, &self.round_robin_tables())?;
+            }
+            Format::Swiss { rounds } => {
+                for stage in 0..rounds {
+                    let (tables, sitting_out) = self.swiss_tables(stage, &records, &byes);
+                    records.extend(self.play_stage(stage, table_count, &tables)?);
+                    table_count += tables.len();
+                    byes.extend(sitting_out.into_iter().map(|entrant| Bye {
+                        stage,
+                        entrant,
+                        points: self.games_per_table as f64,
+                    }));
+                }
+            }
+            Format::Knockout => {
+                let mut remaining = (0..self.entrants.len()).collect::<Vec<_>>();
+                let mut stage = 0;
+                while remaining.len() > 1 {
+                    // whoever does not fit at a full table sits out, starting from the fewest byes
+                    remaining.sort_by_key(|entrant| {
+                        Reverse(byes.iter().filter(|x| x.entrant == *entrant).count())
+                    });
+                    // when too few are left for a full table, the final is played at a smaller one
+                    let tables = if remaining.len() < self.table_size {
+                        vec![remaining.clone()]
+                    } else {
+                        self.tables_of(&remaining)
+                    };
+                    byes.extend(
+                        remaining
+                            .iter()
+                            .filter(|x| !tables.iter().any(|table| table.contains(x)))
+                            .map(|entrant| Bye {
+                                stage,
+                                entrant: *entrant,
+                                points: self.games_per_table as f64,
+                            }),
+                    );
+                    let stage_records = self.play_stage(stage, table_count, &tables)?;
+                    let points = points(self.entrants.len(), &stage_records, &[]);
+                    for table in &tables {
+                        let best = *table
+                            .iter()
+                            .max_by(|a, b| points[**a].total_cmp(&points[**b]).then(b.cmp(a)))
+                            .expect("Tables should not be empty");
+                        for entrant in table {
+                            if *entrant != best {
+                                knocked_out[*entrant] = Some(stage);
+                            }
+                        }
+                    }
+                    remaining.retain(|x| knocked_out[*x].is_none());
+                    records.extend(stage_records);
+                    table_count += tables.len();
+                    stage += 1;
+                }
+            }
+        }
+        Ok(Leaderboard::new(
+            self.entrants.iter().map(|x| x.player.clone()).collect(),
+            &knocked_out,
+            records,
+            byes,
+        ))
+    }
+}
+
+/// Points of every entrant over `records` and `byes`
+fn points(entrants: usize, records: &[GameRecord], byes: &[Bye]) -> Vec<f64> {
+    let mut points = vec![0.0; entrants];
+    for record in records {
+        for entrant in &record.seats {
+            points[*entrant] += record.points_of(*entrant);
+        }
+    }
+    for bye in byes {
+        points[bye.entrant] += bye.points;
+    }
+    points
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct OpponentRecord {
+    pub opponent: Player,
+    /// Games both played at the same table
+    pub games: usize,
+    /// Games where the entrant outlasted the opponent
+    pub finished_ahead: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Standing {
+    pub player: Player,
+    pub games: usize,
+    pub wins: usize,
+    /// Share of the other players outlasted, added up over every game and bye
+    pub points: f64,
+    pub byes: usize,
+    /// Average place, starting from 1 for a win
+    pub average_finish: f64,
+    /// Round in which the entrant was knocked out of a knockout tournament
+    pub knocked_out_in: Option<usize>,
+    pub opponents: Vec<OpponentRecord>,
+}
+
+/// Results of a tournament, with the best entrant first
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Leaderboard {
+    pub standings: Vec<Standing>,
+    pub games: Vec<GameRecord>,
+    pub byes: Vec<Bye>,
+}
+
+impl Leaderboard {
+    #[must_use]
+    pub fn new(
+        players: Vec<Player>,
+        knocked_out: &[Option<usize>],
+        games: Vec<GameRecord>,
+        byes: Vec<Bye>,
+    ) -> Self {
+        let points = points(players.len(), &games, &byes);
+        let mut head_to_head: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+        for game in &games {
+            for (place, entrant) in game.finishing_order.iter().enumerate() {
+                for (other_place, other) in game.finishing_order.iter().enumerate() {
+                    if entrant != other {
+                        let record = head_to_head.entry((*entrant, *other)).or_default();
+                        record.0 += 1;
+                        record.1 += usize::from(place < other_place);
+                    }
+                }
+            }
+        }
+        let mut standings = players
+            .iter()
+            .enumerate()
+            .map(|(entrant, player)| {
+                let played = games
+                    .iter()
+                    .filter_map(|x| x.finishing_order.iter().position(|y| *y == entrant))
+                    .collect::<Vec<_>>();
+                Standing {
+                    player: player.clone(),
+                    games: played.len(),
+                    wins: played.iter().filter(|x| **x == 0).count(),
+                    points: points[entrant],
+                    byes: byes.iter().filter(|x| x.entrant == entrant).count(),
+                    average_finish: played.iter().map(|x| x + 1).sum::<usize>() as f64
+                        / played.len().max(1) as f64,
+                    knocked_out_in: knocked_out.get(entrant).copied().flatten(),
+                    opponents: players
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(other, opponent)| {
+                            let (games, finished_ahead) = *head_to_head.get(&(entrant, other))?;
+                            Some(OpponentRecord {
+                                opponent: opponent.clone(),
+                                games,
+                                finished_ahead,
+                            })
+                        })
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+        standings.sort_by(|a, b| {
+            let survived = |x: &Standing| x.knocked_out_in.map_or(usize::MAX, |stage| stage);
+            survived(b)
+                .cmp(&survived(a))
+                .then(b.points.total_cmp(&a.points))
+                .then(b.wins.cmp(&a.wins))
+        });
+        Self {
+            standings,
+            games,
+            byes,
+        }
+    }
+}
+
+impl Display for Leaderboard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .standings
+            .iter()
+            .map(|x| x.player.as_str().len())
+            .max()
+            .unwrap_or(0)
+            .max("Player".len());
+        writeln!(
+            f,
+            "{:>4}  {:<width$}  {:>5}  {:>5}  {:>8}  {:>10}",
+            "#", "Player", "Games", "Wins", "Points", "Avg finish"
+        )?;
+        for (rank, standing) in self.standings.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>4}  {:<width$}  {:>5}  {:>5}  {:>8.2}  {:>10.2}",
+                rank + 1,
+                standing.player.as_str(),
+                standing.games,
+                standing.wins,
+                standing.points,
+                standing.average_finish,
+            )?;
+            for opponent in &standing.opponents {
+                writeln!(
+                    f,
+                    "{:>4}  {:<width$}  vs {}: ahead in {} of {}",
+                    "", "", opponent.opponent, opponent.finished_ahead, opponent.games,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        simulation::factory,
+        strategy::bots::{Bluffer, Cautious, RandomLegal},
+    };
+
+    fn tournament(format: Format) -> Tournament {
+        Tournament::new(
+            GameConfig::new(NonZeroUsize::new(2).unwrap(), NonZeroUsize::new(6).unwrap()),
+            vec![
+                Seat::new(Player::new("Random"), factory(RandomLegal::new)),
+                Seat::new(Player::new("Cautious"), factory(|_| Cautious::default())),
+                Seat::new(Player::new("Bluffer"), factory(Bluffer::new)),
+                Seat::new(Player::new("Random 2"), factory(RandomLegal::new)),
+                Seat::new(Player::new("Cautious 2"), factory(|_| Cautious::default())),
+            ],
+            format,
+            7,
+        )
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let leaderboard = tournament(Format::RoundRobin)
+            .with_tables(3, 3)
+            .with_threads(NonZeroUsize::new(3).unwrap())
+            .run()
+            .unwrap();
+        // 10 tables of 3 out of 5 entrants
+        assert_eq!(leaderboard.games.len(), 30);
+        assert!(leaderboard.standings.iter().all(|x| x.games == 18));
+        assert_eq!(
+            leaderboard.standings.iter().map(|x| x.wins).sum::<usize>(),
+            30
+        );
+        let single_threaded = tournament(Format::RoundRobin)
+            .with_tables(3, 3)
+            .run()
+            .unwrap();
+        assert_eq!(leaderboard, single_threaded);
+        let standing = &leaderboard.standings[0];
+        assert_eq!(standing.opponents.len(), 4);
+        assert!(standing.opponents.iter().all(|x| x.games == 9));
+    }
+
+    #[test]
+    fn test_swiss() {
+        let swiss = tournament(Format::Swiss { rounds: 4 }).run().unwrap();
+        // 2 tables of 2 games per round, with one entrant sitting out
+        assert_eq!(swiss.games.len(), 16);
+        // everyone but one sits out once, and nobody meets the same opponent twice
+        assert_eq!(swiss.byes.len(), 4);
+        assert_eq!(swiss.standings.iter().filter(|x| x.byes == 1).count(), 4);
+        assert_eq!(swiss.standings.iter().map(|x| x.games).sum::<usize>(), 32);
+        assert!(swiss
+            .standings
+            .iter()
+            .all(|x| x.opponents.iter().all(|y| y.games == 2)));
+        let total = swiss.standings.iter().map(|x| x.points).sum::<f64>();
+        assert!((total - (16.0 + 4.0 * 2.0)).abs() < 1e-9);
+
+        // 5 entrants at tables of 3 leaves 2 sitting out every round
+        let swiss = tournament(Format::Swiss { rounds: 3 })
+            .with_tables(3, 1)
+            .run()
+            .unwrap();
+        assert_eq!(swiss.games.len(), 3);
+        assert_eq!(swiss.byes.len(), 6);
+        assert!(swiss.standings.iter().all(|x| (1..=2).contains(&x.byes)));
+    }
+
+    #[test]
+    fn test_knockout() {
+        let knockout = tournament(Format::Knockout).run().unwrap();
+        let champions = knockout
+            .standings
+            .iter()
+            .filter(|x| x.knocked_out_in.is_none())
+            .count();
+        assert_eq!(champions, 1);
+        assert!(knockout.standings[0].knocked_out_in.is_none());
+        // 5 entrants at tables of 2 leave one out of the first round, and 3 one out of the second
+        assert_eq!(
+            knockout.byes.iter().map(|x| x.stage).collect::<Vec<_>>(),
+            [0, 1]
+        );
+        assert_ne!(knockout.byes[0].entrant, knockout.byes[1].entrant);
+        assert!(knockout
+            .to_string()
+            .contains(knockout.standings[0].player.as_str()));
+    }
+
+    #[test]
+    fn test_duplicate_entrants() {
+        let mut duplicated = tournament(Format::Knockout);
+        duplicated.entrants[1].player = Player::new("Random");
+        assert!(matches!(
+            duplicated.run(),
+            Err(TournamentError::DuplicateEntrant(x)) if x.as_str() == "Random"
+        ));
+    }
+}